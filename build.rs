@@ -0,0 +1,10 @@
+use std::path::PathBuf;
+use std::{env, fs};
+
+fn main() {
+    // make the linker script of the deferred log messages available to the linker
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::copy("xdevs-log.x", out.join("xdevs-log.x")).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed=xdevs-log.x");
+}
@@ -11,72 +11,81 @@ use hifive1::hal::{
 };
 
 use riscv_rt::entry;
+use riscv_xdevs::rt::clock::Clint;
 use riscv_xdevs::*;
 
-use portable_atomic::{AtomicBool, Ordering::*};
+use riscv_xdevs::rt::debounce::{Debouncer, Edges};
+use riscv_xdevs::rt::queue::EventQueue;
 use xdevs::aux::Bag;
 
-/// atomic variable to communicate between [GPIO9] interrupt handler and input_handler function
-static PRESSED: AtomicBool = AtomicBool::new(false);
+// Machine timer interrupt handler: it clears the alarm after waking up the core
+riscv_xdevs::clint_handler!();
+
+/// queue of button levels between [GPIO9] interrupt handler and input_handler function
+static PRESSED: EventQueue<bool, 16> = EventQueue::new();
 
 /// GPIO9 interrupt handler.
-/// It sets the atomic variable PRESSED to true and clears the interrupt pending flag.
+/// It pushes the button level (with its capture time) into PRESSED and clears the interrupt pending flags.
 #[no_mangle]
 #[allow(non_snake_case)]
 fn GPIO9() {
-    unsafe { (*GPIO0::ptr()).fall_ip.write(|w| w.bits(1 << 9)) };
-    PRESSED.store(true, Release);
-}
-
-/// Machine timer interrupt handler.
-/// It fills CLINT's MTIMECMP0 register with the maximum value to clear the interrupt.
-#[no_mangle]
-#[allow(non_snake_case)]
-fn MachineTimer() {
-    CLINT::mtimecmp0().write(u64::MAX);
+    let tick = Clint::mtime();
+    let level = unsafe {
+        let gpio_block = &*GPIO0::ptr();
+        gpio_block.fall_ip.write(|w| w.bits(1 << 9));
+        gpio_block.rise_ip.write(|w| w.bits(1 << 9));
+        gpio_block.input_val.read().bits() & (1 << 9) != 0
+    };
+    PRESSED.push(tick, level).ok();
 }
 
 /// Closure for RT simulation on SiFive E310x boards.
 pub fn wait_until() -> impl FnMut(f64, &mut PTInput) -> f64 {
     let mut count = 0;
-    let mtimer = hifive1::hal::e310x::CLINT::mtimer();
-    let (mtimecmp, mtime) = (mtimer.mtimecmp0, mtimer.mtime);
-    mtime.write(0);
-    let mut debounce = mtime.read();
+    // capture the epoch of the simulation (mtime is never reset, as others may rely on it)
+    let epoch = Clint::mtime();
+    // presses (falling edges) closer than 1 second are bounces
+    let base = rt::time::TimeBase::new(CLINT::freq() as u64);
+    let mut debouncer = Debouncer::new(Edges::Falling)
+        .with_level(true)
+        .with_interval(base, 1_000_000);
 
     move |t_next, input| -> f64 {
         // translate next simulation time to next CLINT tick
-        let next_tick = t_next as u64 * CLINT::freq() as u64;
+        let next_tick = epoch + secf64_to_ticku64(t_next);
         // wait for event (either button press or next CLINT tick)
-        while mtime.read() < next_tick {
+        let mut captured = None;
+        while Clint::mtime() < next_tick {
             // check if button was pressed and inject event and break if so
-            if PRESSED
-                .compare_exchange(true, false, Acquire, Relaxed)
-                .is_ok()
-            {
-                let now = mtime.read();
-                if now - debounce > CLINT::freq() as u64 {
-                    debounce = now;
+            let mut injected = false;
+            while let Some(sample) = PRESSED.pop() {
+                if debouncer.update(sample.tick, sample.payload).is_some() {
                     if input.in_job.add_value(count).is_ok() {
                         count += 1;
+                        injected = true;
+                        captured = Some(sample.tick);
                         break;
                     } else {
                         println!("Error: input buffer full");
                     }
                 }
             }
+            if injected {
+                break;
+            }
             // schedule CLINT's machine timer interrupt
-            mtimecmp.write(next_tick);
+            Clint::set_mtimecmp(next_tick);
             unsafe {
                 CLINT::mtimer_enable();
                 riscv::asm::wfi();
             }
         }
         CLINT::mtimer_disable();
-        //compute current simulation time from current CLINT tick and return it
-        let current_tick = mtime.read();
+        // compute simulation time from the tick at which the press was captured and return it
+        let current_tick = Clint::mtime();
         if current_tick < next_tick {
-            current_tick as f64 / CLINT::freq() as f64
+            let capture_tick = captured.map_or(current_tick, |tick| tick.max(epoch));
+            ticku64_to_secf64(capture_tick.min(current_tick) - epoch)
         } else {
             t_next
         }
@@ -105,13 +114,13 @@ fn main() -> ! {
     let ctx = PLIC::ctx0();
     ctx.enables().disable_all::<Interrupt>();
 
-    // Configure button pin for interrupt in falling edge
+    // Configure button pin for interrupt in both edges (the debouncer needs to see the releases)
     gpio.pin9.into_pull_up_input();
     unsafe {
         let gpio_block = &*GPIO0::ptr();
-        // Enable GPIO fall interrupts
+        // Enable GPIO fall and rise interrupts
         gpio_block.fall_ie.write(|w| w.bits(1 << 9));
-        gpio_block.rise_ie.write(|w| w.bits(0x0));
+        gpio_block.rise_ie.write(|w| w.bits(1 << 9));
         // Clear pending interrupts from previous states
         gpio_block.fall_ip.write(|w| w.bits(0xffffffff));
         gpio_block.rise_ip.write(|w| w.bits(0x0fffffff));
@@ -151,7 +160,7 @@ fn main() -> ! {
         riscv::register::mstatus::set_mie();
     };
 
-    println!("Simulating for {} time units", t_sim);
+    println!("Simulating for {} time units", t_sim as u64);
     simulator.simulate_rt(0.0, t_sim, wait_until(), propagate_output(blueled));
     println!("Simulation finished");
 
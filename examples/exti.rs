@@ -4,108 +4,37 @@
 #[cfg(not(feature = "qemu"))]
 extern crate panic_halt;
 
-use hifive1::hal::e310x::{Interrupt, Priority, CLINT, GPIO0, PLIC};
+use hifive1::hal::e310x::{Interrupt, Priority, PLIC};
 
 use hifive1::hal::prelude::*;
 use hifive1::hal::DeviceResources;
 use riscv_rt::entry;
 use riscv_xdevs::*;
 
-use portable_atomic::{AtomicBool, Ordering};
-use xdevs::aux::Bag;
+use riscv_xdevs::rt::clock::Clock;
+use riscv_xdevs::rt::debounce::{Debouncer, Edge, Edges};
+use riscv_xdevs::rt::gpio::GpioInputs;
+use riscv_xdevs::rt::output::{self, Action, GpioOutputs};
+use riscv_xdevs::rt::queue::Event;
 
-/// Machine timer interrupt handler.
-/// This function is called when the machine timer interrupt is triggered.
-/// It fills the MTIMECMP0 register with the maximum value to disable the timer.
-#[no_mangle]
-#[allow(non_snake_case)]
-fn MachineTimer() {
-    CLINT::mtimecmp0().write(u64::MAX);
-}
+// Machine timer interrupt handler: it clears the alarm after waking up the core
+riscv_xdevs::clint_handler!();
 
-/// atomic variable to communicate between [GPIO9] interrupt handler and input_handler function
-static BUTTON_PRESSED: AtomicBool = AtomicBool::new(false);
-
-/// GPIO interrupt handler.
-/// This function is called when the GPIO9 interrupt is triggered.
-/// It sets the atomic variable BUTTON_PRESSED to true and clears the interrupt pending flag.
-#[no_mangle]
-#[allow(non_snake_case)]
-fn GPIO9() {
-    unsafe { (*GPIO0::ptr()).fall_ip.write(|w| w.bits(1 << 9)) };
-    BUTTON_PRESSED.store(true, Ordering::Release);
-}
+// GPIO9 interrupt handler: it timestamps the level of the button
+riscv_xdevs::gpio_handlers!(GPIO9);
 
-/// Closure for injecting external events into the model.
-/// This function checks the atomic variable BUTTON_PRESSED and adds a value to the input buffer.
-pub fn input_handler() -> impl FnMut(&mut PTInput) -> bool {
+/// Closure for injecting button presses into the model.
+/// It adds a value to the input buffer for every press.
+pub fn on_press() -> impl FnMut(&mut PTInput, &Event<Edge>) -> bool {
     let mut count = 0;
 
-    move |input| -> bool {
-        match BUTTON_PRESSED.compare_exchange(true, false, Ordering::Acquire, Ordering::Relaxed) {
-            Ok(_) => {
-                println!("Button pressed");
-                if input.in_job.add_value(count).is_err() {
-                    println!("Error: input buffer full");
-                }
-                count += 1;
-                true
-            }
-            Err(_) => false,
-        }
-    }
-}
-
-pub fn output_handler(mut blueled: BlueLed) -> impl FnMut(&PTOutput) {
-    move |o| {
-        if !o.is_empty() {
-            blueled.set_high().unwrap();
-        }
-    }
-}
-
-/// Closure for RT simulation on SiFive E310x boards.
-pub fn wait_exti<T: xdevs::aux::Bag>(
-    t_start: f64,
-    time_scale: f64,
-    max_jitter_us: Option<u64>,
-    mut input_handler: impl FnMut(&mut T) -> bool,
-) -> impl FnMut(f64, &mut T) -> f64 {
-    let mtimer = hifive1::hal::e310x::CLINT::mtimer();
-    let (mtimecmp, mtime) = (mtimer.mtimecmp0, mtimer.mtime);
-    mtime.write(0);
-
-    move |t_next, input| -> f64 {
-        // configure machine timer interrupt and sleep until next tick
-        let next_tick = secf64_to_ticku64((t_next - t_start) * time_scale);
-        while mtime.read() < next_tick {
-            mtimecmp.write(next_tick);
-            unsafe {
-                CLINT::mtimer_enable();
-                riscv::asm::wfi();
-            }
-            // check for external events and break if one is found
-            if input_handler(input) {
-                break;
-            }
-        }
-        CLINT::mtimer_disable(); // make sure interrupts are disabled after sleep
-
-        let current_tick = mtime.read();
-        if current_tick < next_tick {
-            ticku64_to_secf64(current_tick) / time_scale + t_start
-        } else {
-            if current_tick > next_tick {
-                if let Some(max_jitter) = max_jitter_us {
-                    let jitter = (current_tick - next_tick) * 1_000_000 / CLINT::freq() as u64;
-                    println!("jitter: {} us", jitter);
-                    if jitter > max_jitter {
-                        panic!("jitter is too high");
-                    }
-                }
-            }
-            t_next
+    move |input, _| -> bool {
+        if input.in_job.add_value(count).is_err() {
+            return false; // input buffer full: the press is injected in the next step
         }
+        println!("Button pressed");
+        count += 1;
+        true
     }
 }
 
@@ -123,21 +52,12 @@ fn main() -> ! {
     let ctx = PLIC::ctx0();
     ctx.enables().disable_all::<Interrupt>();
 
-    // Configure button pin for interrupt in falling edge
+    // Configure button pin as input (interrupts are configured by GpioInputs)
     gpio.pin9.into_pull_up_input();
-    unsafe {
-        let gpio_block = &*GPIO0::ptr();
-        // Enable GPIO fall interrupts
-        gpio_block.fall_ie.write(|w| w.bits(1 << 9));
-        gpio_block.rise_ie.write(|w| w.bits(0x0));
-        // Clear pending interrupts from previous states
-        gpio_block.fall_ip.write(|w| w.bits(0xffffffff));
-        gpio_block.rise_ip.write(|w| w.bits(0x0fffffff));
-    }
 
     // Configure LED pins for output
     let redled = gpio.pin0.into_output();
-    let blueled = gpio.pin1.into_output();
+    let mut blueled = gpio.pin1.into_output();
     let mut greenled = gpio.pin2.into_output();
 
     // Configure stdout for debugging (only on real hardware)
@@ -155,8 +75,7 @@ fn main() -> ! {
     let proc_time = 2.1;
     let obs_time = 10.;
     let t_sim = 15.;
-    let max_jitter_us = Some(60000);
-    // let max_jitter_us = None;
+    let max_jitter_us = 60000;
 
     let processor = processor::Processor::new(processor::ProcessorState::new(proc_time, redled));
     let transducer = transducer::Transducer::new(transducer::TransducerState::new(obs_time));
@@ -165,25 +84,48 @@ fn main() -> ! {
 
     let mut simulator = xdevs::simulator::Simulator::new(pt);
 
-    let ihandler = input_handler();
-    let wait = wait_exti(0.0, 1., max_jitter_us, ihandler);
-
-    let ohandler = output_handler(blueled);
+    let mut stats = rt::jitter::JitterStats::new(4000);
+    // stop the simulation cleanly if we miss a deadline (the processor turns off the red LED)
+    let deadline = rt::deadline::Deadline::new(max_jitter_us, rt::deadline::OnMiss::Abort);
+    let mut clock = rt::clock::Clint::new();
+    // presses are timestamped in the interrupt handler, so the simulation sees them when they happened
+    let mut on_press = on_press();
+    let inputs = GpioInputs::new().pin(
+        9,
+        Debouncer::new(Edges::Falling).with_interval(clock.time_base(), 20_000),
+        &mut on_press,
+    );
+    // machine interrupts are enabled below, once everything is configured
+    let ihandler = unsafe { inputs.enable(Priority::P2, &clock) };
+    // the blue LED blinks for half a second when the transducer asks to stop
+    let stop = output::when(|o: &PTOutput| o.out_stop.get_values(), |&stop| stop);
+    let mut outputs = GpioOutputs::new(clock.time_base());
+    let led = outputs.pin(&mut blueled);
+    outputs.on(led, &stop, Action::Pulse(500));
+    let ohandler = outputs.handler(rt::clock::Clint::new());
+    let timeline = rt::time::Timeline::start(&mut clock, 0.0, 1.);
+    let wait = rt::wait_exti(
+        clock,
+        timeline,
+        rt::WaitOptions::new()
+            .with_deferred(&outputs)
+            .with_deadline(deadline)
+            .with_stats(&mut stats),
+        ihandler,
+    );
 
     println!("Enabling interrupts");
     unsafe {
-        PLIC::priorities().set_priority(Interrupt::GPIO9, Priority::P2);
         ctx.threshold().set_threshold(Priority::P0);
-        ctx.enables().enable(Interrupt::GPIO9);
-        PLIC::enable();
         riscv::register::mstatus::set_mie();
     };
 
-    println!("Simulating for {} time units", t_sim);
+    println!("Simulating for {} time units", t_sim as u64);
 
     simulator.simulate_rt(0.0, t_sim, wait, ohandler);
 
     println!("Simulation finished");
+    println!("{}", stats);
 
     greenled.set_high().unwrap();
 
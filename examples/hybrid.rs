@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[cfg(not(feature = "qemu"))]
+extern crate panic_halt;
+
+use hifive1::hal::prelude::*;
+use hifive1::hal::DeviceResources;
+use riscv_rt::entry;
+use riscv_xdevs::*;
+
+// Machine timer interrupt handler: it clears the alarm after waking up the core
+riscv_xdevs::clint_handler!();
+
+#[entry]
+fn main() -> ! {
+    let dr = DeviceResources::take().unwrap();
+    let p = dr.peripherals;
+    let gpio = dr.pins;
+
+    // Configure clocks
+    let _clocks = hifive1::clock::configure(p.PRCI, p.AONCLK, 320.mhz().into());
+
+    // Configure red LED pin
+    let redled = gpio.pin0.into_output();
+    let mut greenled = gpio.pin1.into_output();
+
+    // Configure stdout for debugging (only on real hardware)
+    #[cfg(not(feature = "qemu"))]
+    hifive1::stdout::configure(
+        p.UART0,
+        hifive1::pin!(gpio, uart0_tx),
+        hifive1::pin!(gpio, uart0_rx),
+        115_200.bps(),
+        _clocks,
+    );
+
+    println!("Building model");
+
+    let period = 1.;
+    let proc_time = 1.1;
+    let obs_time = 10.;
+    let t_sim = 15.;
+    let max_jitter_us = 5000;
+    let guard_band = rt::GuardBand::Auto(5000);
+
+    let generator = generator::Generator::new(generator::GeneratorState::new(period));
+    let processor = processor::Processor::new(processor::ProcessorState::new(proc_time, redled));
+    let transducer = transducer::Transducer::new(transducer::TransducerState::new(obs_time));
+
+    let ef = EF::new(generator, transducer);
+    let efp = EFP::new(ef, processor);
+
+    let mut simulator = xdevs::simulator::Simulator::new(efp);
+
+    let mut stats = rt::jitter::JitterStats::new(250);
+    let deadline = rt::deadline::Deadline::new(max_jitter_us, rt::deadline::OnMiss::Log);
+    let mut clock = rt::clock::Clint::new();
+    let timeline = rt::time::Timeline::start(&mut clock, 0.0, 1.);
+    let wait = rt::wait_hybrid(
+        clock,
+        timeline,
+        rt::WaitOptions::new()
+            .with_deadline(deadline)
+            .with_stats(&mut stats),
+        rt::no_input,
+        guard_band,
+    );
+
+    println!("Enabling machine interrupts");
+    unsafe { riscv::register::mstatus::set_mie() };
+
+    println!("Simulating for {} time units", t_sim as u64);
+    simulator.simulate_rt(0.0, t_sim, wait, |_| {});
+
+    println!("Simulation finished");
+    println!("{}", stats);
+
+    greenled.set_high().unwrap();
+
+    exit(0);
+}
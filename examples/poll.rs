@@ -1,7 +1,6 @@
 #![no_std]
 #![no_main]
 
-use hifive1::hal::e310x::CLINT;
 use hifive1::hal::prelude::*;
 use hifive1::hal::DeviceResources;
 use riscv_rt::entry;
@@ -10,34 +9,6 @@ use riscv_xdevs::*;
 #[cfg(not(feature = "qemu"))]
 extern crate panic_halt;
 
-/// Sleep closure for RT simulation on SiFive E310x boards.
-/// This is based on busy loops, and interrupts are not used.
-/// While this approach reduces the jitter, it incurs a high CPU load.
-pub fn wait_poll<T: xdevs::aux::Bag>(
-    t_start: f64,
-    t_scale: f64,
-    max_jitter_us: Option<u64>,
-) -> impl FnMut(f64, &mut T) -> f64 {
-    let mtime = CLINT::mtimer().mtime;
-    mtime.write(0);
-
-    move |t_next, _| -> f64 {
-        // wait until next tick in busy loop
-        let next_tick = secf64_to_ticku64((t_next - t_start) * t_scale);
-        while mtime.read() < next_tick {}
-
-        // check jitter (if necessary)
-        if let Some(max_jitter) = max_jitter_us {
-            let jitter = (mtime.read() - next_tick) * 1_000_000 / CLINT::freq() as u64;
-            println!("jitter: {} us", jitter);
-            if jitter > max_jitter {
-                panic!("jitter is too high");
-            }
-        }
-        t_next
-    }
-}
-
 #[entry]
 fn main() -> ! {
     let dr = DeviceResources::take().unwrap();
@@ -66,7 +37,7 @@ fn main() -> ! {
     let proc_time = 1.1;
     let obs_time = 10.;
     let t_sim = 15.;
-    let max_jitter_us = Some(5000);
+    let max_jitter_us = 5000;
 
     let generator = generator::Generator::new(generator::GeneratorState::new(period));
     let processor = processor::Processor::new(processor::ProcessorState::new(proc_time, redled));
@@ -77,13 +48,28 @@ fn main() -> ! {
 
     let mut simulator = xdevs::simulator::Simulator::new(efp);
 
-    let wait = wait_poll(0.0, 1., max_jitter_us);
+    let mut stats = rt::jitter::JitterStats::new(250);
+    let mut misses = 0;
+    let deadline =
+        rt::deadline::Deadline::new(max_jitter_us, rt::deadline::OnMiss::Count(&mut misses));
+    let mut clock = rt::clock::Clint::new();
+    let timeline = rt::time::Timeline::start(&mut clock, 0.0, 1.);
+    let wait = rt::wait_poll(
+        clock,
+        timeline,
+        rt::WaitOptions::new()
+            .with_deadline(deadline)
+            .with_stats(&mut stats),
+        rt::no_input,
+    );
 
-    println!("Simulating for {} seconds", t_sim);
+    println!("Simulating for {} seconds", t_sim as u64);
 
     simulator.simulate_rt(0.0, t_sim, wait, |_| {});
 
     println!("Simulation finished");
+    println!("{}", stats);
+    println!("Deadline misses: {}", misses);
 
     greenled.set_high().unwrap();
 
@@ -0,0 +1,83 @@
+//! QEMU test: CLINT's mtime register rolls over its low word during the simulation.
+//! Torn reads would make mtime jump 2^32 ticks (i.e., more than 36 hours) back and forth.
+//!
+//! Run it with the QEMU runner of `.cargo/config`:
+//! `cargo run --example qemu_rollover --features qemu`
+
+#![no_std]
+#![no_main]
+
+use hifive1::hal::e310x::CLINT;
+use riscv_rt::entry;
+use riscv_xdevs::rt::clock::{Clint, Clock};
+use riscv_xdevs::*;
+
+// Machine timer interrupt handler: it clears the alarm after waking up the core
+riscv_xdevs::clint_handler!();
+
+/// Ticks before the low word of mtime rolls over when the test starts (0.5 seconds).
+const MARGIN: u64 = 16_384;
+/// Simulation step, in seconds.
+const STEP: f64 = 0.1;
+/// Number of simulation steps.
+const N_STEPS: usize = 10;
+
+#[entry]
+fn main() -> ! {
+    let rollover = 1 << 32;
+    // this test is the only user of mtime, so it is safe to move it close to the rollover
+    CLINT::mtimer().mtime.write(rollover - MARGIN);
+
+    println!("Reading mtime across the rollover");
+    let mut previous = Clint::mtime();
+    while previous < rollover + MARGIN {
+        let now = Clint::mtime();
+        if now < previous || now - previous > MARGIN {
+            println!("FAILED: torn read ({} after {})", now, previous);
+            exit(1);
+        }
+        previous = now;
+    }
+
+    CLINT::mtimer().mtime.write(rollover - MARGIN);
+    println!("Sleeping across the rollover");
+    let mut clock = Clint::new();
+    let timeline = rt::time::Timeline::start(&mut clock, 0., 1.);
+    let mut stats = rt::jitter::JitterStats::new(100);
+    let mut misses = 0;
+    {
+        let deadline = rt::deadline::Deadline::new(1_000, rt::deadline::OnMiss::Count(&mut misses));
+        let mut wait = rt::wait_sleep(
+            clock,
+            timeline,
+            rt::WaitOptions::new()
+                .with_deadline(deadline)
+                .with_stats(&mut stats),
+            rt::no_input,
+        );
+
+        unsafe { riscv::register::mstatus::set_mie() };
+        let mut input = PTInput::default();
+        for i in 1..=N_STEPS {
+            let t_next = i as f64 * STEP;
+            if wait(t_next, &mut input) != t_next {
+                println!("FAILED: early wake-up at step {}", i);
+                exit(1);
+            }
+        }
+    }
+
+    let elapsed = timeline.elapsed(Clint::new().now());
+    let expected = timeline.sim_to_tick(N_STEPS as f64 * STEP) - timeline.epoch();
+    println!("{}", stats);
+    if misses > 0 || elapsed > expected + MARGIN {
+        println!(
+            "FAILED: {} deadline misses, {} ticks elapsed",
+            misses, elapsed
+        );
+        exit(1);
+    }
+
+    println!("PASSED");
+    exit(0);
+}
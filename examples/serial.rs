@@ -0,0 +1,103 @@
+#![no_std]
+#![no_main]
+
+#[cfg(not(feature = "qemu"))]
+extern crate panic_halt;
+
+use hifive1::hal::e310x::{Interrupt, Priority, PLIC};
+
+use hifive1::hal::prelude::*;
+use hifive1::hal::DeviceResources;
+use riscv_rt::entry;
+use riscv_xdevs::*;
+
+use riscv_xdevs::rt::uart::{self, SerialInputs, Uart};
+
+// Machine timer interrupt handler: it clears the alarm after waking up the core
+riscv_xdevs::clint_handler!();
+
+// UART0 interrupt handler: it timestamps the received bytes
+riscv_xdevs::uart_handler!(UART0);
+
+#[entry]
+fn main() -> ! {
+    let dr = DeviceResources::take().unwrap();
+    let p = dr.peripherals;
+    let gpio = dr.pins;
+
+    // Configure clocks
+    let clocks = hifive1::clock::configure(p.PRCI, p.AONCLK, 320.mhz().into());
+
+    // Make sure PLIC is reset and disabled
+    PLIC::disable();
+    let ctx = PLIC::ctx0();
+    ctx.enables().disable_all::<Interrupt>();
+
+    // Configure LED pins for output
+    let redled = gpio.pin0.into_output();
+    let mut greenled = gpio.pin2.into_output();
+
+    // Configure UART0 for receiving commands (also on QEMU, where it is connected to stdio)
+    hifive1::stdout::configure(
+        p.UART0,
+        hifive1::pin!(gpio, uart0_tx),
+        hifive1::pin!(gpio, uart0_rx),
+        115_200.bps(),
+        clocks,
+    );
+
+    println!("Building model");
+
+    let proc_time = 2.1;
+    let obs_time = 30.;
+    let t_sim = 40.;
+    let max_jitter_us = 60000;
+
+    let processor = processor::Processor::new(processor::ProcessorState::new(proc_time, redled));
+    let transducer = transducer::Transducer::new(transducer::TransducerState::new(obs_time));
+
+    let pt = PT::new(processor, transducer);
+
+    let mut simulator = xdevs::simulator::Simulator::new(pt);
+
+    let deadline = rt::deadline::Deadline::new(max_jitter_us, rt::deadline::OnMiss::Log);
+    let mut clock = rt::clock::Clint::new();
+    // every line sent over the serial port (e.g., `in_job 42`) injects a job into the model
+    let mut on_job = uart::parse(|input: &mut PTInput, job| input.in_job.add_value(job).is_ok());
+    let inputs = SerialInputs::new().port("in_job", &mut on_job);
+    // machine interrupts are enabled below, once everything is configured
+    let ihandler = unsafe { inputs.enable(Uart::Uart0, Priority::P2, &clock) };
+    let timeline = rt::time::Timeline::start(&mut clock, 0.0, 1.);
+    let wait = rt::wait_exti(
+        clock,
+        timeline,
+        rt::WaitOptions::new().with_deadline(deadline),
+        ihandler,
+    );
+
+    // with the log-buffer feature, the UART0 handler also sends the buffered log
+    // (with the priority of the receiver, as both share the interrupt of UART0)
+    #[cfg(feature = "log-buffer")]
+    unsafe {
+        rt::log::start(Priority::P2);
+    }
+
+    println!("Enabling interrupts");
+    unsafe {
+        ctx.threshold().set_threshold(Priority::P0);
+        riscv::register::mstatus::set_mie();
+    };
+
+    println!(
+        "Simulating for {} time units (send `in_job <n>` lines)",
+        t_sim as u64
+    );
+
+    simulator.simulate_rt(0.0, t_sim, wait, |_| {});
+
+    println!("Simulation finished");
+
+    greenled.set_high().unwrap();
+
+    exit(0);
+}
@@ -9,19 +9,14 @@ use hifive1::hal::e310x::{Interrupt, Priority, CLINT, GPIO0, PLIC};
 use hifive1::hal::prelude::*;
 use hifive1::hal::DeviceResources;
 use riscv_rt::entry;
+use riscv_xdevs::rt::clock::Clint;
 use riscv_xdevs::*;
 
 use portable_atomic::{AtomicBool, Ordering};
 use xdevs::aux::Bag;
 
-/// Machine timer interrupt handler.
-/// This function is called when the machine timer interrupt is triggered.
-/// It fills the MTIMECMP0 register with the maximum value to disable the timer.
-#[no_mangle]
-#[allow(non_snake_case)]
-fn MachineTimer() {
-    CLINT::mtimecmp0().write(u64::MAX);
-}
+// Machine timer interrupt handler: it clears the alarm after waking up the core
+riscv_xdevs::clint_handler!();
 
 /// atomic variable to communicate between [GPIO9] interrupt handler and input_handler function
 static BUTTON_PRESSED: AtomicBool = AtomicBool::new(false);
@@ -66,15 +61,14 @@ pub fn output_handler(mut blueled: BlueLed) -> impl FnMut(&PTOutput) {
 /// Closure for RT simulation on SiFive E310x boards.
 pub fn wait() -> impl FnMut(f64, &mut PTInput) -> f64 {
     let mut ihandler = input_handler();
-    let mtimer = hifive1::hal::e310x::CLINT::mtimer();
-    let (mtimecmp, mtime) = (mtimer.mtimecmp0, mtimer.mtime);
-    mtime.write(0);
+    // capture the epoch of the simulation (mtime is never reset, as others may rely on it)
+    let epoch = Clint::mtime();
 
     move |t_next, input| -> f64 {
         // configure machine timer interrupt and sleep until next tick
-        let next_tick = t_next as u64 * CLINT::freq() as u64;
-        while mtime.read() < next_tick || !ihandler(input) {
-            mtimecmp.write(next_tick);
+        let next_tick = epoch + secf64_to_ticku64(t_next);
+        while Clint::mtime() < next_tick || !ihandler(input) {
+            Clint::set_mtimecmp(next_tick);
             unsafe {
                 CLINT::mtimer_enable();
                 riscv::asm::wfi();
@@ -82,12 +76,12 @@ pub fn wait() -> impl FnMut(f64, &mut PTInput) -> f64 {
         }
         CLINT::mtimer_disable(); // make sure interrupts are disabled after sleep
 
-        let current_tick = mtime.read();
+        let current_tick = Clint::mtime();
         if current_tick < next_tick {
-            current_tick as f64 / CLINT::freq() as f64
+            ticku64_to_secf64(current_tick - epoch)
         } else {
             // check jitter
-            let jitter = (mtime.read() - next_tick) * 1_000_000 / CLINT::freq() as u64;
+            let jitter = (Clint::mtime() - next_tick) * 1_000_000 / CLINT::freq() as u64;
             println!("jitter: {} us", jitter);
             t_next
         }
@@ -161,7 +155,7 @@ fn main() -> ! {
         riscv::register::mstatus::set_mie();
     };
 
-    println!("Simulating for {} time units", t_sim);
+    println!("Simulating for {} time units", t_sim as u64);
 
     simulator.simulate_rt(0.0, t_sim, wait, ohandler);
 
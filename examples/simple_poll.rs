@@ -5,6 +5,7 @@ use hifive1::hal::e310x::CLINT;
 use hifive1::hal::prelude::*;
 use hifive1::hal::DeviceResources;
 use riscv_rt::entry;
+use riscv_xdevs::rt::clock::Clint;
 use riscv_xdevs::*;
 
 #[cfg(not(feature = "qemu"))]
@@ -14,16 +15,15 @@ extern crate panic_halt;
 /// This is based on busy loops, and interrupts are not used.
 /// While this approach reduces the jitter, it incurs a high CPU load.
 pub fn wait_poll<T: xdevs::aux::Bag>() -> impl FnMut(f64, &mut T) -> f64 {
-    // reset CLINT's mtime register (this is done only once)
-    let mtime = CLINT::mtimer().mtime;
-    mtime.write(0);
+    // capture the epoch of the simulation (mtime is never reset, as others may rely on it)
+    let epoch = Clint::mtime();
     // closure for RT simulation (this is called in every simulation step)
     move |t_next, _| -> f64 {
         // wait until next tick in busy loop
-        let next_tick = t_next as u64 * CLINT::freq() as u64;
-        while mtime.read() < next_tick {}
+        let next_tick = epoch + secf64_to_ticku64(t_next);
+        while Clint::mtime() < next_tick {}
         // check jitter
-        let jitter = (mtime.read() - next_tick) * 1_000_000 / CLINT::freq() as u64;
+        let jitter = (Clint::mtime() - next_tick) * 1_000_000 / CLINT::freq() as u64;
         println!("jitter: {} us", jitter);
         // return next simulation time
         t_next
@@ -70,7 +70,7 @@ fn main() -> ! {
 
     let wait = wait_poll();
 
-    println!("Simulating for {} seconds", t_sim);
+    println!("Simulating for {} seconds", t_sim as u64);
 
     simulator.simulate_rt(0.0, t_sim, wait, |_| {});
 
@@ -9,29 +9,22 @@ use hifive1::hal::e310x::CLINT;
 use hifive1::hal::prelude::*;
 use hifive1::hal::DeviceResources;
 use riscv_rt::entry;
+use riscv_xdevs::rt::clock::Clint;
 use riscv_xdevs::*;
 
-/// Machine timer interrupt handler.
-/// This function is called when the machine timer interrupt is triggered.
-/// It fills the MTIMECMP0 register with the maximum value to disable the timer.
-#[no_mangle]
-#[allow(non_snake_case)]
-fn MachineTimer() {
-    CLINT::mtimecmp0().write(u64::MAX);
-}
+// Machine timer interrupt handler: it clears the alarm after waking up the core
+riscv_xdevs::clint_handler!();
 
 /// Closure for RT simulation on SiFive E310x boards.
 pub fn wait_sleep<T: xdevs::aux::Bag>() -> impl FnMut(f64, &mut T) -> f64 {
-    // reset CLINT's mtime register (this is done only once)
-    let mtimer = hifive1::hal::e310x::CLINT::mtimer();
-    let (mtimecmp, mtime) = (mtimer.mtimecmp0, mtimer.mtime);
-    mtime.write(0);
+    // capture the epoch of the simulation (mtime is never reset, as others may rely on it)
+    let epoch = Clint::mtime();
     // closure for RT simulation (this is called in every simulation step)
     move |t_next, _| -> f64 {
         // configure machine timer interrupt and sleep until next tick
-        let next_tick = t_next as u64 * CLINT::freq() as u64;
-        while mtime.read() < next_tick {
-            mtimecmp.write(next_tick);
+        let next_tick = epoch + secf64_to_ticku64(t_next);
+        while Clint::mtime() < next_tick {
+            Clint::set_mtimecmp(next_tick);
             unsafe {
                 CLINT::mtimer_enable();
                 riscv::asm::wfi();
@@ -40,7 +33,7 @@ pub fn wait_sleep<T: xdevs::aux::Bag>() -> impl FnMut(f64, &mut T) -> f64 {
         // make sure interrupts are disabled after sleep
         CLINT::mtimer_disable();
         // check jitter
-        let jitter = (mtime.read() - next_tick) * 1_000_000 / CLINT::freq() as u64;
+        let jitter = (Clint::mtime() - next_tick) * 1_000_000 / CLINT::freq() as u64;
         println!("jitter: {} us", jitter);
         // return next simulation time
         t_next
@@ -91,7 +84,7 @@ fn main() -> ! {
     println!("Enabling machine interrupts");
     unsafe { riscv::register::mstatus::set_mie() };
 
-    println!("Simulating for {} time units", t_sim);
+    println!("Simulating for {} time units", t_sim as u64);
     simulator.simulate_rt(0.0, t_sim, wait, |_| {});
 
     println!("Simulation finished");
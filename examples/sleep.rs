@@ -4,55 +4,13 @@
 #[cfg(not(feature = "qemu"))]
 extern crate panic_halt;
 
-use hifive1::hal::e310x::CLINT;
-
 use hifive1::hal::prelude::*;
 use hifive1::hal::DeviceResources;
 use riscv_rt::entry;
 use riscv_xdevs::*;
 
-/// Machine timer interrupt handler.
-/// This function is called when the machine timer interrupt is triggered.
-/// It fills the MTIMECMP0 register with the maximum value to disable the timer.
-#[no_mangle]
-#[allow(non_snake_case)]
-fn MachineTimer() {
-    CLINT::mtimecmp0().write(u64::MAX);
-}
-
-/// Closure for RT simulation on SiFive E310x boards.
-pub fn wait_sleep<T: xdevs::aux::Bag>(
-    t_start: f64,
-    t_scale: f64,
-    max_jitter_us: Option<u64>,
-) -> impl FnMut(f64, &mut T) -> f64 {
-    let mtimer = hifive1::hal::e310x::CLINT::mtimer();
-    let (mtimecmp, mtime) = (mtimer.mtimecmp0, mtimer.mtime);
-    mtime.write(0);
-
-    move |t_next, _| -> f64 {
-        // configure machine timer interrupt and sleep until next tick
-        let next_tick = secf64_to_ticku64((t_next - t_start) * t_scale);
-        while mtime.read() < next_tick {
-            mtimecmp.write(next_tick);
-            unsafe {
-                CLINT::mtimer_enable();
-                riscv::asm::wfi();
-            }
-        }
-        CLINT::mtimer_disable(); // make sure interrupts are disabled after sleep
-
-        // check jitter (if necessary)
-        if let Some(max_jitter) = max_jitter_us {
-            let jitter = (mtime.read() - next_tick) * 1_000_000 / CLINT::freq() as u64;
-            println!("jitter: {} us", jitter);
-            if jitter > max_jitter {
-                panic!("jitter is too high");
-            }
-        }
-        t_next
-    }
-}
+// Machine timer interrupt handler: it clears the alarm after waking up the core
+riscv_xdevs::clint_handler!();
 
 #[entry]
 fn main() -> ! {
@@ -83,7 +41,7 @@ fn main() -> ! {
     let proc_time = 1.1;
     let obs_time = 10.;
     let t_sim = 15.;
-    let max_jitter_us = Some(7000);
+    let max_jitter_us = 7000;
 
     let generator = generator::Generator::new(generator::GeneratorState::new(period));
     let processor = processor::Processor::new(processor::ProcessorState::new(proc_time, redled));
@@ -94,15 +52,27 @@ fn main() -> ! {
 
     let mut simulator = xdevs::simulator::Simulator::new(efp);
 
-    let wait = wait_sleep(0.0, 1., max_jitter_us);
+    let mut stats = rt::jitter::JitterStats::new(500);
+    let deadline = rt::deadline::Deadline::new(max_jitter_us, rt::deadline::OnMiss::Panic);
+    let mut clock = rt::clock::Clint::new();
+    let timeline = rt::time::Timeline::start(&mut clock, 0.0, 1.);
+    let wait = rt::wait_sleep(
+        clock,
+        timeline,
+        rt::WaitOptions::new()
+            .with_deadline(deadline)
+            .with_stats(&mut stats),
+        rt::no_input,
+    );
 
     println!("Enabling machine interrupts");
     unsafe { riscv::register::mstatus::set_mie() };
 
-    println!("Simulating for {} time units", t_sim);
+    println!("Simulating for {} time units", t_sim as u64);
     simulator.simulate_rt(0.0, t_sim, wait, |_| {});
 
     println!("Simulation finished");
+    println!("{}", stats);
 
     greenled.set_high().unwrap();
 
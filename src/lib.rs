@@ -1,41 +1,47 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
+#[cfg(target_arch = "riscv32")]
 use hifive1::hal::gpio::*;
 
+#[cfg(target_arch = "riscv32")]
 pub type RedLed = gpio0::Pin0<Output<Regular<NoInvert>>>;
+#[cfg(target_arch = "riscv32")]
 pub type BlueLed = gpio0::Pin1<Output<Regular<NoInvert>>>;
+#[cfg(target_arch = "riscv32")]
 pub type GreenLed = gpio0::Pin2<Output<Regular<NoInvert>>>;
 
+/// Prints a message and a new line (see [`rt::log::println`]).
 #[macro_export]
 macro_rules! println {
     ($($arg:tt)*) => {
-        #[cfg(feature = "qemu")]
-        semihosting::println!($($arg)*);
-        #[cfg(not(feature = "qemu"))]
-        hifive1::sprintln!($($arg)*);
+        $crate::rt::log::println(core::format_args!($($arg)*))
     };
 }
 
+/// Prints a message (see [`rt::log::print`]).
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => {
-        #[cfg(feature = "qemu")]
-        semihosting::print!($($arg)*);
-        #[cfg(not(feature = "qemu"))]
-        hifive1::sprint!($($arg)*);
+        $crate::rt::log::print(core::format_args!($($arg)*))
     };
 }
 
+/// Converts seconds to CLINT ticks, rounding towards the next tick.
+#[cfg(target_arch = "riscv32")]
 #[inline]
 pub fn secf64_to_ticku64(t: f64) -> u64 {
-    t as u64 * hifive1::hal::e310x::CLINT::freq() as u64
+    rt::time::TimeBase::new(hifive1::hal::e310x::CLINT::freq() as u64)
+        .secs_to_ticks(t, rt::time::Rounding::Ceil)
 }
 
+/// Converts CLINT ticks to seconds.
+#[cfg(target_arch = "riscv32")]
 #[inline]
 pub fn ticku64_to_secf64(t: u64) -> f64 {
-    t as f64 / hifive1::hal::e310x::CLINT::freq() as f64
+    rt::time::TimeBase::new(hifive1::hal::e310x::CLINT::freq() as u64).ticks_to_secs(t)
 }
 
+#[cfg(target_arch = "riscv32")]
 #[inline]
 #[allow(unused_variables)]
 pub fn exit(code: i32) -> ! {
@@ -43,13 +49,24 @@ pub fn exit(code: i32) -> ! {
         #[cfg(feature = "qemu")]
         () => semihosting::process::exit(code),
         #[cfg(not(feature = "qemu"))]
-        () => loop {
-            unsafe { riscv::asm::wfi() };
-        },
+        () => {
+            #[cfg(feature = "log-buffer")]
+            rt::log::flush();
+            loop {
+                unsafe { riscv::asm::wfi() };
+            }
+        }
     }
 }
 
+pub mod rt;
+
+#[cfg(target_arch = "riscv32")]
 pub mod generator {
+    use crate::rt::log::Component;
+
+    /// Log component of the generator.
+    pub const LOG: Component = Component::new(0, "G");
 
     pub struct GeneratorState {
         sigma: f64,
@@ -85,7 +102,7 @@ pub mod generator {
         }
 
         fn lambda(state: &Self::State, output: &mut Self::Output) {
-            println!("[G] sending job {}", state.count);
+            crate::debug!(LOG, "sending job {}", state.count);
             output.out_job.add_value(state.count).unwrap();
         }
 
@@ -96,7 +113,7 @@ pub mod generator {
         fn delta_ext(state: &mut Self::State, e: f64, x: &Self::Input) {
             state.sigma -= e;
             if let Some(&stop) = x.in_stop.get_values().last() {
-                println!("[G] received stop: {}", stop);
+                crate::info!(LOG, "received stop: {}", stop);
                 if stop {
                     state.sigma = f64::INFINITY;
                 }
@@ -105,10 +122,15 @@ pub mod generator {
     }
 }
 
+#[cfg(target_arch = "riscv32")]
 pub mod processor {
     use super::RedLed;
+    use crate::rt::log::Component;
     use hifive1::hal::prelude::*;
 
+    /// Log component of the processor.
+    pub const LOG: Component = Component::new(1, "P");
+
     pub struct ProcessorState {
         sigma: f64,
         time: f64,
@@ -147,7 +169,7 @@ pub mod processor {
         fn delta_int(state: &mut Self::State) {
             state.sigma = f64::INFINITY;
             if let Some(job) = state.job {
-                println!("[P] processed job {}", job);
+                crate::debug!(LOG, "processed job {}", job);
                 state.job = None;
                 state.redled.set_low().unwrap();
             }
@@ -166,21 +188,25 @@ pub mod processor {
         fn delta_ext(state: &mut Self::State, e: f64, x: &Self::Input) {
             state.sigma -= e;
             if let Some(&job) = x.in_job.get_values().last() {
-                print!("[P] received job {}", job);
                 if state.job.is_none() {
-                    println!(" (idle)");
+                    crate::debug!(LOG, "received job {} (idle)", job);
                     state.job = Some(job);
                     state.sigma = state.time;
                     state.redled.set_high().unwrap();
                 } else {
-                    println!(" (busy)");
+                    crate::debug!(LOG, "received job {} (busy)", job);
                 }
             }
         }
     }
 }
 
+#[cfg(target_arch = "riscv32")]
 pub mod transducer {
+    use crate::rt::log::Component;
+
+    /// Log component of the transducer.
+    pub const LOG: Component = Component::new(2, "T");
 
     pub struct TransducerState {
         sigma: f64,
@@ -223,9 +249,11 @@ pub mod transducer {
             } else {
                 (0.0, 0.0)
             };
-            println!(
-                "[T] acceptance: {:.2}, throughput: {:.2}",
-                acceptance, throughput
+            crate::info!(
+                LOG,
+                "acceptance: {:.2}, throughput: {:.2}",
+                acceptance,
+                throughput
             );
             state.sigma = f64::INFINITY;
         }
@@ -247,6 +275,7 @@ pub mod transducer {
     }
 }
 
+#[cfg(target_arch = "riscv32")]
 xdevs::component!(
     ident = PT,
     input = {
@@ -267,6 +296,12 @@ xdevs::component!(
     }
 );
 
+#[cfg(target_arch = "riscv32")]
+crate::port_bag!(PTInput { in_job });
+#[cfg(target_arch = "riscv32")]
+crate::port_bag!(PTOutput { out_stop });
+
+#[cfg(target_arch = "riscv32")]
 xdevs::component!(
     ident = GPT,
     components = {
@@ -282,6 +317,7 @@ xdevs::component!(
     }
 );
 
+#[cfg(target_arch = "riscv32")]
 xdevs::component!(
     ident = EF,
     input = {
@@ -302,6 +338,7 @@ xdevs::component!(
     }
 );
 
+#[cfg(target_arch = "riscv32")]
 xdevs::component!(
     ident = EFP,
     components = {
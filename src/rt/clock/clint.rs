@@ -0,0 +1,103 @@
+use super::{split, Alarm, Clock, Timestamp};
+use hifive1::hal::e310x::CLINT;
+
+/// Address of CLINT's mtime register (low word first).
+const MTIME: *mut u32 = 0x0200_BFF8 as *mut u32;
+/// Address of CLINT's mtimecmp register of hart 0 (low word first).
+const MTIMECMP0: *mut u32 = 0x0200_4000 as *mut u32;
+
+/// CLINT's machine timer.
+///
+/// Applications that arm it (e.g., through the wait strategies) must define the `MachineTimer`
+/// interrupt handler, either on their own or with [`crate::clint_handler`].
+pub struct Clint {
+    _private: (),
+}
+
+impl Clint {
+    /// Creates a new CLINT clock. CLINT's mtime register is left untouched,
+    /// so several clocks can share the machine timer.
+    pub fn new() -> Self {
+        Self { _private: () }
+    }
+
+    /// Reads CLINT's mtime register.
+    /// Unlike a plain 64-bit read, it never tears when the low word rolls over.
+    #[inline]
+    pub fn mtime() -> u64 {
+        unsafe { split::read(|| MTIME.add(1).read_volatile(), || MTIME.read_volatile()) }
+    }
+
+    /// Writes CLINT's mtimecmp register of hart 0.
+    /// Unlike a plain 64-bit write, it never triggers a spurious interrupt while updating.
+    #[inline]
+    pub fn set_mtimecmp(tick: u64) {
+        unsafe {
+            split::write(
+                |hi| MTIMECMP0.add(1).write_volatile(hi),
+                |lo| MTIMECMP0.write_volatile(lo),
+                tick,
+            )
+        }
+    }
+}
+
+impl Default for Clint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for Clint {
+    #[inline]
+    fn now(&mut self) -> u64 {
+        Self::mtime()
+    }
+
+    #[inline]
+    fn freq(&self) -> u64 {
+        CLINT::freq() as u64
+    }
+}
+
+impl Timestamp for Clint {
+    #[inline]
+    fn timestamp() -> u64 {
+        Self::mtime()
+    }
+}
+
+impl Alarm for Clint {
+    #[inline]
+    fn arm(&mut self, tick: u64) {
+        Self::set_mtimecmp(tick);
+        unsafe { CLINT::mtimer_enable() };
+    }
+
+    #[inline]
+    fn disarm(&mut self) {
+        CLINT::mtimer_disable();
+    }
+
+    #[inline]
+    fn wait_for_interrupt(&mut self) {
+        unsafe { riscv::asm::wfi() };
+    }
+}
+
+/// Generates the `MachineTimer` interrupt handler of [`Clint`].
+/// It fills CLINT's MTIMECMP0 register with the maximum value to clear the interrupt.
+///
+/// ```ignore
+/// riscv_xdevs::clint_handler!();
+/// ```
+#[macro_export]
+macro_rules! clint_handler {
+    () => {
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        fn MachineTimer() {
+            $crate::rt::clock::Clint::set_mtimecmp(u64::MAX);
+        }
+    };
+}
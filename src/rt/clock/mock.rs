@@ -0,0 +1,310 @@
+use super::{Alarm, Clock};
+use core::cell::Cell;
+use core::fmt;
+
+/// Scripted external interrupts for [`MockClock`].
+///
+/// The clock fires each interrupt once it reaches its tick,
+/// and waiting for an interrupt wakes up the core at the next scripted interrupt (if it comes
+/// before the alarm). Interrupts are shared by reference, so input handlers can take them
+/// as an interrupt handler would do.
+pub struct Interrupts<'a> {
+    /// Ticks of the scripted interrupts, in ascending order.
+    ticks: &'a [u64],
+    /// Index of the next interrupt to be fired.
+    next: Cell<usize>,
+    /// Number of fired interrupts not taken yet.
+    pending: Cell<usize>,
+    /// Interrupt handler (if any). It receives the index of the fired interrupt.
+    handler: Option<&'a dyn Fn(usize)>,
+}
+
+impl<'a> Interrupts<'a> {
+    /// Creates a new interrupt script.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `ticks` are not sorted in ascending order.
+    pub fn new(ticks: &'a [u64]) -> Self {
+        assert!(
+            ticks.windows(2).all(|w| w[0] <= w[1]),
+            "interrupts must be sorted"
+        );
+        Self {
+            ticks,
+            next: Cell::new(0),
+            pending: Cell::new(0),
+            handler: None,
+        }
+    }
+
+    /// Creates a new interrupt script with an interrupt handler.
+    /// The handler is called every time an interrupt is fired, with the index of the interrupt.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `ticks` are not sorted in ascending order.
+    pub fn with_handler(ticks: &'a [u64], handler: &'a dyn Fn(usize)) -> Self {
+        Self {
+            handler: Some(handler),
+            ..Self::new(ticks)
+        }
+    }
+
+    /// Takes the fired interrupts. It returns how many interrupts were pending.
+    #[inline]
+    pub fn take(&self) -> usize {
+        self.pending.replace(0)
+    }
+
+    /// Returns the tick of the next interrupt to be fired (if any).
+    #[inline]
+    pub fn next_tick(&self) -> Option<u64> {
+        self.ticks.get(self.next.get()).copied()
+    }
+
+    /// Fires all the interrupts scheduled up to `tick`.
+    fn fire_until(&self, tick: u64) {
+        while matches!(self.next_tick(), Some(next) if next <= tick) {
+            let index = self.next.get();
+            self.next.set(index + 1);
+            self.pending.set(self.pending.get() + 1);
+            if let Some(handler) = self.handler {
+                handler(index);
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Interrupts<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interrupts")
+            .field("ticks", &self.ticks)
+            .field("next", &self.next)
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Software clock for testing the wait strategies on the host.
+///
+/// Time only advances when told so, or by a fixed step every time the clock is read
+/// (to emulate busy loops). Waiting for an interrupt jumps straight to the armed alarm
+/// (plus an optional wake-up latency) or to the next scripted interrupt,
+/// so tests run instantaneously.
+#[derive(Clone, Debug)]
+pub struct MockClock<'a> {
+    /// Frequency of the clock, in ticks per second.
+    freq: u64,
+    /// Current tick.
+    now: u64,
+    /// Ticks that the clock advances every time it is read.
+    step: u64,
+    /// Tick of the armed alarm (if any).
+    alarm: Option<u64>,
+    /// Ticks between the alarm and the moment the core wakes up.
+    latency: u64,
+    /// Scripted external interrupts (if any).
+    interrupts: Option<&'a Interrupts<'a>>,
+}
+
+impl<'a> MockClock<'a> {
+    /// Creates a new mock clock with the given frequency, starting at tick 0.
+    pub const fn new(freq: u64) -> Self {
+        Self {
+            freq,
+            now: 0,
+            step: 0,
+            alarm: None,
+            latency: 0,
+            interrupts: None,
+        }
+    }
+
+    /// Sets the scripted external interrupts.
+    pub fn set_interrupts(&mut self, interrupts: &'a Interrupts<'a>) {
+        self.interrupts = Some(interrupts);
+    }
+
+    /// Sets the wake-up latency of the alarm, in ticks.
+    pub fn set_latency(&mut self, latency: u64) {
+        self.latency = latency;
+    }
+
+    /// Sets the ticks that the clock advances every time it is read.
+    pub fn set_step(&mut self, step: u64) {
+        self.step = step;
+    }
+
+    /// Sets the current tick of the clock.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `tick` is in the past, as clocks are monotonic.
+    pub fn set(&mut self, tick: u64) {
+        assert!(tick >= self.now, "mock clock cannot go backwards");
+        self.now = tick;
+        self.fire();
+    }
+
+    /// Advances the clock the given number of ticks.
+    pub fn advance(&mut self, ticks: u64) {
+        self.now += ticks;
+        self.fire();
+    }
+
+    /// Returns the tick of the armed alarm (if any).
+    #[inline]
+    pub fn alarm(&self) -> Option<u64> {
+        self.alarm
+    }
+
+    /// Fires all the scripted interrupts up to the current tick.
+    fn fire(&self) {
+        if let Some(interrupts) = self.interrupts {
+            interrupts.fire_until(self.now);
+        }
+    }
+}
+
+impl<'a> Clock for MockClock<'a> {
+    #[inline]
+    fn now(&mut self) -> u64 {
+        self.advance(self.step);
+        self.now
+    }
+
+    #[inline]
+    fn freq(&self) -> u64 {
+        self.freq
+    }
+}
+
+impl<'a> Alarm for MockClock<'a> {
+    #[inline]
+    fn arm(&mut self, tick: u64) {
+        self.alarm = Some(tick);
+    }
+
+    #[inline]
+    fn disarm(&mut self) {
+        self.alarm = None;
+    }
+
+    /// It advances the clock to the armed alarm plus the wake-up latency,
+    /// or to the next scripted interrupt if it comes first.
+    ///
+    /// # Panics
+    ///
+    /// It panics if there is neither an armed alarm nor a scripted interrupt,
+    /// as the core would sleep forever.
+    fn wait_for_interrupt(&mut self) {
+        let alarm = self.alarm.map(|alarm| alarm.saturating_add(self.latency));
+        let interrupt = self
+            .interrupts
+            .and_then(|interrupts| interrupts.next_tick());
+        let wake_up = match (alarm, interrupt) {
+            (Some(alarm), Some(interrupt)) => u64::min(alarm, interrupt),
+            (Some(tick), None) | (None, Some(tick)) => tick,
+            (None, None) => panic!("waiting for interrupt with no alarm armed"),
+        };
+        self.now = u64::max(self.now, wake_up);
+        self.fire();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_clock() {
+        let mut clock = MockClock::new(32_768);
+        assert_eq!(clock.now(), 0);
+        assert_eq!(clock.time_base().freq(), 32_768);
+        clock.advance(10);
+        assert_eq!(clock.now(), 10);
+        clock.set(20);
+        assert_eq!(clock.now(), 20);
+
+        clock.arm(100);
+        assert_eq!(clock.alarm(), Some(100));
+        clock.wait_for_interrupt();
+        assert_eq!(clock.now(), 100);
+
+        clock.set_latency(5);
+        clock.arm(200);
+        clock.wait_for_interrupt();
+        assert_eq!(clock.now(), 205);
+        // alarms in the past wake up the core immediately
+        clock.arm(0);
+        clock.wait_for_interrupt();
+        assert_eq!(clock.now(), 205);
+
+        clock.disarm();
+        assert_eq!(clock.alarm(), None);
+
+        clock.set_step(2);
+        assert_eq!(clock.now(), 207);
+        assert_eq!(clock.now(), 209);
+    }
+
+    #[test]
+    fn interrupts() {
+        let interrupts = Interrupts::new(&[50, 60, 60, 300]);
+        let mut clock = MockClock::new(32_768);
+        clock.set_interrupts(&interrupts);
+        assert_eq!(interrupts.take(), 0);
+
+        // interrupts wake up the core before the alarm
+        clock.arm(100);
+        clock.wait_for_interrupt();
+        assert_eq!(clock.now(), 50);
+        assert_eq!(interrupts.take(), 1);
+        clock.wait_for_interrupt();
+        assert_eq!(clock.now(), 60);
+        assert_eq!(interrupts.take(), 2);
+        clock.wait_for_interrupt();
+        assert_eq!(clock.now(), 100);
+        assert_eq!(interrupts.take(), 0);
+
+        // interrupts are also fired when the clock advances
+        clock.set(400);
+        assert_eq!(interrupts.take(), 1);
+        assert_eq!(interrupts.next_tick(), None);
+    }
+
+    #[test]
+    fn interrupt_handler() {
+        let fired = Cell::new(0);
+        let handler = |index| fired.set(fired.get() + 10 * index + 1);
+        let interrupts = Interrupts::with_handler(&[50, 60], &handler);
+        let mut clock = MockClock::new(32_768);
+        clock.set_interrupts(&interrupts);
+        clock.advance(55);
+        assert_eq!(fired.get(), 1);
+        clock.advance(5);
+        assert_eq!(fired.get(), 12);
+        assert_eq!(interrupts.take(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "mock clock cannot go backwards")]
+    fn backwards() {
+        let mut clock = MockClock::new(32_768);
+        clock.advance(10);
+        clock.set(5);
+    }
+
+    #[test]
+    #[should_panic(expected = "waiting for interrupt with no alarm armed")]
+    fn sleep_forever() {
+        MockClock::new(32_768).wait_for_interrupt();
+    }
+
+    #[test]
+    #[should_panic(expected = "interrupts must be sorted")]
+    fn unsorted_interrupts() {
+        Interrupts::new(&[2, 1]);
+    }
+}
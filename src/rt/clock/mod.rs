@@ -0,0 +1,90 @@
+//! Clock abstraction of the wait strategies.
+//!
+//! Wait strategies do not access any timer directly. Instead, they rely on the [`Clock`]
+//! and [`Alarm`] traits. This crate provides the following implementations:
+//!
+//! - [`Clint`]: CLINT's machine timer (only on the target).
+//! - [`AonRtc`]: real-time clock of the always-on domain (only on the target).
+//! - [`MockClock`]: software clock for testing the wait strategies on the host.
+
+use super::time::TimeBase;
+
+#[cfg(target_arch = "riscv32")]
+mod clint;
+mod mock;
+#[cfg(target_arch = "riscv32")]
+mod rtc;
+#[cfg(any(target_arch = "riscv32", test))]
+mod split;
+
+#[cfg(target_arch = "riscv32")]
+pub use clint::Clint;
+pub use mock::{Interrupts, MockClock};
+#[cfg(target_arch = "riscv32")]
+pub use rtc::AonRtc;
+
+/// Monotonic clock.
+pub trait Clock {
+    /// Returns the current tick of the clock.
+    fn now(&mut self) -> u64;
+
+    /// Returns the frequency of the clock, in ticks per second.
+    fn freq(&self) -> u64;
+
+    /// Returns the time base of the clock.
+    #[inline]
+    fn time_base(&self) -> TimeBase {
+        TimeBase::new(self.freq())
+    }
+}
+
+/// Clock that can wake up the core at a given tick.
+pub trait Alarm: Clock {
+    /// Arms the alarm, so it triggers an interrupt once the clock reaches `tick`.
+    fn arm(&mut self, tick: u64);
+
+    /// Disarms the alarm.
+    fn disarm(&mut self);
+
+    /// Puts the core to sleep until an interrupt is triggered.
+    /// Note that the interrupt may come from the alarm or from any other source.
+    fn wait_for_interrupt(&mut self);
+}
+
+/// Clock that can be read without a handle, so interrupt handlers can timestamp the events
+/// they capture with the clock of the wait strategy (see [`super::queue::CaptureClock`]).
+pub trait Timestamp: Clock {
+    /// Returns the current tick of the clock.
+    fn timestamp() -> u64;
+}
+
+/// Wait strategies take ownership of their clock. Passing a mutable reference instead
+/// allows inspecting the clock afterwards (e.g., in tests).
+impl<C: Clock + ?Sized> Clock for &mut C {
+    #[inline]
+    fn now(&mut self) -> u64 {
+        (**self).now()
+    }
+
+    #[inline]
+    fn freq(&self) -> u64 {
+        (**self).freq()
+    }
+}
+
+impl<A: Alarm + ?Sized> Alarm for &mut A {
+    #[inline]
+    fn arm(&mut self, tick: u64) {
+        (**self).arm(tick)
+    }
+
+    #[inline]
+    fn disarm(&mut self) {
+        (**self).disarm()
+    }
+
+    #[inline]
+    fn wait_for_interrupt(&mut self) {
+        (**self).wait_for_interrupt()
+    }
+}
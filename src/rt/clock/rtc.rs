@@ -0,0 +1,89 @@
+use super::{split, Alarm, Clock, Timestamp};
+use hifive1::hal::e310x::RTC;
+use hifive1::hal::rtc::{Rtc, RtcExt};
+
+/// Real-time clock of the always-on (AON) domain.
+///
+/// It counts at the frequency of the low-frequency clock (32.768 kHz), and it keeps counting
+/// while the core is in deep sleep. Its comparator only holds 32 bits, so alarms beyond the
+/// current 32-bit period are armed at the end of the period and re-armed after waking up.
+///
+/// The alarm interrupt is routed through the PLIC, so applications must enable the `RTC`
+/// interrupt source in the PLIC and define the `RTC` interrupt handler,
+/// either on their own or with [`crate::rtc_handler`].
+pub struct AonRtc {
+    rtc: Rtc,
+}
+
+impl AonRtc {
+    /// Frequency of the low-frequency clock, in ticks per second.
+    pub const FREQ: u64 = 32_768;
+
+    /// Creates a new AON RTC clock. It enables the RTC counter with no scaling.
+    /// The counter is not reset, as it keeps track of time across deep sleep periods.
+    pub fn new(rtc: RTC) -> Self {
+        let mut rtc = rtc.constrain();
+        rtc.set_rtccmp(u32::MAX);
+        rtc.set_scale(0);
+        rtc.enable();
+        Self { rtc }
+    }
+
+    /// Clears the alarm interrupt by filling the RTCCMP0 register with the maximum value.
+    /// It is called from the `RTC` interrupt handler generated with [`crate::rtc_handler`].
+    pub fn clear_interrupt() {
+        unsafe { RTC::steal() }.constrain().set_rtccmp(u32::MAX);
+    }
+}
+
+impl Clock for AonRtc {
+    #[inline]
+    fn now(&mut self) -> u64 {
+        Self::timestamp()
+    }
+
+    #[inline]
+    fn freq(&self) -> u64 {
+        Self::FREQ
+    }
+}
+
+impl Timestamp for AonRtc {
+    /// Reads the counter of the RTC without tearing.
+    fn timestamp() -> u64 {
+        let rtc = unsafe { &*RTC::ptr() };
+        split::read(|| rtc.rtchi.read().bits(), || rtc.rtclo.read().bits())
+    }
+}
+
+impl Alarm for AonRtc {
+    fn arm(&mut self, tick: u64) {
+        self.rtc.set_rtccmp(split::compare32(tick, Self::timestamp()));
+    }
+
+    #[inline]
+    fn disarm(&mut self) {
+        self.rtc.set_rtccmp(u32::MAX);
+    }
+
+    #[inline]
+    fn wait_for_interrupt(&mut self) {
+        unsafe { riscv::asm::wfi() };
+    }
+}
+
+/// Generates the `RTC` interrupt handler of [`AonRtc`] (see [`AonRtc::clear_interrupt`]).
+///
+/// ```ignore
+/// riscv_xdevs::rtc_handler!();
+/// ```
+#[macro_export]
+macro_rules! rtc_handler {
+    () => {
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        fn RTC() {
+            $crate::rt::clock::AonRtc::clear_interrupt();
+        }
+    };
+}
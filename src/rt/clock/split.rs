@@ -0,0 +1,116 @@
+//! Access to 64-bit timer registers split in two 32-bit halves.
+//!
+//! On RV32, 64-bit registers such as CLINT's mtime and mtimecmp are accessed
+//! as two 32-bit words, so a plain read or write is not atomic.
+
+/// Reads a 64-bit counter without tearing.
+///
+/// If the low half rolls over between the two accesses, the value read may be 2^32 ticks off.
+/// Thus, it reads the high half before and after the low half, and retries if it changed.
+pub fn read(mut read_hi: impl FnMut() -> u32, mut read_lo: impl FnMut() -> u32) -> u64 {
+    loop {
+        let hi = read_hi();
+        let lo = read_lo();
+        if read_hi() == hi {
+            return ((hi as u64) << 32) | lo as u64;
+        }
+    }
+}
+
+/// Returns the value of a 32-bit comparator of a 64-bit counter for an alarm at `tick`,
+/// where `now` is the current value of the counter (read with [`read`]).
+/// As the comparator only holds the lower 32 bits of the counter, alarms beyond the current
+/// 32-bit period are set to the end of the period (and they must be re-armed after waking up).
+pub fn compare32(tick: u64, now: u64) -> u32 {
+    if (tick >> 32) == (now >> 32) {
+        tick as u32
+    } else {
+        u32::MAX
+    }
+}
+
+/// Writes a 64-bit comparator without glitches.
+///
+/// The low half is first set to its maximum value, so the comparator is never lower than
+/// both the old and the new values while updating the high half, and no spurious interrupt is triggered.
+pub fn write(mut write_hi: impl FnMut(u32), mut write_lo: impl FnMut(u32), value: u64) {
+    write_lo(u32::MAX);
+    write_hi((value >> 32) as u32);
+    write_lo(value as u32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::{Cell, RefCell};
+
+    #[test]
+    fn read_across_rollover() {
+        // the counter advances a few ticks on every access
+        for step in 1..8 {
+            let start = u32::MAX as u64 - 10;
+            let counter = Cell::new(start);
+            let access = || {
+                let value = counter.get();
+                counter.set(value + step);
+                value
+            };
+            let mut previous = start;
+            for _ in 0..10 {
+                let now = read(|| (access() >> 32) as u32, || access() as u32);
+                assert!(now >= previous);
+                assert!(now <= counter.get());
+                previous = now;
+            }
+            assert!(previous > u32::MAX as u64);
+        }
+    }
+
+    #[test]
+    fn compare_across_rollover() {
+        let tick = 0x1_0000_0005;
+        assert_eq!(compare32(tick, 0x1_0000_0000), 5);
+        assert_eq!(compare32(tick, 0xFFFF_FFF0), u32::MAX);
+        assert_eq!(compare32(tick, 0x2_0000_0000), u32::MAX);
+
+        // the counter rolls over between the accesses to its halves
+        let counter = Cell::new(u32::MAX as u64);
+        let access = || {
+            let value = counter.get();
+            counter.set(value + 1);
+            value
+        };
+        // a torn read would be 2^32 ticks behind, and the alarm would wait for a whole period
+        let now = read(|| (access() >> 32) as u32, || access() as u32);
+        assert!(now > u32::MAX as u64);
+        assert_eq!(compare32(tick, now), 5);
+    }
+
+    #[test]
+    fn write_without_glitches() {
+        for (old, new) in [
+            (u64::MAX, 0x1_0000_0010),
+            (0x1_0000_0010, 0x2_0000_0000),
+            (0x2_0000_0000, 0x1_FFFF_FFFF),
+            (0x1_FFFF_FFFF, 5),
+        ] {
+            let cmp = Cell::new(old);
+            let history = RefCell::new(Vec::new());
+            let update = |value: u64| {
+                cmp.set(value);
+                history.borrow_mut().push(value);
+            };
+            write(
+                |hi| update((cmp.get() & 0xFFFF_FFFF) | (hi as u64) << 32),
+                |lo| update((cmp.get() & !0xFFFF_FFFF) | lo as u64),
+                new,
+            );
+            assert_eq!(cmp.get(), new);
+            // intermediate values never trigger an interrupt that neither value would trigger
+            let history = history.into_inner();
+            for &value in &history[..history.len() - 1] {
+                assert!(value >= u64::min(old, new));
+            }
+        }
+    }
+}
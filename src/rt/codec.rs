@@ -0,0 +1,359 @@
+//! Encoding of port values without allocation.
+//!
+//! [`PortCodec`] encodes a value into a byte buffer (e.g., the payload of a [`super::frame::Frame`],
+//! a trace buffer, or a semihosting write), and decodes it back.
+//! It is implemented for the primitive types (in little endian, with `usize` and `isize`
+//! widened to 64 bits so boards and hosts agree), and it can be implemented for structs
+//! with [`crate::port_codec`], which encodes their fields in order:
+//!
+//! ```ignore
+//! struct Job { id: usize, urgent: bool }
+//! riscv_xdevs::port_codec!(Job { id: usize, urgent: bool });
+//! ```
+//!
+//! [`PortBag`] walks the ports of an input or output bag, and it is implemented with
+//! [`crate::port_bag`], which assigns indices to the ports in the given order.
+//! [`ports`] connects a bag to the output handler of [`super::frame`]:
+//!
+//! ```ignore
+//! riscv_xdevs::port_bag!(PTOutput { out_stop });
+//!
+//! // the output handler is the only writer of UART0
+//! let ohandler = rt::frame::handler(clock, rt::codec::ports, |bytes| unsafe {
+//!     Uart::Uart0.write(bytes)
+//! });
+//! ```
+
+use super::uart::InjectError;
+use xdevs::aux::Bag;
+
+/// Maximum length of an encoded value when walking a [`PortBag`], in bytes.
+pub const MAX_LEN: usize = super::frame::MAX_VALUE;
+
+/// Error of an encoding or a decoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodecError {
+    /// The buffer is too small for the encoded value.
+    BufferTooSmall,
+    /// The buffer ends before the encoded value.
+    Truncated,
+    /// The encoded value is invalid (e.g., a `bool` that is neither 0 nor 1).
+    Invalid,
+}
+
+/// Value of a port that can be encoded into (and decoded from) a byte buffer.
+pub trait PortCodec: Sized {
+    /// Maximum length of an encoded value, in bytes.
+    const MAX_LEN: usize;
+
+    /// Encodes the value at the beginning of `buf` and returns the number of bytes written.
+    fn encode(&self, buf: &mut [u8]) -> Result<usize, CodecError>;
+
+    /// Decodes a value from the beginning of `buf` and returns it with the number of bytes read.
+    fn decode(buf: &[u8]) -> Result<(Self, usize), CodecError>;
+}
+
+/// Implements [`PortCodec`] for numbers, in little endian.
+macro_rules! impl_number {
+    ($($ty:ty),+) => {
+        $(
+            impl PortCodec for $ty {
+                const MAX_LEN: usize = core::mem::size_of::<$ty>();
+
+                fn encode(&self, buf: &mut [u8]) -> Result<usize, CodecError> {
+                    (buf.get_mut(..Self::MAX_LEN))
+                        .ok_or(CodecError::BufferTooSmall)?
+                        .copy_from_slice(&self.to_le_bytes());
+                    Ok(Self::MAX_LEN)
+                }
+
+                fn decode(buf: &[u8]) -> Result<(Self, usize), CodecError> {
+                    let mut bytes = [0; core::mem::size_of::<$ty>()];
+                    bytes.copy_from_slice(buf.get(..Self::MAX_LEN).ok_or(CodecError::Truncated)?);
+                    Ok((Self::from_le_bytes(bytes), Self::MAX_LEN))
+                }
+            }
+        )+
+    };
+}
+
+impl_number!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl PortCodec for usize {
+    const MAX_LEN: usize = u64::MAX_LEN;
+
+    fn encode(&self, buf: &mut [u8]) -> Result<usize, CodecError> {
+        (*self as u64).encode(buf)
+    }
+
+    /// Values that do not fit in a `usize` are invalid.
+    fn decode(buf: &[u8]) -> Result<(Self, usize), CodecError> {
+        let (value, len) = u64::decode(buf)?;
+        let value = Self::try_from(value).map_err(|_| CodecError::Invalid)?;
+        Ok((value, len))
+    }
+}
+
+impl PortCodec for isize {
+    const MAX_LEN: usize = i64::MAX_LEN;
+
+    fn encode(&self, buf: &mut [u8]) -> Result<usize, CodecError> {
+        (*self as i64).encode(buf)
+    }
+
+    /// Values that do not fit in an `isize` are invalid.
+    fn decode(buf: &[u8]) -> Result<(Self, usize), CodecError> {
+        let (value, len) = i64::decode(buf)?;
+        let value = Self::try_from(value).map_err(|_| CodecError::Invalid)?;
+        Ok((value, len))
+    }
+}
+
+impl PortCodec for bool {
+    const MAX_LEN: usize = 1;
+
+    fn encode(&self, buf: &mut [u8]) -> Result<usize, CodecError> {
+        (*self as u8).encode(buf)
+    }
+
+    fn decode(buf: &[u8]) -> Result<(Self, usize), CodecError> {
+        match u8::decode(buf)? {
+            (0, len) => Ok((false, len)),
+            (1, len) => Ok((true, len)),
+            _ => Err(CodecError::Invalid),
+        }
+    }
+}
+
+impl PortCodec for () {
+    const MAX_LEN: usize = 0;
+
+    fn encode(&self, _buf: &mut [u8]) -> Result<usize, CodecError> {
+        Ok(0)
+    }
+
+    fn decode(_buf: &[u8]) -> Result<(Self, usize), CodecError> {
+        Ok(((), 0))
+    }
+}
+
+/// Input or output bag whose ports can be walked.
+pub trait PortBag: Bag {
+    /// Names of the ports, by index.
+    const PORTS: &'static [&'static str];
+
+    /// Encodes every value of every non-empty port and passes it to `visit`,
+    /// together with the index of its port. It stops at the first encoding error.
+    fn walk(&self, visit: &mut dyn FnMut(u8, &[u8])) -> Result<(), CodecError>;
+
+    /// Decodes a value and adds it to the port with the given index.
+    /// Unknown ports and invalid values are [`InjectError::Invalid`].
+    fn inject(&mut self, port: u8, buf: &[u8]) -> Result<(), InjectError>;
+}
+
+/// Encodes the values of `bag` and passes them to `emit` with the index of their ports.
+/// It can be used as the `ports` argument of [`super::frame::handler`].
+/// Encoding errors are logged, and the remaining values are dropped.
+pub fn ports<T: PortBag>(bag: &T, emit: &mut dyn FnMut(u8, &[u8])) {
+    if let Err(error) = bag.walk(emit) {
+        crate::warn!(super::log::RT, "failed to encode output: {:?}", error);
+    }
+}
+
+/// Implements [`PortCodec`] for a struct, encoding its fields in the given order.
+/// The types of all the fields must implement [`PortCodec`].
+///
+/// ```ignore
+/// riscv_xdevs::port_codec!(Job { id: usize, urgent: bool });
+/// ```
+#[macro_export]
+macro_rules! port_codec {
+    ($ty:ident { $($field:ident: $field_ty:ty),* $(,)? }) => {
+        impl $crate::rt::codec::PortCodec for $ty {
+            const MAX_LEN: usize =
+                0 $(+ <$field_ty as $crate::rt::codec::PortCodec>::MAX_LEN)*;
+
+            #[allow(unused_variables, unused_mut)]
+            fn encode(&self, buf: &mut [u8]) -> Result<usize, $crate::rt::codec::CodecError> {
+                let mut len = 0;
+                $(
+                    len += $crate::rt::codec::PortCodec::encode(
+                        &self.$field,
+                        &mut buf[len..],
+                    )?;
+                )*
+                Ok(len)
+            }
+
+            #[allow(unused_variables, unused_mut)]
+            fn decode(buf: &[u8]) -> Result<(Self, usize), $crate::rt::codec::CodecError> {
+                let mut len = 0;
+                $(
+                    let ($field, field_len) = <$field_ty as $crate::rt::codec::PortCodec>::decode(
+                        &buf[len..],
+                    )?;
+                    len += field_len;
+                )*
+                Ok((Self { $($field),* }, len))
+            }
+        }
+    };
+}
+
+/// Implements [`PortBag`] for an input or output bag, indexing its ports in the given order.
+/// The value types of all the ports must implement [`PortCodec`].
+///
+/// ```ignore
+/// riscv_xdevs::port_bag!(PTInput { in_job });
+/// ```
+#[macro_export]
+macro_rules! port_bag {
+    ($ty:ident { $($port:ident),* $(,)? }) => {
+        impl $crate::rt::codec::PortBag for $ty {
+            const PORTS: &'static [&'static str] = &[$(stringify!($port)),*];
+
+            #[allow(unused_variables, unused_mut, unused_assignments)]
+            fn walk(
+                &self,
+                visit: &mut dyn FnMut(u8, &[u8]),
+            ) -> Result<(), $crate::rt::codec::CodecError> {
+                let mut index = 0;
+                $(
+                    for value in self.$port.get_values() {
+                        let mut buf = [0; $crate::rt::codec::MAX_LEN];
+                        let len = $crate::rt::codec::PortCodec::encode(value, &mut buf)?;
+                        visit(index, &buf[..len]);
+                    }
+                    index += 1;
+                )*
+                Ok(())
+            }
+
+            #[allow(unused_variables, unused_mut, unused_assignments)]
+            fn inject(
+                &mut self,
+                port: u8,
+                buf: &[u8],
+            ) -> Result<(), $crate::rt::uart::InjectError> {
+                let mut index = 0;
+                $(
+                    if port == index {
+                        let (value, _) = $crate::rt::codec::PortCodec::decode(buf)
+                            .map_err(|_| $crate::rt::uart::InjectError::Invalid)?;
+                        return self
+                            .$port
+                            .add_value(value)
+                            .map_err(|_| $crate::rt::uart::InjectError::Full);
+                    }
+                    index += 1;
+                )*
+                Err($crate::rt::uart::InjectError::Invalid)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xdevs::port::Port;
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    struct Job {
+        id: usize,
+        urgent: bool,
+        load: f32,
+    }
+
+    crate::port_codec!(Job {
+        id: usize,
+        urgent: bool,
+        load: f32,
+    });
+
+    #[derive(Debug, Default)]
+    struct Output {
+        out_job: Port<Job, 2>,
+        out_stop: Port<bool, 1>,
+    }
+
+    impl Bag for Output {
+        fn is_empty(&self) -> bool {
+            self.out_job.is_empty() && self.out_stop.is_empty()
+        }
+
+        fn clear(&mut self) {
+            self.out_job.clear();
+            self.out_stop.clear();
+        }
+    }
+
+    crate::port_bag!(Output { out_job, out_stop });
+
+    /// Encodes a value into a vector.
+    fn encode<V: PortCodec>(value: V) -> Vec<u8> {
+        let mut buf = [0; MAX_LEN];
+        let len = value.encode(&mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn primitives() {
+        assert_eq!(encode(0x0102u16), [0x02, 0x01]);
+        assert_eq!(encode(-1i8), [0xFF]);
+        assert_eq!(encode(true), [1]);
+        assert_eq!(encode(42usize), [42, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(encode(()), []);
+        assert_eq!(u32::decode(&[1, 0, 0, 0, 9]), Ok((1, 4)));
+        assert_eq!(f64::decode(&encode(0.5f64)), Ok((0.5, 8)));
+        assert_eq!(isize::decode(&encode(-3isize)), Ok((-3, 8)));
+        assert_eq!(bool::decode(&[2]), Err(CodecError::Invalid));
+        assert_eq!(u16::decode(&[1]), Err(CodecError::Truncated));
+        assert_eq!(1u32.encode(&mut [0; 3]), Err(CodecError::BufferTooSmall));
+    }
+
+    #[test]
+    fn structs() {
+        let job = Job {
+            id: 7,
+            urgent: true,
+            load: 0.25,
+        };
+        assert_eq!(Job::MAX_LEN, 13);
+        let encoded = encode(job);
+        assert_eq!(encoded.len(), 13);
+        assert_eq!(Job::decode(&encoded), Ok((job, 13)));
+        assert_eq!(Job::decode(&encoded[..12]), Err(CodecError::Truncated));
+        assert_eq!(job.encode(&mut [0; 12]), Err(CodecError::BufferTooSmall));
+    }
+
+    #[test]
+    fn walk_bag() {
+        assert_eq!(Output::PORTS, ["out_job", "out_stop"]);
+        let mut output = Output::default();
+        let job = Job {
+            id: 1,
+            urgent: false,
+            load: 1.,
+        };
+        output.out_job.add_value(job).unwrap();
+        output.out_stop.add_value(true).unwrap();
+
+        let mut visited = Vec::new();
+        ports(&output, &mut |port, value| {
+            visited.push((port, value.to_vec()))
+        });
+        assert_eq!(visited, [(0, encode(job)), (1, vec![1])]);
+
+        // the walked values can be injected back
+        let mut input = Output::default();
+        for (port, value) in &visited {
+            input.inject(*port, value).unwrap();
+        }
+        assert_eq!(input.out_job.get_values(), [job]);
+        assert_eq!(input.out_stop.get_values(), [true]);
+        assert_eq!(input.inject(1, &[1]), Err(InjectError::Full));
+        assert_eq!(input.inject(0, &[1]), Err(InjectError::Invalid));
+        assert_eq!(input.inject(2, &[1]), Err(InjectError::Invalid));
+    }
+}
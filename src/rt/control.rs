@@ -0,0 +1,120 @@
+//! Runtime control of real-time simulations.
+//!
+//! A [`Control`] handle is shared between the wait strategy and the rest of the application
+//! (e.g., the interrupt handler of a button or a UART command parser). It can pause and resume
+//! the real-time clock, and change the time scale in the middle of the simulation.
+//! Commands are applied by the wait strategy the next time it wakes up, and the timeline is
+//! rebased so the model never sees the paused interval nor any jump in the simulation time.
+//! Deferred actions (e.g., the end of a pulse) follow the wall clock, so they still run on time
+//! while the simulation is paused.
+//!
+//! As it only relies on atomics, it can be placed in a `static` and used from interrupt handlers.
+
+use portable_atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Shared handle for controlling a real-time simulation.
+#[derive(Debug)]
+pub struct Control {
+    /// It is set to `true` while the simulation is paused.
+    paused: AtomicBool,
+    /// Requested time scale (as `f64` bits). Zero if there is no pending request.
+    time_scale: AtomicU64,
+}
+
+impl Control {
+    /// Creates a new control handle. The simulation is not paused.
+    pub const fn new() -> Self {
+        Self {
+            paused: AtomicBool::new(false),
+            time_scale: AtomicU64::new(0),
+        }
+    }
+
+    /// Pauses the simulation.
+    #[inline]
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Release);
+    }
+
+    /// Resumes the simulation.
+    #[inline]
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Release);
+    }
+
+    /// Pauses the simulation if it is running, and resumes it otherwise.
+    /// It returns `true` if the simulation is paused now.
+    #[inline]
+    pub fn toggle(&self) -> bool {
+        !self.paused.fetch_xor(true, Ordering::AcqRel)
+    }
+
+    /// Returns `true` if the simulation is paused.
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    /// Requests a new time scale (i.e., wall-clock seconds per simulation time unit).
+    ///
+    /// # Panics
+    ///
+    /// It panics if `time_scale` is not positive and finite.
+    pub fn set_time_scale(&self, time_scale: f64) {
+        assert!(
+            time_scale > 0. && time_scale.is_finite(),
+            "time scale must be positive and finite"
+        );
+        self.time_scale
+            .store(time_scale.to_bits(), Ordering::Release);
+    }
+
+    /// Takes the pending time scale request (if any).
+    pub(crate) fn take_time_scale(&self) -> Option<f64> {
+        match self.time_scale.swap(0, Ordering::AcqRel) {
+            0 => None,
+            bits => Some(f64::from_bits(bits)),
+        }
+    }
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pause_and_resume() {
+        let control = Control::new();
+        assert!(!control.is_paused());
+        control.pause();
+        assert!(control.is_paused());
+        control.resume();
+        assert!(!control.is_paused());
+        assert!(control.toggle());
+        assert!(control.is_paused());
+        assert!(!control.toggle());
+        assert!(!control.is_paused());
+    }
+
+    #[test]
+    fn time_scale() {
+        let control = Control::new();
+        assert_eq!(control.take_time_scale(), None);
+        control.set_time_scale(2.);
+        control.set_time_scale(0.5);
+        assert_eq!(control.take_time_scale(), Some(0.5));
+        assert_eq!(control.take_time_scale(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "time scale must be positive and finite")]
+    fn invalid_time_scale() {
+        Control::new().set_time_scale(0.);
+    }
+}
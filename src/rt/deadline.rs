@@ -0,0 +1,185 @@
+//! Deadline-miss policies of real-time simulations.
+//!
+//! A simulation step misses its deadline when the wait strategy wakes up later than
+//! the maximum jitter allowed. What to do then depends on the application: during development,
+//! panicking is usually fine, but a board in the field should rather log, count, or react to the miss.
+//! Steps that start after their deadline because the previous one took too long are overruns
+//! (see [`super::time::Pacing`]): they are not checked here, but counted in [`super::jitter::JitterStats`].
+
+use xdevs::aux::Bag;
+
+/// Action to take when a simulation step misses its deadline.
+pub enum OnMiss<'a, T> {
+    /// Deadline misses are ignored.
+    Ignore,
+    /// Deadline misses are printed.
+    Log,
+    /// Deadline misses are counted in the given counter.
+    Count(&'a mut u64),
+    /// The given callback is called with the simulation time of the step and its jitter (in microseconds).
+    Callback(&'a mut dyn FnMut(f64, u64)),
+    /// The given closure injects an event into a designated input port of the model.
+    /// It receives the input bag of the model and the jitter of the step (in microseconds).
+    /// The event is processed by the model at the simulation time of the step.
+    Inject(&'a mut dyn FnMut(&mut T, u64)),
+    /// The simulation is stopped cleanly.
+    /// The simulator calls the `stop` method of every atomic model before returning.
+    Abort,
+    /// The execution panics.
+    Panic,
+}
+
+/// Deadline of the simulation steps and the action to take when it is missed.
+pub struct Deadline<'a, T> {
+    /// Maximum jitter allowed, in microseconds.
+    max_jitter_us: u64,
+    /// Action to take when the jitter of a step is greater than `max_jitter_us`.
+    on_miss: OnMiss<'a, T>,
+    /// It is set to `true` once the simulation is aborted.
+    aborted: bool,
+}
+
+impl<'a, T: Bag> Deadline<'a, T> {
+    /// Creates a new deadline policy.
+    pub fn new(max_jitter_us: u64, on_miss: OnMiss<'a, T>) -> Self {
+        Self {
+            max_jitter_us,
+            on_miss,
+            aborted: false,
+        }
+    }
+
+    /// Creates a deadline policy that never misses a deadline.
+    pub fn none() -> Self {
+        Self::new(u64::MAX, OnMiss::Ignore)
+    }
+
+    /// Returns the maximum jitter allowed, in microseconds.
+    #[inline]
+    pub fn max_jitter_us(&self) -> u64 {
+        self.max_jitter_us
+    }
+
+    /// Returns `true` if the simulation has been aborted due to a deadline miss.
+    #[inline]
+    pub fn is_aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Checks the jitter of a simulation step that was scheduled at `t_next`.
+    /// It returns the simulation time that the wait strategy must return.
+    ///
+    /// To abort the simulation, it clears the input bag and returns NaN.
+    /// NaN is never greater than or equal to the next internal event nor lower than the stop time,
+    /// so `simulate_rt` neither triggers a new transition nor continues with the simulation loop.
+    pub fn check(&mut self, t_next: f64, jitter_us: u64, input: &mut T) -> f64 {
+        if self.aborted {
+            input.clear();
+            return f64::NAN;
+        }
+        if jitter_us <= self.max_jitter_us {
+            return t_next;
+        }
+        match &mut self.on_miss {
+            OnMiss::Ignore => {}
+            OnMiss::Log => {
+                println!("deadline missed at t={}: jitter {} us", t_next, jitter_us);
+            }
+            OnMiss::Count(count) => **count += 1,
+            OnMiss::Callback(callback) => callback(t_next, jitter_us),
+            OnMiss::Inject(inject) => inject(input, jitter_us),
+            OnMiss::Abort => {
+                self.aborted = true;
+                input.clear();
+                return f64::NAN;
+            }
+            OnMiss::Panic => panic!("jitter is too high: {} us", jitter_us),
+        }
+        t_next
+    }
+}
+
+impl<'a, T: Bag> Default for Deadline<'a, T> {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rt::harness::Input;
+
+    #[test]
+    fn within_deadline() {
+        let mut input = Input::default();
+        let mut deadline = Deadline::new(100, OnMiss::Panic);
+        assert_eq!(deadline.check(1., 0, &mut input), 1.);
+        assert_eq!(deadline.check(2., 100, &mut input), 2.);
+        let mut deadline = Deadline::none();
+        assert_eq!(deadline.check(3., u64::MAX, &mut input), 3.);
+    }
+
+    #[test]
+    fn ignore_and_log() {
+        let mut input = Input::default();
+        let mut deadline = Deadline::new(100, OnMiss::Ignore);
+        assert_eq!(deadline.check(1., 101, &mut input), 1.);
+        let mut deadline = Deadline::new(100, OnMiss::Log);
+        assert_eq!(deadline.check(1., 101, &mut input), 1.);
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn count() {
+        let mut input = Input::default();
+        let mut misses = 0;
+        let mut deadline = Deadline::new(100, OnMiss::Count(&mut misses));
+        for (t, jitter) in [(1., 50), (2., 150), (3., 100), (4., 101)] {
+            assert_eq!(deadline.check(t, jitter, &mut input), t);
+        }
+        assert_eq!(misses, 2);
+    }
+
+    #[test]
+    fn callback() {
+        let mut input = Input::default();
+        let mut log = Vec::new();
+        let mut callback = |t, jitter| log.push((t, jitter));
+        let mut deadline = Deadline::new(100, OnMiss::Callback(&mut callback));
+        assert_eq!(deadline.check(1., 150, &mut input), 1.);
+        assert_eq!(deadline.check(2., 50, &mut input), 2.);
+        assert_eq!(log, [(1., 150)]);
+    }
+
+    #[test]
+    fn inject() {
+        let mut input = Input::default();
+        let mut inject = |input: &mut Input, jitter| input.0.push(jitter);
+        let mut deadline = Deadline::new(100, OnMiss::Inject(&mut inject));
+        assert_eq!(deadline.check(1., 150, &mut input), 1.);
+        assert_eq!(input.0, [150]);
+    }
+
+    #[test]
+    fn abort() {
+        let mut input = Input(vec![1, 2]);
+        let mut deadline = Deadline::new(100, OnMiss::Abort);
+        assert_eq!(deadline.check(1., 100, &mut input), 1.);
+        assert!(!deadline.is_aborted());
+        assert!(deadline.check(2., 101, &mut input).is_nan());
+        assert!(deadline.is_aborted());
+        assert!(input.is_empty());
+        // once aborted, every step is aborted
+        input.0.push(3);
+        assert!(deadline.check(3., 0, &mut input).is_nan());
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "jitter is too high: 101 us")]
+    fn panic() {
+        let mut deadline = Deadline::new(100, OnMiss::Panic);
+        deadline.check(1., 101, &mut Input::default());
+    }
+}
@@ -0,0 +1,337 @@
+//! Debouncing of digital inputs (e.g., mechanical buttons).
+//!
+//! The contacts of a button bounce for a few milliseconds when it is pressed or released,
+//! so a single press triggers a burst of edge interrupts. A [`Debouncer`] receives raw samples
+//! of the input level and only reports the changes that survive its filters:
+//!
+//! - A minimum interval between reported changes, for inputs that only change on interrupts.
+//! - An integrate-and-threshold filter, for inputs that are sampled periodically:
+//!   an integrator moves one unit towards every sample, and the debounced level only changes
+//!   once the integrator saturates.
+//!
+//! Edge interrupts must report both edges (or read the pin level in the interrupt handler),
+//! so the debouncer can tell a new press from the bounces of a release.
+//! Interrupt handlers can push the sampled levels into an [`EventQueue`],
+//! and [`drain`] debounces them before injecting them into the model.
+
+use super::queue::{Event, EventQueue};
+use super::time::{Rounding, TimeBase};
+use xdevs::aux::Bag;
+
+/// Edge of a digital input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    /// The input changed from low to high.
+    Rising,
+    /// The input changed from high to low.
+    Falling,
+}
+
+impl Edge {
+    /// Returns the level of the input after the edge.
+    #[inline]
+    pub const fn level(self) -> bool {
+        matches!(self, Self::Rising)
+    }
+}
+
+/// Edges reported by a [`Debouncer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edges {
+    /// Only rising edges are reported.
+    Rising,
+    /// Only falling edges are reported (e.g., presses of a button with a pull-up resistor).
+    Falling,
+    /// Both edges are reported.
+    Both,
+}
+
+impl Edges {
+    /// Returns `true` if `edge` must be reported.
+    #[inline]
+    pub const fn matches(self, edge: Edge) -> bool {
+        match self {
+            Self::Rising => matches!(edge, Edge::Rising),
+            Self::Falling => matches!(edge, Edge::Falling),
+            Self::Both => true,
+        }
+    }
+}
+
+/// Debouncer of a digital input.
+///
+/// By default, it does not filter anything: every change of the sampled level is a new edge.
+/// Filters are added with [`Debouncer::with_interval`] and [`Debouncer::with_integration`].
+/// Edges that do not match the selected [`Edges`] still change the debounced level.
+#[derive(Clone, Debug)]
+pub struct Debouncer {
+    /// Edges to report.
+    edges: Edges,
+    /// Minimum ticks between two changes of the debounced level.
+    min_ticks: u64,
+    /// Saturation value of the integrator.
+    threshold: u32,
+    /// Integrator of the samples, between `0` and `threshold`.
+    integrator: u32,
+    /// Debounced level of the input.
+    level: bool,
+    /// Tick of the last change of the debounced level that started a minimum interval (if any).
+    last: Option<u64>,
+}
+
+impl Debouncer {
+    /// Creates a new debouncer that reports the given edges. The input starts low.
+    pub const fn new(edges: Edges) -> Self {
+        Self {
+            edges,
+            min_ticks: 0,
+            threshold: 1,
+            integrator: 0,
+            level: false,
+            last: None,
+        }
+    }
+
+    /// Sets the initial level of the input (e.g., `true` for a button with a pull-up resistor).
+    pub const fn with_level(mut self, level: bool) -> Self {
+        self.level = level;
+        self.integrator = if level { self.threshold } else { 0 };
+        self
+    }
+
+    /// Ignores changes that come less than `min_us` microseconds after the previous change.
+    /// Ignored changes are not reported and do not restart the interval, but they still
+    /// update the debounced level, so a release within the interval is not mistaken for a held button.
+    pub fn with_interval(mut self, base: TimeBase, min_us: u64) -> Self {
+        self.min_ticks = base.us_to_ticks(min_us, Rounding::Ceil);
+        self
+    }
+
+    /// Only changes the debounced level after `threshold` consecutive samples with the new level
+    /// (bouncing samples move the integrator back and delay the change).
+    ///
+    /// # Panics
+    ///
+    /// It panics if `threshold` is zero.
+    pub fn with_integration(self, threshold: u32) -> Self {
+        assert!(
+            threshold > 0,
+            "integration threshold must be greater than zero"
+        );
+        let level = self.level;
+        Self { threshold, ..self }.with_level(level)
+    }
+
+    /// Returns the debounced level of the input.
+    #[inline]
+    pub fn level(&self) -> bool {
+        self.level
+    }
+
+    /// Feeds a sample of the input level taken at `tick`.
+    /// It returns the edge of the debounced input (if any, and only if it must be reported).
+    pub fn update(&mut self, tick: u64, level: bool) -> Option<Edge> {
+        self.integrator = match level {
+            true => u32::min(self.integrator + 1, self.threshold),
+            false => self.integrator.saturating_sub(1),
+        };
+        let edge = match (self.level, self.integrator) {
+            (false, i) if i == self.threshold => Edge::Rising,
+            (true, 0) => Edge::Falling,
+            _ => return None,
+        };
+        self.level = edge.level();
+        if matches!(self.last, Some(last) if tick.saturating_sub(last) < self.min_ticks) {
+            return None;
+        }
+        self.last = Some(tick);
+        match self.edges.matches(edge) {
+            true => Some(edge),
+            false => None,
+        }
+    }
+}
+
+/// Input handler that debounces the input levels sampled in `queue` and injects the resulting edges.
+/// Every debounced edge is passed to `inject`, which must add it to an input port and return `true`.
+/// If `inject` returns `false` (e.g., the port is full), the edge is kept for the next call.
+/// As [`super::queue::drain`], it returns the capture tick of the earliest injected edge (if any).
+pub fn drain<'a, T: Bag, const N: usize>(
+    queue: &'a EventQueue<bool, N>,
+    mut debouncer: Debouncer,
+    mut inject: impl FnMut(&mut T, &Event<Edge>) -> bool + 'a,
+) -> impl FnMut(&mut T) -> Option<u64> + 'a {
+    let mut pending: Option<Event<Edge>> = None;
+
+    move |input| -> Option<u64> {
+        let mut capture_tick: Option<u64> = None;
+        loop {
+            let event = match pending.take() {
+                Some(event) => event,
+                None => match queue.pop() {
+                    Some(sample) => match debouncer.update(sample.tick, sample.payload) {
+                        Some(edge) => Event {
+                            tick: sample.tick,
+                            payload: edge,
+                        },
+                        None => continue,
+                    },
+                    None => break,
+                },
+            };
+            if !inject(input, &event) {
+                pending = Some(event);
+                break;
+            }
+            capture_tick = Some(capture_tick.map_or(event.tick, |tick| tick.min(event.tick)));
+        }
+        capture_tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rt::harness::Input;
+
+    const BASE: TimeBase = TimeBase::new(1_000_000);
+
+    #[test]
+    fn edges() {
+        assert!(Edge::Rising.level());
+        assert!(!Edge::Falling.level());
+        assert!(Edges::Rising.matches(Edge::Rising));
+        assert!(!Edges::Rising.matches(Edge::Falling));
+        assert!(Edges::Falling.matches(Edge::Falling));
+        assert!(!Edges::Falling.matches(Edge::Rising));
+        assert!(Edges::Both.matches(Edge::Rising));
+        assert!(Edges::Both.matches(Edge::Falling));
+    }
+
+    #[test]
+    fn no_filter() {
+        let mut debouncer = Debouncer::new(Edges::Both);
+        assert_eq!(debouncer.update(0, false), None);
+        assert_eq!(debouncer.update(1, true), Some(Edge::Rising));
+        assert_eq!(debouncer.update(2, true), None);
+        assert_eq!(debouncer.update(3, false), Some(Edge::Falling));
+    }
+
+    #[test]
+    fn interval() {
+        // button with a pull-up resistor: presses are falling edges
+        let mut debouncer = Debouncer::new(Edges::Falling)
+            .with_level(true)
+            .with_interval(BASE, 10_000);
+        assert!(debouncer.level());
+        // the press bounces for a few microseconds
+        assert_eq!(debouncer.update(1_000, false), Some(Edge::Falling));
+        assert_eq!(debouncer.update(1_100, true), None);
+        assert_eq!(debouncer.update(1_200, false), None);
+        assert!(!debouncer.level());
+        // the bounces of the release are not new presses
+        assert_eq!(debouncer.update(50_000, true), None);
+        assert_eq!(debouncer.update(50_100, false), None);
+        assert_eq!(debouncer.update(50_200, true), None);
+        assert!(debouncer.level());
+        assert_eq!(debouncer.update(60_200, false), Some(Edge::Falling));
+    }
+
+    #[test]
+    fn integration() {
+        let mut debouncer = Debouncer::new(Edges::Both).with_integration(3);
+        assert_eq!(debouncer.update(0, true), None);
+        assert_eq!(debouncer.update(1, true), None);
+        // a bouncing sample moves the integrator back
+        assert_eq!(debouncer.update(2, false), None);
+        assert_eq!(debouncer.update(3, true), None);
+        assert_eq!(debouncer.update(4, true), Some(Edge::Rising));
+        // the integrator saturates
+        assert_eq!(debouncer.update(5, true), None);
+        assert_eq!(debouncer.update(6, false), None);
+        assert_eq!(debouncer.update(7, false), None);
+        assert_eq!(debouncer.update(8, false), Some(Edge::Falling));
+
+        // the initial level also sets the integrator
+        let mut debouncer = Debouncer::new(Edges::Both)
+            .with_integration(2)
+            .with_level(true);
+        assert_eq!(debouncer.update(0, false), None);
+        assert_eq!(debouncer.update(1, false), Some(Edge::Falling));
+    }
+
+    #[test]
+    fn integration_and_interval() {
+        let mut debouncer = Debouncer::new(Edges::Both)
+            .with_interval(BASE, 10)
+            .with_integration(2);
+        assert_eq!(debouncer.update(0, true), None);
+        assert_eq!(debouncer.update(1, true), Some(Edge::Rising));
+        assert_eq!(debouncer.update(2, false), None);
+        // the integrator is saturated, but the change comes too soon
+        assert_eq!(debouncer.update(3, false), None);
+        assert!(!debouncer.level());
+        assert_eq!(debouncer.update(11, false), None);
+        assert_eq!(debouncer.update(12, true), None);
+        assert_eq!(debouncer.update(13, true), Some(Edge::Rising));
+    }
+
+    #[test]
+    fn release_within_interval() {
+        // presses closer than 1 second are bounces
+        let mut debouncer = Debouncer::new(Edges::Falling)
+            .with_level(true)
+            .with_interval(BASE, 1_000_000);
+        assert_eq!(debouncer.update(0, false), Some(Edge::Falling));
+        // a short press is released within the interval...
+        assert_eq!(debouncer.update(300_000, true), None);
+        assert!(debouncer.level());
+        // ... so the next press is not mistaken for a held button
+        assert_eq!(debouncer.update(5_000_000, false), Some(Edge::Falling));
+        assert_eq!(debouncer.update(5_300_000, true), None);
+        assert_eq!(debouncer.update(10_000_000, false), Some(Edge::Falling));
+    }
+
+    #[test]
+    #[should_panic(expected = "integration threshold must be greater than zero")]
+    fn zero_threshold() {
+        Debouncer::new(Edges::Both).with_integration(0);
+    }
+
+    #[test]
+    fn drain_debounced_edges() {
+        let queue = EventQueue::<bool, 8>::new();
+        let debouncer = Debouncer::new(Edges::Falling)
+            .with_level(true)
+            .with_interval(BASE, 10_000);
+        // the port can only hold one value
+        let mut handler = drain(&queue, debouncer, |input: &mut Input, event| {
+            input.0.is_empty() && {
+                input.0.push(event.tick);
+                true
+            }
+        });
+        let mut input = Input::default();
+        assert_eq!(handler(&mut input), None);
+        for (tick, level) in [(100, false), (200, true), (300, false), (20_000, true)] {
+            queue.push(tick, level).unwrap();
+        }
+        // bounces are not injected
+        assert_eq!(handler(&mut input), Some(100));
+        assert_eq!(input.0, [100]);
+        assert!(queue.is_empty());
+
+        for (tick, level) in [(40_000, false), (60_000, true), (80_000, false)] {
+            queue.push(tick, level).unwrap();
+        }
+        assert_eq!(handler(&mut input), None);
+        input.clear();
+        // the second press is kept until the port is cleared
+        assert_eq!(handler(&mut input), Some(40_000));
+        assert_eq!(input.0, [40_000]);
+        assert_eq!(handler(&mut input), None);
+        input.clear();
+        assert_eq!(handler(&mut input), Some(80_000));
+        assert_eq!(input.0, [80_000]);
+    }
+}
@@ -0,0 +1,423 @@
+//! Deferred formatting of log messages.
+//!
+//! Formatting messages on the board is slow and large (e.g., `{:.2}` pulls in soft-float
+//! formatting code). With the `log-deferred` feature, the leveled macros of [`super::log`]
+//! do not format anything: their format strings are interned in the `.xdevs_log` section
+//! of the ELF file (which is not loaded into the board), and every message is sent as a
+//! [`Record`] with the address of its format string and its raw arguments.
+//! The `xdevs-log` tool of the workspace reads the format strings from the ELF file
+//! and formats the records on the host.
+//!
+//! Records are framed as the frames of [`super::frame`], but they start with their own [`KIND`],
+//! so decoders of each format reject the packets of the other one. They are written to the log
+//! buffer if the `log-buffer` feature is enabled, or directly to UART0 otherwise:
+//!
+//! ```text
+//! 0x00 | COBS(kind: u8 | format: u32 LE | level: u8 | tag: str | args: [arg] | crc: u16 LE) | 0x00
+//! str := len: u8 | [u8]
+//! arg := type: u8 | value (little endian, see `Value`)
+//! ```
+//!
+//! Arguments must be primitive types or `&str` (see [`Arg`]), and the format strings only
+//! support positional arguments. The section is placed by the `xdevs-log.x` linker script.
+
+use super::codec::PortCodec;
+use super::frame::{cobs_decode, cobs_encode, crc16, FrameError};
+use super::log::Level;
+
+/// Name of the section of the interned format strings.
+pub const SECTION: &str = ".xdevs_log";
+/// First byte of every record, which tells it apart from the frames of [`super::frame`].
+pub const KIND: u8 = b'L';
+/// Maximum length of a record before COBS encoding, including its kind and its CRC.
+/// Arguments that do not fit are dropped.
+pub const MAX_RECORD: usize = 64;
+/// Maximum length of an encoded record, including the COBS overhead and the delimiters.
+pub const MAX_ENCODED: usize = MAX_RECORD + MAX_RECORD / 254 + 3;
+
+/// Returns the bytes of `fmt` followed by a zero byte, to be interned in [`SECTION`].
+/// `N` must be `fmt.len() + 1`.
+pub const fn intern<const N: usize>(fmt: &str) -> [u8; N] {
+    let bytes = fmt.as_bytes();
+    let mut out = [0; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        out[i] = bytes[i];
+        i += 1;
+    }
+    out
+}
+
+/// Type tags of the arguments.
+mod tag {
+    pub const BOOL: u8 = 0;
+    pub const U8: u8 = 1;
+    pub const U16: u8 = 2;
+    pub const U32: u8 = 3;
+    pub const U64: u8 = 4;
+    pub const I8: u8 = 5;
+    pub const I16: u8 = 6;
+    pub const I32: u8 = 7;
+    pub const I64: u8 = 8;
+    pub const F32: u8 = 9;
+    pub const F64: u8 = 10;
+    pub const STR: u8 = 11;
+}
+
+/// Argument of a deferred log message.
+pub trait Arg {
+    /// Encodes the type tag and the value of the argument at the beginning of `buf`.
+    /// It returns the number of bytes written, or `None` if it does not fit.
+    fn encode(&self, buf: &mut [u8]) -> Option<usize>;
+}
+
+/// Implements [`Arg`] for primitive types with their type tag, encoded with [`PortCodec`].
+macro_rules! impl_arg {
+    ($($ty:ty => $tag:expr),+ $(,)?) => {
+        $(
+            impl Arg for $ty {
+                fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+                    let (tag, value) = buf.split_first_mut()?;
+                    *tag = $tag;
+                    PortCodec::encode(self, value).ok().map(|len| len + 1)
+                }
+            }
+        )+
+    };
+}
+
+impl_arg!(
+    bool => tag::BOOL,
+    u8 => tag::U8,
+    u16 => tag::U16,
+    u32 => tag::U32,
+    u64 => tag::U64,
+    usize => tag::U64,
+    i8 => tag::I8,
+    i16 => tag::I16,
+    i32 => tag::I32,
+    i64 => tag::I64,
+    isize => tag::I64,
+    f32 => tag::F32,
+    f64 => tag::F64,
+);
+
+impl Arg for str {
+    /// Strings that do not fit are truncated.
+    fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        let (tag, buf) = buf.split_first_mut()?;
+        *tag = tag::STR;
+        encode_str(self, buf).map(|len| len + 1)
+    }
+}
+
+impl<T: Arg + ?Sized> Arg for &T {
+    fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        (**self).encode(buf)
+    }
+}
+
+/// Encodes a string (truncated to fit `buf`) with its length.
+fn encode_str(s: &str, buf: &mut [u8]) -> Option<usize> {
+    let (len, buf) = buf.split_first_mut()?;
+    let mut n = s.len().min(buf.len()).min(u8::MAX as usize);
+    while !s.is_char_boundary(n) {
+        n -= 1;
+    }
+    buf[..n].copy_from_slice(&s.as_bytes()[..n]);
+    *len = n as u8;
+    Some(n + 1)
+}
+
+/// Decodes a string encoded with [`encode_str`].
+/// It returns the string and the number of bytes read.
+fn decode_str(buf: &[u8]) -> Option<(&str, usize)> {
+    let (&len, buf) = buf.split_first()?;
+    let s = core::str::from_utf8(buf.get(..len as usize)?).ok()?;
+    Some((s, len as usize + 1))
+}
+
+/// Decoded argument of a deferred log message.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value<'a> {
+    /// Boolean.
+    Bool(bool),
+    /// Unsigned integer (of any width).
+    Unsigned(u64),
+    /// Signed integer (of any width).
+    Signed(i64),
+    /// Single-precision float.
+    F32(f32),
+    /// Double-precision float.
+    F64(f64),
+    /// String.
+    Str(&'a str),
+}
+
+impl<'a> Value<'a> {
+    /// Decodes an argument from the beginning of `buf`.
+    /// It returns the value and the number of bytes read, or `None` if it is invalid.
+    fn decode(buf: &'a [u8]) -> Option<(Self, usize)> {
+        /// Decodes a number with [`PortCodec`] and maps it to a value.
+        fn number<'a, T: PortCodec>(
+            buf: &[u8],
+            map: impl FnOnce(T) -> Value<'a>,
+        ) -> Option<(Value<'a>, usize)> {
+            T::decode(buf).ok().map(|(value, len)| (map(value), len))
+        }
+
+        let (&tag, buf) = buf.split_first()?;
+        let (value, len) = match tag {
+            tag::BOOL => number(buf, Value::Bool),
+            tag::U8 => number(buf, |v: u8| Value::Unsigned(v.into())),
+            tag::U16 => number(buf, |v: u16| Value::Unsigned(v.into())),
+            tag::U32 => number(buf, |v: u32| Value::Unsigned(v.into())),
+            tag::U64 => number(buf, Value::Unsigned),
+            tag::I8 => number(buf, |v: i8| Value::Signed(v.into())),
+            tag::I16 => number(buf, |v: i16| Value::Signed(v.into())),
+            tag::I32 => number(buf, |v: i32| Value::Signed(v.into())),
+            tag::I64 => number(buf, Value::Signed),
+            tag::F32 => number(buf, Value::F32),
+            tag::F64 => number(buf, Value::F64),
+            tag::STR => decode_str(buf).map(|(s, len)| (Value::Str(s), len)),
+            _ => None,
+        }?;
+        Some((value, len + 1))
+    }
+}
+
+/// Deferred log message: the address of its format string, its level, the tag of its
+/// component, and its arguments.
+#[derive(Clone, Debug)]
+pub struct Record {
+    /// Contents of the record (without its kind and its CRC).
+    buf: [u8; MAX_RECORD - 3],
+    /// Length of the contents.
+    len: usize,
+    /// It is set to `true` once an argument does not fit.
+    truncated: bool,
+}
+
+impl Record {
+    /// Creates a new record without arguments.
+    pub fn new(format: u32, level: Level, tag: &str) -> Self {
+        let mut record = Self {
+            buf: [0; MAX_RECORD - 3],
+            len: 5,
+            truncated: false,
+        };
+        record.buf[..4].copy_from_slice(&format.to_le_bytes());
+        record.buf[4] = level as u8;
+        // tags are short, so they always fit
+        record.len += encode_str(tag, &mut record.buf[5..]).unwrap_or_default();
+        record
+    }
+
+    /// Appends an argument. Once an argument does not fit, it and the following ones are dropped.
+    pub fn arg<A: Arg + ?Sized>(&mut self, arg: &A) {
+        if self.truncated {
+            return;
+        }
+        match arg.encode(&mut self.buf[self.len..]) {
+            Some(len) => self.len += len,
+            None => self.truncated = true,
+        }
+    }
+
+    /// Returns the address of the format string.
+    pub fn format(&self) -> u32 {
+        let mut format = [0; 4];
+        format.copy_from_slice(&self.buf[..4]);
+        u32::from_le_bytes(format)
+    }
+
+    /// Returns the level of the message (if valid).
+    pub fn level(&self) -> Option<Level> {
+        match self.buf[4] {
+            0 => Some(Level::Error),
+            1 => Some(Level::Warn),
+            2 => Some(Level::Info),
+            3 => Some(Level::Debug),
+            4 => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    /// Returns the tag of the component (or an empty string if it is invalid).
+    pub fn tag(&self) -> &str {
+        decode_str(&self.buf[5..self.len]).map_or("", |(tag, _)| tag)
+    }
+
+    /// Returns an iterator over the arguments. It stops at the first invalid argument.
+    pub fn args(&self) -> impl Iterator<Item = Value<'_>> {
+        let tag_len = self.buf[5] as usize + 1;
+        let mut buf = &self.buf[5 + tag_len.min(self.len - 5)..self.len];
+        core::iter::from_fn(move || {
+            let (value, len) = Value::decode(buf)?;
+            buf = &buf[len..];
+            Some(value)
+        })
+    }
+
+    /// Encodes the record into `out`, including the delimiters.
+    /// It returns the number of bytes written.
+    pub fn encode(&self, out: &mut [u8; MAX_ENCODED]) -> usize {
+        let mut raw = [0; MAX_RECORD];
+        let len = self.len + 1;
+        raw[0] = KIND;
+        raw[1..len].copy_from_slice(&self.buf[..self.len]);
+        let crc = crc16(&raw[..len]);
+        raw[len..len + 2].copy_from_slice(&crc.to_le_bytes());
+        out[0] = 0;
+        let n = cobs_encode(&raw[..len + 2], &mut out[1..]);
+        out[n + 1] = 0;
+        n + 2
+    }
+
+    /// Decodes a record from its COBS encoding (without the delimiters).
+    pub fn decode(encoded: &[u8]) -> Result<Self, FrameError> {
+        let mut raw = [0; MAX_RECORD];
+        let len = cobs_decode(encoded, &mut raw)?;
+        let len = len
+            .checked_sub(2)
+            .filter(|&len| len > 6)
+            .ok_or(FrameError::Short)?;
+        if u16::from_le_bytes([raw[len], raw[len + 1]]) != crc16(&raw[..len]) {
+            return Err(FrameError::Crc);
+        }
+        if raw[0] != KIND {
+            return Err(FrameError::Kind);
+        }
+        let mut record = Self {
+            buf: [0; MAX_RECORD - 3],
+            len: len - 1,
+            truncated: false,
+        };
+        record.buf[..len - 1].copy_from_slice(&raw[1..len]);
+        Ok(record)
+    }
+}
+
+/// Sends a record through the log buffer (with the `log-buffer` feature) or UART0.
+/// It is called by [`crate::log`] with the `log-deferred` feature.
+#[cfg(all(target_arch = "riscv32", feature = "log-deferred"))]
+pub fn write(record: &Record) {
+    let mut buf = [0; MAX_ENCODED];
+    let len = record.encode(&mut buf);
+    #[cfg(feature = "log-buffer")]
+    super::log::write(&buf[..len]);
+    // without the `log-buffer` feature, log records own the transmitter of UART0
+    #[cfg(not(feature = "log-buffer"))]
+    unsafe {
+        super::uart::Uart::Uart0.write(&buf[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a record and decodes it back.
+    fn round_trip(record: &Record) -> Record {
+        let mut buf = [0; MAX_ENCODED];
+        let len = record.encode(&mut buf);
+        assert_eq!((buf[0], buf[len - 1]), (0, 0));
+        assert!(!buf[1..len - 1].contains(&0));
+        Record::decode(&buf[1..len - 1]).unwrap()
+    }
+
+    #[test]
+    fn interned_strings() {
+        const FMT: [u8; 10] = intern("job {} ok");
+        assert_eq!(&FMT, b"job {} ok\0");
+    }
+
+    #[test]
+    fn records() {
+        let mut record = Record::new(0x1234, Level::Info, "T");
+        record.arg(&0.5f64);
+        record.arg(&42usize);
+        record.arg(&-3i8);
+        record.arg(&true);
+        record.arg("idle");
+        let record = round_trip(&record);
+        assert_eq!(record.format(), 0x1234);
+        assert_eq!(record.level(), Some(Level::Info));
+        assert_eq!(record.tag(), "T");
+        let args: Vec<_> = record.args().collect();
+        assert_eq!(
+            args,
+            [
+                Value::F64(0.5),
+                Value::Unsigned(42),
+                Value::Signed(-3),
+                Value::Bool(true),
+                Value::Str("idle"),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_records() {
+        let mut record = Record::new(1, Level::Debug, "P");
+        for i in 0..10u64 {
+            record.arg(&i);
+        }
+        // the arguments that fit are kept in order
+        let record = round_trip(&record);
+        let args: Vec<_> = record.args().collect();
+        assert_eq!(args, (0..6).map(Value::Unsigned).collect::<Vec<_>>());
+
+        // strings are truncated instead
+        let mut record = Record::new(1, Level::Debug, "P");
+        record.arg(&"x".repeat(100)[..]);
+        let record = round_trip(&record);
+        assert!(matches!(record.args().next(), Some(Value::Str(s)) if s.len() == 52));
+        // at a character boundary
+        let mut record = Record::new(1, Level::Debug, "P");
+        record.arg(&format!("x{}", "é".repeat(50))[..]);
+        let record = round_trip(&record);
+        assert!(matches!(record.args().next(), Some(Value::Str(s)) if s.len() == 51));
+    }
+
+    #[test]
+    fn invalid_records() {
+        let mut buf = [0; MAX_ENCODED];
+        let len = Record::new(1, Level::Warn, "rt").encode(&mut buf);
+        let i = buf.iter().position(|&b| b == b'r').unwrap();
+        buf[i] = b's';
+        assert_eq!(
+            Record::decode(&buf[1..len - 1]).err(),
+            Some(FrameError::Crc)
+        );
+        assert_eq!(
+            Record::decode(&[0x03, 0x01, 0x02]).err(),
+            Some(FrameError::Short)
+        );
+    }
+
+    #[test]
+    fn mixed_stream() {
+        use crate::rt::frame::{self, Frame};
+
+        let mut stream = Vec::new();
+        let mut record = Record::new(7, Level::Info, "T");
+        record.arg(&0.5f64);
+        let mut buf = [0; MAX_ENCODED];
+        let len = record.encode(&mut buf);
+        stream.extend_from_slice(&buf[..len]);
+        let mut buf = [0; frame::MAX_ENCODED];
+        let len = Frame::new(1, 100, &[42]).unwrap().encode(&mut buf);
+        stream.extend_from_slice(&buf[..len]);
+
+        // both formats share the framing, but each decoder rejects the packets of the other one
+        let packets: Vec<_> = (stream.split(|&b| b == 0))
+            .filter(|packet| !packet.is_empty())
+            .collect();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(Record::decode(packets[0]).map(|r| r.format()), Ok(7));
+        assert_eq!(Frame::decode(packets[0]), Err(FrameError::Kind));
+        assert_eq!(Record::decode(packets[1]).err(), Some(FrameError::Kind));
+        assert_eq!(Frame::decode(packets[1]), Frame::new(1, 100, &[42]));
+    }
+}
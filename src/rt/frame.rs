@@ -0,0 +1,591 @@
+//! Binary framed protocol for port values.
+//!
+//! Every [`Frame`] carries the value of one port: its port ID, the clock tick at which it was
+//! sent, and its encoded value (up to [`MAX_VALUE`] bytes). On the wire, the frame starts with
+//! its [`KIND`] and it is followed by a CRC-16/CCITT-FALSE of its contents, and the result is
+//! COBS-encoded and delimited with zero bytes on both sides, so receivers resynchronize after
+//! a lost byte (or after text sent through the same UART, e.g., logs). The kind tells frames
+//! apart from the deferred log records of [`super::defer`], which share the same framing:
+//!
+//! ```text
+//! 0x00 | COBS(kind: u8 | port: u8 | tick: u64 LE | value: [u8] | crc: u16 LE) | 0x00
+//! ```
+//!
+//! [`handler`] streams the output events of `simulate_rt` to a host, and [`FramedInputs`]
+//! injects the frames sent by the host into the input bag, as [`super::uart::SerialInputs`]
+//! does with text lines. The `xdevs-frames` crate of the workspace decodes them on the host.
+
+use super::clock::Clock;
+use super::queue::{Event, EventQueue};
+use super::uart::{InjectError, Rx};
+use xdevs::aux::Bag;
+
+#[cfg(target_arch = "riscv32")]
+use super::{clock::Timestamp, uart::Uart};
+#[cfg(target_arch = "riscv32")]
+use hifive1::hal::e310x::Priority;
+
+/// First byte of every frame, which tells it apart from other packets with the same framing.
+pub const KIND: u8 = b'F';
+/// Maximum length of an encoded value, in bytes.
+pub const MAX_VALUE: usize = 32;
+/// Length of the header of a frame (kind, port ID, and tick), in bytes.
+const HEADER: usize = 10;
+/// Maximum length of a frame before COBS encoding, including its CRC.
+pub const MAX_FRAME: usize = HEADER + MAX_VALUE + 2;
+/// Maximum length of an encoded frame, including the COBS overhead and the delimiters.
+pub const MAX_ENCODED: usize = MAX_FRAME + MAX_FRAME / 254 + 3;
+/// Maximum number of ports of a [`FramedInputs`].
+pub const MAX_PORTS: usize = 8;
+
+/// Error of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// The value (or the encoded frame) is too long.
+    TooLong,
+    /// The COBS encoding is invalid.
+    Cobs,
+    /// The frame is shorter than its header and CRC.
+    Short,
+    /// The CRC does not match the contents of the frame.
+    Crc,
+    /// Bytes of the frame were lost before reaching the decoder.
+    Lost,
+    /// The packet is of another kind (e.g., a log record instead of a frame).
+    Kind,
+}
+
+/// Returns the CRC-16/CCITT-FALSE of `data`.
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| match crc & 0x8000 {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0x1021,
+        })
+    })
+}
+
+/// Value of a port with its port ID and timestamp.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// ID of the port.
+    pub port: u8,
+    /// Tick of the clock at which the frame was sent.
+    pub tick: u64,
+    /// Length of the encoded value.
+    len: usize,
+    /// Encoded value.
+    value: [u8; MAX_VALUE],
+}
+
+impl Frame {
+    /// Creates a new frame.
+    /// It returns [`FrameError::TooLong`] if `value` is longer than [`MAX_VALUE`].
+    pub fn new(port: u8, tick: u64, value: &[u8]) -> Result<Self, FrameError> {
+        let mut frame = Self {
+            port,
+            tick,
+            len: value.len(),
+            value: [0; MAX_VALUE],
+        };
+        (frame.value.get_mut(..value.len()))
+            .ok_or(FrameError::TooLong)?
+            .copy_from_slice(value);
+        Ok(frame)
+    }
+
+    /// Returns the encoded value.
+    #[inline]
+    pub fn value(&self) -> &[u8] {
+        &self.value[..self.len]
+    }
+
+    /// Encodes the frame into `out`, including the delimiters.
+    /// It returns the number of bytes written.
+    pub fn encode(&self, out: &mut [u8; MAX_ENCODED]) -> usize {
+        let mut raw = [0; MAX_FRAME];
+        raw[0] = KIND;
+        raw[1] = self.port;
+        raw[2..HEADER].copy_from_slice(&self.tick.to_le_bytes());
+        raw[HEADER..HEADER + self.len].copy_from_slice(self.value());
+        let len = HEADER + self.len;
+        let crc = crc16(&raw[..len]);
+        raw[len..len + 2].copy_from_slice(&crc.to_le_bytes());
+        out[0] = 0;
+        let n = cobs_encode(&raw[..len + 2], &mut out[1..]);
+        out[n + 1] = 0;
+        n + 2
+    }
+
+    /// Decodes a frame from its COBS encoding (without the delimiters).
+    pub fn decode(encoded: &[u8]) -> Result<Self, FrameError> {
+        let mut raw = [0; MAX_FRAME];
+        let len = cobs_decode(encoded, &mut raw)?;
+        let len = len.checked_sub(HEADER + 2).ok_or(FrameError::Short)? + HEADER;
+        let crc = u16::from_le_bytes([raw[len], raw[len + 1]]);
+        if crc != crc16(&raw[..len]) {
+            return Err(FrameError::Crc);
+        }
+        if raw[0] != KIND {
+            return Err(FrameError::Kind);
+        }
+        let mut tick = [0; 8];
+        tick.copy_from_slice(&raw[2..HEADER]);
+        Self::new(raw[1], u64::from_le_bytes(tick), &raw[HEADER..len])
+    }
+}
+
+/// COBS-encodes `data` into `out` (without the delimiter) and returns the encoded length.
+/// `out` must be at least `data.len() + data.len() / 254 + 1` bytes long.
+pub(crate) fn cobs_encode(data: &[u8], out: &mut [u8]) -> usize {
+    let mut code_index = 0;
+    let mut code = 1;
+    let mut n = 1;
+    for &byte in data {
+        if byte != 0 {
+            out[n] = byte;
+            n += 1;
+            code += 1;
+        }
+        if byte == 0 || code == 0xFF {
+            out[code_index] = code;
+            code_index = n;
+            n += 1;
+            code = 1;
+        }
+    }
+    out[code_index] = code;
+    n
+}
+
+/// Decodes the COBS encoding `encoded` (without the delimiter) into `out`
+/// and returns the decoded length.
+pub(crate) fn cobs_decode(encoded: &[u8], out: &mut [u8]) -> Result<usize, FrameError> {
+    let mut n = 0;
+    let mut i = 0;
+    while i < encoded.len() {
+        let code = encoded[i] as usize;
+        let block = encoded.get(i + 1..i + code).ok_or(FrameError::Cobs)?;
+        (out.get_mut(n..n + block.len()))
+            .ok_or(FrameError::TooLong)?
+            .copy_from_slice(block);
+        n += block.len();
+        i += code;
+        if code < 0xFF && i < encoded.len() {
+            *out.get_mut(n).ok_or(FrameError::TooLong)? = 0;
+            n += 1;
+        }
+    }
+    Ok(n)
+}
+
+/// Incremental decoder of a stream of encoded frames.
+#[derive(Clone, Debug)]
+pub struct Decoder {
+    /// Bytes of the frame being received.
+    buf: [u8; MAX_ENCODED],
+    /// Number of bytes of the frame being received.
+    len: usize,
+    /// It is set to `true` if the frame being received is too long.
+    overflow: bool,
+    /// It is set to `true` if bytes of the frame being received were lost.
+    lost: bool,
+}
+
+impl Decoder {
+    /// Creates a new decoder.
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_ENCODED],
+            len: 0,
+            overflow: false,
+            lost: false,
+        }
+    }
+
+    /// Tells the decoder that bytes were lost (e.g., see [`Rx::Lost`]).
+    /// The frame being received is rejected with [`FrameError::Lost`] at its delimiter.
+    pub fn lose(&mut self) {
+        self.lost = true;
+    }
+
+    /// Feeds a received byte.
+    /// It returns the decoded frame (or its error) when the byte is a delimiter.
+    /// Consecutive delimiters are ignored.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Frame, FrameError>> {
+        if byte != 0 {
+            match self.buf.get_mut(self.len) {
+                Some(slot) => {
+                    *slot = byte;
+                    self.len += 1;
+                }
+                None => self.overflow = true,
+            }
+            return None;
+        }
+        let result = match (self.len, self.overflow, self.lost) {
+            (_, _, true) => Some(Err(FrameError::Lost)),
+            (0, false, false) => None,
+            (_, true, false) => Some(Err(FrameError::TooLong)),
+            (len, false, false) => Some(Frame::decode(&self.buf[..len])),
+        };
+        self.len = 0;
+        self.overflow = false;
+        self.lost = false;
+        result
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Output handler that sends the values of the output bag as frames.
+///
+/// For every output event, `ports` is called with an emitter that must receive
+/// the ID and the encoded value of every value in the bag (e.g., [`super::codec::ports`]).
+/// Frames are timestamped with `clock` and passed to `write` (e.g., `Uart::write` on the board).
+/// Values longer than [`MAX_VALUE`] are logged and dropped.
+pub fn handler<'a, T: Bag, C: Clock + 'a>(
+    mut clock: C,
+    mut ports: impl FnMut(&T, &mut dyn FnMut(u8, &[u8])) + 'a,
+    mut write: impl FnMut(&[u8]) + 'a,
+) -> impl FnMut(&T) + 'a {
+    move |output| {
+        if output.is_empty() {
+            return;
+        }
+        let tick = clock.now();
+        ports(
+            output,
+            &mut |port, value| match Frame::new(port, tick, value) {
+                Ok(frame) => {
+                    let mut buf = [0; MAX_ENCODED];
+                    let len = frame.encode(&mut buf);
+                    write(&buf[..len]);
+                }
+                Err(_) => {
+                    crate::warn!(super::log::RT, "value of port {} is too long", port);
+                }
+            },
+        );
+    }
+}
+
+/// Closure that injects the encoded value of a frame into the input bag.
+pub type Inject<'a, T> = &'a mut dyn FnMut(&mut T, &[u8]) -> Result<(), InjectError>;
+
+/// Builder of the mapping between port IDs and the input ports of the model.
+pub struct FramedInputs<'a, T> {
+    /// Injections of the ports, indexed by their ID.
+    ports: [Option<Inject<'a, T>>; MAX_PORTS],
+}
+
+impl<'a, T: Bag> FramedInputs<'a, T> {
+    /// Creates a new builder with no ports.
+    pub fn new() -> Self {
+        Self {
+            ports: Default::default(),
+        }
+    }
+
+    /// Maps a port ID to an injection.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `id` is not less than [`MAX_PORTS`] or if it is already mapped.
+    pub fn port(mut self, id: u8, inject: Inject<'a, T>) -> Self {
+        let port = self
+            .ports
+            .get_mut(id as usize)
+            .expect("frame port out of range");
+        assert!(port.is_none(), "frame port already mapped");
+        *port = Some(inject);
+        self
+    }
+
+    /// Returns an input handler that decodes the frames in `queue` and injects their values.
+    /// Invalid frames (and frames of unknown ports) are logged and dropped.
+    /// If a port is full, the frame is kept for the next call.
+    /// As [`super::queue::drain`], it returns the capture tick of the earliest injected frame
+    /// (if any), which is the tick at which its delimiter was received.
+    /// The timestamps of the frames are set by the host, so they are ignored.
+    pub fn drain<const N: usize>(
+        mut self,
+        queue: &'a EventQueue<Rx, N>,
+    ) -> impl FnMut(&mut T) -> Option<u64> + 'a
+    where
+        T: 'a,
+    {
+        let mut decoder = Decoder::new();
+        let mut pending: Option<(u64, Frame)> = None;
+
+        move |input| -> Option<u64> {
+            let mut capture_tick: Option<u64> = None;
+            loop {
+                let (tick, frame) = match pending.take() {
+                    Some(pending) => pending,
+                    None => match queue.pop() {
+                        Some(Event {
+                            payload: Rx::Lost, ..
+                        }) => {
+                            decoder.lose();
+                            continue;
+                        }
+                        Some(Event {
+                            tick,
+                            payload: Rx::Byte(byte),
+                        }) => match decoder.push(byte) {
+                            Some(Ok(frame)) => (tick, frame),
+                            Some(Err(error)) => {
+                                crate::warn!(super::log::RT, "invalid frame: {:?}", error);
+                                continue;
+                            }
+                            None => continue,
+                        },
+                        None => break,
+                    },
+                };
+                let inject = self
+                    .ports
+                    .get_mut(frame.port as usize)
+                    .and_then(Option::as_mut);
+                match inject.map(|inject| inject(input, frame.value())) {
+                    Some(Ok(())) => {
+                        capture_tick = Some(capture_tick.map_or(tick, |t| t.min(tick)));
+                    }
+                    Some(Err(InjectError::Full)) => {
+                        pending = Some((tick, frame));
+                        break;
+                    }
+                    Some(Err(InjectError::Invalid)) | None => {
+                        crate::warn!(
+                            super::log::RT,
+                            "invalid value for frame port {}",
+                            frame.port
+                        );
+                    }
+                }
+            }
+            capture_tick
+        }
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+impl<'a, T: Bag + 'a> FramedInputs<'a, T> {
+    /// Enables the receiver of `uart` and its interrupt, with the given PLIC priority.
+    /// Received bytes are timestamped with `clock`, which must be the clock of the wait strategy.
+    /// It returns the input handler of the wait strategy (see [`Uart::listen`]).
+    ///
+    /// # Safety
+    ///
+    /// The caller must uphold the contract of [`Uart::listen`].
+    pub unsafe fn enable<C: Timestamp>(
+        self,
+        uart: Uart,
+        priority: Priority,
+        clock: &C,
+    ) -> impl FnMut(&mut T) -> Option<u64> + 'a {
+        self.drain(uart.listen(priority, clock))
+    }
+}
+
+impl<'a, T: Bag> Default for FramedInputs<'a, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rt::clock::MockClock;
+    use crate::rt::harness::Input;
+    use core::cell::RefCell;
+
+    /// Encodes a frame into a vector.
+    fn encode(port: u8, tick: u64, value: &[u8]) -> Vec<u8> {
+        let mut buf = [0; MAX_ENCODED];
+        let len = Frame::new(port, tick, value).unwrap().encode(&mut buf);
+        buf[..len].to_vec()
+    }
+
+    /// Injection of a `u64` value (little endian) into a port that can hold two values.
+    fn inject_u64(input: &mut Input, value: &[u8]) -> Result<(), InjectError> {
+        let mut bytes = [0; 8];
+        match value.len() {
+            8 => bytes.copy_from_slice(value),
+            _ => return Err(InjectError::Invalid),
+        }
+        if input.0.len() == 2 {
+            return Err(InjectError::Full);
+        }
+        input.0.push(u64::from_le_bytes(bytes));
+        Ok(())
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn cobs() {
+        let cases: [(&[u8], &[u8]); 5] = [
+            (&[], &[0x01]),
+            (&[0x00], &[0x01, 0x01]),
+            (&[0x00, 0x00], &[0x01, 0x01, 0x01]),
+            (&[0x11, 0x22, 0x00, 0x33], &[0x03, 0x11, 0x22, 0x02, 0x33]),
+            (&[0x11, 0x00, 0x00, 0x00], &[0x02, 0x11, 0x01, 0x01, 0x01]),
+        ];
+        for (data, encoded) in cases {
+            let mut out = [0; 16];
+            let n = cobs_encode(data, &mut out);
+            assert_eq!(&out[..n], encoded);
+            let n = cobs_decode(encoded, &mut out).unwrap();
+            assert_eq!(&out[..n], data);
+        }
+        // long blocks are split
+        let data: Vec<u8> = (1..=255).collect();
+        let mut out = [0; 300];
+        let n = cobs_encode(&data, &mut out);
+        assert_eq!((n, out[0], out[255]), (257, 0xFF, 0x02));
+        let mut decoded = [0; 300];
+        let m = cobs_decode(&out[..n], &mut decoded).unwrap();
+        assert_eq!(&decoded[..m], &data[..]);
+        assert_eq!(
+            cobs_decode(&[0x05, 0x11], &mut decoded),
+            Err(FrameError::Cobs)
+        );
+    }
+
+    #[test]
+    fn frames() {
+        let encoded = encode(3, 0x0102, &[0, 42]);
+        let (first, last) = (encoded[0], encoded[encoded.len() - 1]);
+        let encoded = &encoded[1..encoded.len() - 1];
+        assert_eq!((first, last), (0, 0));
+        assert!(!encoded.contains(&0));
+        let frame = Frame::decode(encoded).unwrap();
+        assert_eq!(
+            (frame.port, frame.tick, frame.value()),
+            (3, 0x0102, &[0, 42][..])
+        );
+
+        assert_eq!(
+            Frame::new(0, 0, &[0; MAX_VALUE + 1]),
+            Err(FrameError::TooLong)
+        );
+        assert_eq!(encode(0, u64::MAX, &[0xFF; MAX_VALUE]).len(), MAX_ENCODED);
+        assert_eq!(Frame::decode(&[0x02, 0x01]), Err(FrameError::Short));
+        let mut corrupted = encode(3, 0x0102, &[0, 42]);
+        corrupted[2] ^= 0x10;
+        assert_eq!(
+            Frame::decode(&corrupted[1..corrupted.len() - 1]),
+            Err(FrameError::Crc)
+        );
+    }
+
+    #[test]
+    fn decoder() {
+        let mut decoder = Decoder::new();
+        let mut stream = vec![0x42];
+        stream.extend(encode(1, 10, &[7]));
+        stream.extend(encode(2, 20, &[]));
+        stream.extend([0xFF; MAX_ENCODED + 1]);
+        stream.push(0);
+        let results: Vec<_> = stream.into_iter().filter_map(|b| decoder.push(b)).collect();
+        assert_eq!(results.len(), 4);
+        // garbage before the first frame is dropped (frames start with a delimiter)
+        assert!(results[0].is_err());
+        assert_eq!(results[1], Frame::new(1, 10, &[7]));
+        assert_eq!(results[2], Frame::new(2, 20, &[]));
+        assert_eq!(results[3], Err(FrameError::TooLong));
+    }
+
+    #[test]
+    fn output_frames() {
+        let written = RefCell::new(Vec::new());
+        let mut clock = MockClock::new(1_000_000);
+        clock.set(500);
+        let mut handler = handler(
+            clock,
+            |output: &Input, emit| {
+                for value in &output.0 {
+                    emit(4, &value.to_le_bytes());
+                }
+            },
+            |bytes| written.borrow_mut().extend_from_slice(bytes),
+        );
+        handler(&Input::default());
+        assert!(written.borrow().is_empty());
+        handler(&Input(vec![1, 2]));
+        let mut decoder = Decoder::new();
+        let frames: Vec<_> = (written.borrow().iter())
+            .filter_map(|&b| decoder.push(b))
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                Frame::new(4, 500, &1u64.to_le_bytes()),
+                Frame::new(4, 500, &2u64.to_le_bytes()),
+            ]
+        );
+    }
+
+    #[test]
+    fn drain_frames() {
+        let queue = EventQueue::<Rx, 128>::new();
+        let send = |tick, port, value: u64| {
+            for byte in encode(port, 0, &value.to_le_bytes()) {
+                queue.push(tick, Rx::Byte(byte)).unwrap();
+            }
+        };
+        let mut job = inject_u64;
+        let mut handler = FramedInputs::new().port(1, &mut job).drain(&queue);
+        let mut input = Input::default();
+        assert_eq!(handler(&mut input), None);
+
+        // frames of unknown ports are dropped
+        send(10, 2, 5);
+        send(20, 1, 42);
+        assert_eq!(handler(&mut input), Some(20));
+        assert_eq!(input.0, [42]);
+        input.clear();
+
+        // frames are kept while their port is full
+        send(30, 1, 1);
+        send(40, 1, 2);
+        send(50, 1, 3);
+        assert_eq!(handler(&mut input), Some(30));
+        assert_eq!(input.0, [1, 2]);
+        input.clear();
+        assert_eq!(handler(&mut input), Some(50));
+        assert_eq!(input.0, [3]);
+        input.clear();
+
+        // frames with lost bytes are dropped
+        let frame = encode(1, 0, &7u64.to_le_bytes());
+        for (i, &byte) in frame.iter().enumerate() {
+            if i == 4 {
+                queue.push(60, Rx::Lost).unwrap();
+            } else {
+                queue.push(60, Rx::Byte(byte)).unwrap();
+            }
+        }
+        send(70, 1, 8);
+        assert_eq!(handler(&mut input), Some(70));
+        assert_eq!(input.0, [8]);
+    }
+
+    #[test]
+    #[should_panic(expected = "frame port out of range")]
+    fn port_out_of_range() {
+        let mut job = inject_u64;
+        let _ = FramedInputs::new().port(MAX_PORTS as u8, &mut job);
+    }
+}
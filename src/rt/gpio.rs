@@ -0,0 +1,376 @@
+//! Bridge between GPIO interrupts and the input ports of the model.
+//!
+//! [`GpioInputs`] maps GPIO pins to injections into the input bag. Every pin has its own
+//! [`Debouncer`], which selects the edges to inject (rising, falling, or both).
+//! On the board, [`GpioInputs::enable`] configures the GPIO interrupts of every pin
+//! (on both edges, so the debouncer can track the level of the pin) and the PLIC,
+//! and it returns the input handler of the wait strategy. The `GPIOn` interrupt handlers
+//! only timestamp the level of the pins with the clock of the wait strategy,
+//! and they are generated with [`crate::gpio_handlers`].
+//! Thus, every pin takes two steps: mapping it with [`GpioInputs::pin`] and listing its
+//! `GPIOn` handler in [`crate::gpio_handlers`]. Otherwise, the interrupt of the pin has no handler:
+//!
+//! ```ignore
+//! riscv_xdevs::gpio_handlers!(GPIO9, GPIO10);
+//!
+//! let inputs = GpioInputs::new()
+//!     .pin(9, Debouncer::new(Edges::Falling).with_interval(base, 20_000), &mut on_start)
+//!     .pin(10, Debouncer::new(Edges::Falling).with_interval(base, 20_000), &mut on_stop);
+//! // machine interrupts are enabled later, by the wait strategy
+//! let handler = unsafe { inputs.enable(Priority::P2, &clock) };
+//! ```
+
+use super::debounce::{Debouncer, Edge};
+use super::queue::{Event, EventQueue};
+use xdevs::aux::Bag;
+
+#[cfg(target_arch = "riscv32")]
+use super::{clock::Timestamp, queue::CaptureClock};
+#[cfg(target_arch = "riscv32")]
+use hifive1::hal::e310x::{Interrupt, Priority, GPIO0, PLIC};
+
+/// Number of pins of the GPIO block.
+pub const N_PINS: usize = 32;
+
+/// Level of a GPIO pin captured by an interrupt handler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinLevel {
+    /// Number of the pin.
+    pub pin: u8,
+    /// Level of the pin.
+    pub level: bool,
+}
+
+/// Closure that injects a debounced edge of a pin into the input bag.
+/// It must return `true` if the edge was injected.
+pub type Inject<'a, T> = &'a mut dyn FnMut(&mut T, &Event<Edge>) -> bool;
+
+/// Mapping of a pin to an injection.
+struct Binding<'a, T> {
+    /// Debouncer of the pin.
+    debouncer: Debouncer,
+    /// Injection of the debounced edges.
+    inject: Inject<'a, T>,
+}
+
+/// Builder of the mapping between GPIO pins and the input ports of the model.
+pub struct GpioInputs<'a, T> {
+    /// Bindings of the pins, indexed by pin number.
+    bindings: [Option<Binding<'a, T>>; N_PINS],
+}
+
+impl<'a, T: Bag> GpioInputs<'a, T> {
+    /// Creates a new builder with no pins.
+    pub fn new() -> Self {
+        Self {
+            bindings: Default::default(),
+        }
+    }
+
+    /// Maps a pin to an injection. The debouncer selects the edges to inject.
+    ///
+    /// # Panics
+    ///
+    /// It panics if the pin does not exist or if it is already mapped.
+    pub fn pin(mut self, pin: u8, debouncer: Debouncer, inject: Inject<'a, T>) -> Self {
+        let binding = self
+            .bindings
+            .get_mut(pin as usize)
+            .expect("GPIO pin out of range");
+        assert!(binding.is_none(), "GPIO pin already mapped");
+        *binding = Some(Binding { debouncer, inject });
+        self
+    }
+
+    /// Returns a bit mask with the mapped pins.
+    pub fn mask(&self) -> u32 {
+        (self.bindings.iter().enumerate())
+            .filter(|(_, binding)| binding.is_some())
+            .fold(0, |mask, (pin, _)| mask | 1 << pin)
+    }
+
+    /// Sets the initial level of the mapped pins from a bit mask of levels.
+    pub fn with_levels(mut self, levels: u32) -> Self {
+        for (pin, binding) in self.bindings.iter_mut().enumerate() {
+            if let Some(binding) = binding {
+                let debouncer = binding.debouncer.clone();
+                binding.debouncer = debouncer.with_level(levels & 1 << pin != 0);
+            }
+        }
+        self
+    }
+
+    /// Returns an input handler that debounces the pin levels in `queue`
+    /// and injects the resulting edges. Levels of unmapped pins are ignored.
+    /// As [`super::queue::drain`], if an injection returns `false`, the edge is kept for the next call,
+    /// and the input handler returns the capture tick of the earliest injected edge (if any).
+    /// Levels that did not fit in `queue` are logged as a warning.
+    pub fn drain<const N: usize>(
+        mut self,
+        queue: &'a EventQueue<PinLevel, N>,
+    ) -> impl FnMut(&mut T) -> Option<u64> + 'a
+    where
+        T: 'a,
+    {
+        let mut pending: Option<(usize, Event<Edge>)> = None;
+        let mut dropped = queue.dropped();
+
+        move |input| -> Option<u64> {
+            if queue.dropped() != dropped {
+                let n = queue.dropped() - dropped;
+                crate::warn!(super::log::RT, "{} GPIO levels dropped (queue full)", n);
+                dropped = queue.dropped();
+            }
+            let mut capture_tick: Option<u64> = None;
+            loop {
+                let (pin, event) = match pending.take() {
+                    Some(pending) => pending,
+                    None => match queue.pop() {
+                        Some(sample) => {
+                            let pin = sample.payload.pin as usize;
+                            let edge = match self.bindings.get_mut(pin) {
+                                Some(Some(binding)) => {
+                                    binding.debouncer.update(sample.tick, sample.payload.level)
+                                }
+                                _ => None,
+                            };
+                            match edge {
+                                Some(edge) => (
+                                    pin,
+                                    Event {
+                                        tick: sample.tick,
+                                        payload: edge,
+                                    },
+                                ),
+                                None => continue,
+                            }
+                        }
+                        None => break,
+                    },
+                };
+                let binding = self.bindings[pin].as_mut().unwrap();
+                if !(binding.inject)(input, &event) {
+                    pending = Some((pin, event));
+                    break;
+                }
+                capture_tick = Some(capture_tick.map_or(event.tick, |tick| tick.min(event.tick)));
+            }
+            capture_tick
+        }
+    }
+}
+
+impl<'a, T: Bag> Default for GpioInputs<'a, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pin levels captured by the `GPIOn` interrupt handlers.
+#[cfg(target_arch = "riscv32")]
+static LEVELS: EventQueue<PinLevel, 32> = EventQueue::new();
+/// Clock that timestamps the pin levels (i.e., the clock of the wait strategy).
+#[cfg(target_arch = "riscv32")]
+static CLOCK: CaptureClock = CaptureClock::new();
+
+/// PLIC interrupt sources of the GPIO pins.
+#[cfg(target_arch = "riscv32")]
+const INTERRUPTS: [Interrupt; N_PINS] = [
+    Interrupt::GPIO0,
+    Interrupt::GPIO1,
+    Interrupt::GPIO2,
+    Interrupt::GPIO3,
+    Interrupt::GPIO4,
+    Interrupt::GPIO5,
+    Interrupt::GPIO6,
+    Interrupt::GPIO7,
+    Interrupt::GPIO8,
+    Interrupt::GPIO9,
+    Interrupt::GPIO10,
+    Interrupt::GPIO11,
+    Interrupt::GPIO12,
+    Interrupt::GPIO13,
+    Interrupt::GPIO14,
+    Interrupt::GPIO15,
+    Interrupt::GPIO16,
+    Interrupt::GPIO17,
+    Interrupt::GPIO18,
+    Interrupt::GPIO19,
+    Interrupt::GPIO20,
+    Interrupt::GPIO21,
+    Interrupt::GPIO22,
+    Interrupt::GPIO23,
+    Interrupt::GPIO24,
+    Interrupt::GPIO25,
+    Interrupt::GPIO26,
+    Interrupt::GPIO27,
+    Interrupt::GPIO28,
+    Interrupt::GPIO29,
+    Interrupt::GPIO30,
+    Interrupt::GPIO31,
+];
+
+#[cfg(target_arch = "riscv32")]
+impl<'a, T: Bag + 'a> GpioInputs<'a, T> {
+    /// Enables the interrupts of the mapped pins on both edges, with the given PLIC priority.
+    /// Pin levels are timestamped with `clock`, which must be the clock of the wait strategy.
+    /// The initial level of every debouncer is read from the pins.
+    /// It returns the input handler of the wait strategy.
+    ///
+    /// Pins must already be configured as inputs, and the `GPIOn` interrupt handlers of the mapped pins
+    /// must be generated with [`crate::gpio_handlers`]. Machine interrupts are not enabled.
+    ///
+    /// # Safety
+    ///
+    /// The caller must own the interrupts of the mapped pins: no other code (e.g., a HAL GPIO driver)
+    /// may configure them. The interrupt enable registers of the GPIO block are read, modified,
+    /// and written back, so it must be called before machine interrupts are enabled.
+    pub unsafe fn enable<C: Timestamp>(
+        self,
+        priority: Priority,
+        _clock: &C,
+    ) -> impl FnMut(&mut T) -> Option<u64> + 'a {
+        CLOCK.set(C::timestamp);
+        let mask = self.mask();
+        let gpio_block = &*GPIO0::ptr();
+        gpio_block.fall_ie.modify(|r, w| w.bits(r.bits() | mask));
+        gpio_block.rise_ie.modify(|r, w| w.bits(r.bits() | mask));
+        // clear pending interrupts from previous states
+        gpio_block.fall_ip.write(|w| w.bits(mask));
+        gpio_block.rise_ip.write(|w| w.bits(mask));
+        let levels = gpio_block.input_val.read().bits();
+        let ctx = PLIC::ctx0();
+        for (pin, &interrupt) in INTERRUPTS.iter().enumerate() {
+            if mask & 1 << pin != 0 {
+                PLIC::priorities().set_priority(interrupt, priority);
+                ctx.enables().enable(interrupt);
+            }
+        }
+        PLIC::enable();
+        self.with_levels(levels).drain(&LEVELS)
+    }
+}
+
+/// Returns the number of pin levels dropped because the queue of the interrupt handlers was full.
+#[cfg(target_arch = "riscv32")]
+pub fn dropped() -> usize {
+    LEVELS.dropped()
+}
+
+/// Timestamps the level of every GPIO pin with a pending interrupt and clears the interrupt.
+/// It is called from the `GPIOn` interrupt handlers generated with [`crate::gpio_handlers`].
+#[cfg(target_arch = "riscv32")]
+pub fn dispatch() {
+    // the clock is set before the interrupts are enabled
+    let tick = CLOCK.now().unwrap_or_default();
+    let (pending, levels) = unsafe {
+        let gpio_block = &*GPIO0::ptr();
+        let pending = gpio_block.fall_ip.read().bits() | gpio_block.rise_ip.read().bits();
+        let pending =
+            pending & (gpio_block.fall_ie.read().bits() | gpio_block.rise_ie.read().bits());
+        gpio_block.fall_ip.write(|w| w.bits(pending));
+        gpio_block.rise_ip.write(|w| w.bits(pending));
+        (pending, gpio_block.input_val.read().bits())
+    };
+    for pin in 0..N_PINS as u8 {
+        if pending & 1 << pin != 0 {
+            let level = levels & 1 << pin != 0;
+            // dropped levels are counted by the queue (see `dropped`)
+            LEVELS.push(tick, PinLevel { pin, level }).ok();
+        }
+    }
+}
+
+/// Generates the given `GPIOn` interrupt handlers, which forward the pin levels to [`GpioInputs`].
+/// Every pin mapped with [`GpioInputs::pin`] needs its handler here.
+///
+/// ```ignore
+/// riscv_xdevs::gpio_handlers!(GPIO9, GPIO10);
+/// ```
+#[macro_export]
+macro_rules! gpio_handlers {
+    ($($handler:ident),+ $(,)?) => {
+        $(
+            #[no_mangle]
+            #[allow(non_snake_case)]
+            fn $handler() {
+                $crate::rt::gpio::dispatch();
+            }
+        )+
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rt::debounce::Edges;
+    use crate::rt::harness::Input;
+
+    #[test]
+    fn mask() {
+        let mut start = |_: &mut Input, _: &Event<Edge>| true;
+        let mut stop = |_: &mut Input, _: &Event<Edge>| true;
+        let inputs = GpioInputs::new()
+            .pin(9, Debouncer::new(Edges::Falling), &mut start)
+            .pin(31, Debouncer::new(Edges::Both), &mut stop);
+        assert_eq!(inputs.mask(), 1 << 9 | 1 << 31);
+        assert_eq!(GpioInputs::<Input>::default().mask(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "GPIO pin out of range")]
+    fn pin_out_of_range() {
+        let mut inject = |_: &mut Input, _: &Event<Edge>| true;
+        let _ = GpioInputs::new().pin(32, Debouncer::new(Edges::Both), &mut inject);
+    }
+
+    #[test]
+    #[should_panic(expected = "GPIO pin already mapped")]
+    fn pin_already_mapped() {
+        let mut start = |_: &mut Input, _: &Event<Edge>| true;
+        let mut stop = |_: &mut Input, _: &Event<Edge>| true;
+        let _ = GpioInputs::new()
+            .pin(9, Debouncer::new(Edges::Both), &mut start)
+            .pin(9, Debouncer::new(Edges::Both), &mut stop);
+    }
+
+    #[test]
+    fn drain_pins() {
+        let queue = EventQueue::<PinLevel, 8>::new();
+        let push = |tick, pin, level| queue.push(tick, PinLevel { pin, level }).unwrap();
+        // pin 9 injects presses (falling edges), and pin 10 injects every edge
+        let mut start = |input: &mut Input, event: &Event<Edge>| {
+            input.0.len() < 2 && {
+                input.0.push(event.tick);
+                true
+            }
+        };
+        let mut stop = |input: &mut Input, event: &Event<Edge>| {
+            input.0.push(1_000 + event.tick);
+            true
+        };
+        let mut handler = GpioInputs::new()
+            .pin(9, Debouncer::new(Edges::Falling), &mut start)
+            .pin(10, Debouncer::new(Edges::Both), &mut stop)
+            .with_levels(1 << 9)
+            .drain(&queue);
+        let mut input = Input::default();
+        assert_eq!(handler(&mut input), None);
+
+        push(10, 9, false);
+        push(20, 10, true);
+        push(30, 3, true); // unmapped pins are ignored
+        push(40, 9, true);
+        assert_eq!(handler(&mut input), Some(10));
+        assert_eq!(input.0, [10, 1_020]);
+
+        // the port of pin 9 is full, so the press is kept for the next call
+        push(50, 9, false);
+        push(60, 10, false);
+        assert_eq!(handler(&mut input), None);
+        input.clear();
+        assert_eq!(handler(&mut input), Some(50));
+        assert_eq!(input.0, [50, 1_060]);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,96 @@
+//! Host-side test harness for the real-time wait strategies.
+//!
+//! Together with [`super::clock::MockClock`] and its scripted [`super::clock::Interrupts`],
+//! it allows exercising the wait strategies with `cargo test` on the host.
+
+use super::clock::{Alarm, Clock, Interrupts, MockClock};
+use super::output::Deferred;
+use core::cell::RefCell;
+use xdevs::aux::Bag;
+
+/// Input bag for testing.
+#[derive(Debug, Default)]
+pub struct Input(pub Vec<u64>);
+
+impl Bag for Input {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.0.clear()
+    }
+}
+
+/// Input handler that injects the number of scripted interrupts fired since the last call.
+/// Spurious wake-ups (i.e., no interrupt fired) do not inject any event.
+pub fn on_interrupt<'a>(interrupts: &'a Interrupts<'a>) -> impl FnMut(&mut Input) -> bool + 'a {
+    move |input| match interrupts.take() {
+        0 => false,
+        n => {
+            input.0.push(n as u64);
+            true
+        }
+    }
+}
+
+/// Deferred actions that record the tick at which they run.
+#[derive(Debug, Default)]
+pub struct Actions {
+    /// Ticks of the pending actions.
+    pub pending: RefCell<Vec<u64>>,
+    /// Ticks at which the actions were run.
+    pub done: RefCell<Vec<u64>>,
+}
+
+impl Actions {
+    /// Creates new deferred actions due at the given ticks.
+    pub fn new(ticks: &[u64]) -> Self {
+        Self {
+            pending: RefCell::new(ticks.to_vec()),
+            done: RefCell::default(),
+        }
+    }
+}
+
+impl Deferred for Actions {
+    fn next_tick(&self) -> Option<u64> {
+        self.pending.borrow().iter().copied().min()
+    }
+
+    fn run(&self, tick: u64) {
+        let mut pending = self.pending.borrow_mut();
+        for _ in pending.iter().filter(|&&due| due <= tick) {
+            self.done.borrow_mut().push(tick);
+        }
+        pending.retain(|&due| due > tick);
+    }
+}
+
+/// Mock clock shared between a wait strategy and the test,
+/// so the test can advance it between steps (e.g., to emulate slow transitions).
+pub struct Shared<'a, 'b>(pub &'a RefCell<MockClock<'b>>);
+
+impl<'a, 'b> Clock for Shared<'a, 'b> {
+    fn now(&mut self) -> u64 {
+        self.0.borrow_mut().now()
+    }
+
+    fn freq(&self) -> u64 {
+        self.0.borrow().freq()
+    }
+}
+
+impl<'a, 'b> Alarm for Shared<'a, 'b> {
+    fn arm(&mut self, tick: u64) {
+        self.0.borrow_mut().arm(tick)
+    }
+
+    fn disarm(&mut self) {
+        self.0.borrow_mut().disarm()
+    }
+
+    fn wait_for_interrupt(&mut self) {
+        self.0.borrow_mut().wait_for_interrupt()
+    }
+}
@@ -0,0 +1,601 @@
+//! Jitter statistics of real-time simulations.
+//!
+//! Printing the jitter of every simulation step over UART perturbs the timing of the simulation.
+//! Instead, the wait strategies record the jitter of every step in a [`JitterStats`] collector,
+//! which only lives in RAM and can be printed on demand (e.g., at the end of the simulation).
+//! It also keeps track of overruns, i.e., steps that started after their deadline
+//! because the previous one took too long (see [`super::time::Pacing`]),
+//! and of the time that the simulator spent computing every step.
+//! Thus, it tells apart the jitter due to the wake-up latency from slow transitions.
+//!
+//! [`JitterStats`] only keeps aggregates. To inspect individual steps after the simulation,
+//! the wait strategies can also report the [`StepTiming`] of every step (see
+//! [`super::WaitOptions::with_steps`]), e.g., to keep the most recent ones in a [`StepLog`].
+
+use core::fmt;
+
+/// Number of buckets of the histograms.
+pub const N_BUCKETS: usize = 16;
+
+/// Statistics of a series of samples, in microseconds.
+///
+/// It keeps track of the minimum, maximum, mean, and standard deviation of the samples,
+/// as well as a histogram with [`N_BUCKETS`] buckets of fixed width.
+/// The last bucket also counts all the samples beyond the histogram range.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Samples {
+    /// Width of the histogram buckets, in microseconds.
+    bucket_us: u64,
+    /// Number of recorded samples.
+    count: u64,
+    /// Minimum sample, in microseconds.
+    min: u64,
+    /// Maximum sample, in microseconds.
+    max: u64,
+    /// Sum of all the samples.
+    sum: u64,
+    /// Sum of the squares of all the samples.
+    sum_sq: u128,
+    /// Histogram of the samples.
+    buckets: [u32; N_BUCKETS],
+}
+
+impl Samples {
+    /// Creates a new, empty series with histogram buckets of `bucket_us` microseconds.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `bucket_us` is zero.
+    pub const fn new(bucket_us: u64) -> Self {
+        assert!(bucket_us > 0, "bucket width must be greater than zero");
+        Self {
+            bucket_us,
+            count: 0,
+            min: u64::MAX,
+            max: 0,
+            sum: 0,
+            sum_sq: 0,
+            buckets: [0; N_BUCKETS],
+        }
+    }
+
+    /// Records a sample, in microseconds.
+    pub fn record(&mut self, us: u64) {
+        self.count += 1;
+        self.min = u64::min(self.min, us);
+        self.max = u64::max(self.max, us);
+        self.sum = self.sum.saturating_add(us);
+        self.sum_sq = self.sum_sq.saturating_add(us as u128 * us as u128);
+        let bucket = usize::min((us / self.bucket_us) as usize, N_BUCKETS - 1);
+        self.buckets[bucket] = self.buckets[bucket].saturating_add(1);
+    }
+
+    /// Discards all the recorded samples.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.bucket_us);
+    }
+
+    /// Returns the number of recorded samples.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the minimum sample, in microseconds.
+    #[inline]
+    pub fn min_us(&self) -> Option<u64> {
+        (self.count > 0).then(|| self.min)
+    }
+
+    /// Returns the maximum sample (i.e., the worst case), in microseconds.
+    #[inline]
+    pub fn max_us(&self) -> Option<u64> {
+        (self.count > 0).then(|| self.max)
+    }
+
+    /// Returns the mean of the samples, in microseconds.
+    pub fn mean_us(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
+
+    /// Returns the (population) standard deviation of the samples, in microseconds.
+    pub fn stddev_us(&self) -> Option<f64> {
+        let mean = self.mean_us()?;
+        let variance = self.sum_sq as f64 / self.count as f64 - mean * mean;
+        Some(sqrt(f64::max(variance, 0.)))
+    }
+
+    /// Returns the width of the histogram buckets, in microseconds.
+    #[inline]
+    pub fn bucket_us(&self) -> u64 {
+        self.bucket_us
+    }
+
+    /// Returns the histogram of the samples.
+    /// Bucket `i` counts the samples in `[i * bucket_us, (i + 1) * bucket_us)`,
+    /// except for the last one, which counts all the samples from `(N_BUCKETS - 1) * bucket_us` on.
+    #[inline]
+    pub fn histogram(&self) -> &[u32; N_BUCKETS] {
+        &self.buckets
+    }
+
+    /// Writes a one-line summary of the samples.
+    fn fmt_summary(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (
+            self.min_us(),
+            self.max_us(),
+            self.mean_us(),
+            self.stddev_us(),
+        ) {
+            (Some(min), Some(max), Some(mean), Some(stddev)) => {
+                let (mean, stddev) = (Centi::new(mean), Centi::new(stddev));
+                write!(
+                    f,
+                    "{} samples, min {} us, max {} us, mean {} us, stddev {} us",
+                    self.count, min, max, mean, stddev
+                )
+            }
+            _ => write!(f, "no samples"),
+        }
+    }
+}
+
+impl fmt::Display for Samples {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_summary(f)?;
+        if self.count == 0 {
+            return Ok(());
+        }
+        for (i, &n) in self.buckets.iter().enumerate() {
+            let from = i as u64 * self.bucket_us;
+            if i < N_BUCKETS - 1 {
+                write!(f, "\n  [{}, {}) us: {}", from, from + self.bucket_us, n)?;
+            } else {
+                write!(f, "\n  [{}, inf) us: {}", from, n)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Non-negative value rounded to two decimals, which is printed as integers.
+/// Float formatting would link its code into the board even with the `log-deferred` feature.
+struct Centi(u64);
+
+impl Centi {
+    /// Rounds a non-negative value to two decimals.
+    fn new(value: f64) -> Self {
+        Self((value * 100. + 0.5) as u64)
+    }
+}
+
+impl fmt::Display for Centi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}", self.0 / 100, self.0 % 100)
+    }
+}
+
+/// Jitter statistics collector.
+///
+/// The jitter of every step is recorded in a [`Samples`] series, which is printed with its histogram.
+/// The time that the simulator spent computing every step (i.e., from the moment the wait strategy
+/// returned until it was called again) is recorded in a separate series with the same bucket width,
+/// and only its summary is printed. Overruns and the drift they caused are only printed if there was any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JitterStats {
+    /// Jitter of the simulation steps.
+    jitter: Samples,
+    /// Computation time of the simulation steps.
+    compute: Samples,
+    /// Number of overruns.
+    overruns: u64,
+    /// Accumulated drift of the timeline due to overruns, in microseconds.
+    drift_us: u64,
+}
+
+impl JitterStats {
+    /// Creates a new, empty collector with histogram buckets of `bucket_us` microseconds.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `bucket_us` is zero.
+    pub const fn new(bucket_us: u64) -> Self {
+        Self {
+            jitter: Samples::new(bucket_us),
+            compute: Samples::new(bucket_us),
+            overruns: 0,
+            drift_us: 0,
+        }
+    }
+
+    /// Records the jitter of a simulation step, in microseconds.
+    #[inline]
+    pub fn record(&mut self, jitter_us: u64) {
+        self.jitter.record(jitter_us);
+    }
+
+    /// Records the time that the simulator spent computing a simulation step, in microseconds.
+    #[inline]
+    pub fn record_compute(&mut self, compute_us: u64) {
+        self.compute.record(compute_us);
+    }
+
+    /// Records an overrun. `drift_us` is how much the timeline slipped due to it, in microseconds.
+    pub fn record_overrun(&mut self, drift_us: u64) {
+        self.overruns += 1;
+        self.drift_us = self.drift_us.saturating_add(drift_us);
+    }
+
+    /// Discards all the recorded samples.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.bucket_us());
+    }
+
+    /// Returns the jitter of the simulation steps.
+    #[inline]
+    pub fn jitter(&self) -> &Samples {
+        &self.jitter
+    }
+
+    /// Returns the computation time of the simulation steps.
+    #[inline]
+    pub fn compute(&self) -> &Samples {
+        &self.compute
+    }
+
+    /// Returns the number of recorded jitter samples.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.jitter.count()
+    }
+
+    /// Returns the minimum jitter, in microseconds.
+    #[inline]
+    pub fn min_us(&self) -> Option<u64> {
+        self.jitter.min_us()
+    }
+
+    /// Returns the maximum jitter, in microseconds.
+    #[inline]
+    pub fn max_us(&self) -> Option<u64> {
+        self.jitter.max_us()
+    }
+
+    /// Returns the mean jitter, in microseconds.
+    #[inline]
+    pub fn mean_us(&self) -> Option<f64> {
+        self.jitter.mean_us()
+    }
+
+    /// Returns the (population) standard deviation of the jitter, in microseconds.
+    #[inline]
+    pub fn stddev_us(&self) -> Option<f64> {
+        self.jitter.stddev_us()
+    }
+
+    /// Returns the number of overruns.
+    #[inline]
+    pub fn overruns(&self) -> u64 {
+        self.overruns
+    }
+
+    /// Returns the accumulated drift of the timeline due to overruns, in microseconds.
+    #[inline]
+    pub fn drift_us(&self) -> u64 {
+        self.drift_us
+    }
+
+    /// Returns the width of the histogram buckets, in microseconds.
+    #[inline]
+    pub fn bucket_us(&self) -> u64 {
+        self.jitter.bucket_us()
+    }
+
+    /// Returns the histogram of the jitter (see [`Samples::histogram`]).
+    #[inline]
+    pub fn histogram(&self) -> &[u32; N_BUCKETS] {
+        self.jitter.histogram()
+    }
+}
+
+impl fmt::Display for JitterStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "jitter: {}", self.jitter)?;
+        if self.compute.count() > 0 {
+            write!(f, "\ncompute: ")?;
+            self.compute.fmt_summary(f)?;
+        }
+        if self.overruns > 0 {
+            write!(
+                f,
+                "\n  overruns: {}, drift: {} us",
+                self.overruns, self.drift_us
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Timing of a simulation step (i.e., of a call to the wait strategy).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StepTiming {
+    /// Number of the step, starting from 0.
+    pub step: u64,
+    /// Jitter of the step, in microseconds.
+    /// It is `None` if the wait strategy returned early due to an external event,
+    /// or if the step overran its deadline.
+    pub jitter_us: Option<u64>,
+    /// Time that the simulator spent computing the previous step (i.e., since the wait strategy
+    /// returned for the last time), in microseconds. It is `None` for the first step.
+    /// Thus, a late step comes with the computation that delayed it.
+    pub compute_us: Option<u64>,
+    /// Whether the step started after its deadline (see [`Pacing`](super::time::Pacing)).
+    /// Overrun steps are counted apart from the jitter samples.
+    pub overrun: bool,
+}
+
+impl fmt::Display for StepTiming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "step {}: ", self.step)?;
+        match (self.jitter_us, self.overrun) {
+            (Some(jitter), _) => write!(f, "jitter {} us", jitter)?,
+            (None, true) => write!(f, "overrun")?,
+            (None, false) => write!(f, "early")?,
+        }
+        if let Some(compute) = self.compute_us {
+            write!(f, ", compute {} us", compute)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fixed-capacity log of the timing of the last `N` simulation steps.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepLog<const N: usize> {
+    /// Storage of the steps.
+    steps: [StepTiming; N],
+    /// Number of recorded steps.
+    count: u64,
+}
+
+impl<const N: usize> StepLog<N> {
+    /// Creates a new, empty log.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `N` is zero.
+    pub const fn new() -> Self {
+        assert!(N > 0, "step log capacity must be greater than zero");
+        Self {
+            steps: [StepTiming {
+                step: 0,
+                jitter_us: None,
+                compute_us: None,
+                overrun: false,
+            }; N],
+            count: 0,
+        }
+    }
+
+    /// Records the timing of a step. If the log is full, the oldest step is overwritten.
+    pub fn record(&mut self, step: StepTiming) {
+        self.steps[(self.count % N as u64) as usize] = step;
+        self.count += 1;
+    }
+
+    /// Discards all the recorded steps.
+    pub fn reset(&mut self) {
+        self.count = 0;
+    }
+
+    /// Returns the number of steps in the log.
+    #[inline]
+    pub fn len(&self) -> usize {
+        u64::min(self.count, N as u64) as usize
+    }
+
+    /// Returns `true` if the log is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the number of steps recorded since the log was created (or reset),
+    /// including the ones that were overwritten.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns an iterator over the steps in the log, from the oldest to the most recent.
+    pub fn iter(&self) -> impl Iterator<Item = &StepTiming> + '_ {
+        let first = self.count - self.len() as u64;
+        (first..self.count).map(move |i| &self.steps[(i % N as u64) as usize])
+    }
+}
+
+impl<const N: usize> Default for StepLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Display for StepLog<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "last {} of {} steps:", self.len(), self.count)?;
+        for step in self.iter() {
+            write!(f, "\n  {}", step)?;
+        }
+        Ok(())
+    }
+}
+
+/// Square root for `no_std` environments (Newton-Raphson method).
+fn sqrt(x: f64) -> f64 {
+    if x <= 0. {
+        return 0.;
+    }
+    // start above the root, so the sequence decreases monotonically until it converges
+    let mut y = f64::max(x, 1.);
+    loop {
+        let next = (y + x / y) / 2.;
+        if next >= y {
+            return y;
+        }
+        y = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        let stats = JitterStats::new(100);
+        assert_eq!(stats.count(), 0);
+        assert_eq!(stats.min_us(), None);
+        assert_eq!(stats.max_us(), None);
+        assert_eq!(stats.mean_us(), None);
+        assert_eq!(stats.stddev_us(), None);
+        assert_eq!(stats.histogram(), &[0; N_BUCKETS]);
+        assert_eq!(format!("{}", stats), "jitter: no samples");
+    }
+
+    #[test]
+    fn statistics() {
+        let mut stats = JitterStats::new(100);
+        for jitter in [2, 4, 4, 4, 5, 5, 7, 9] {
+            stats.record(jitter);
+        }
+        assert_eq!(stats.count(), 8);
+        assert_eq!(stats.min_us(), Some(2));
+        assert_eq!(stats.max_us(), Some(9));
+        assert_eq!(stats.mean_us(), Some(5.));
+        assert_eq!(stats.stddev_us(), Some(2.));
+    }
+
+    #[test]
+    fn histogram() {
+        let mut stats = JitterStats::new(100);
+        for jitter in [0, 99, 100, 250, 1_499, 1_500, 1_000_000] {
+            stats.record(jitter);
+        }
+        let histogram = stats.histogram();
+        assert_eq!(histogram[0], 2);
+        assert_eq!(histogram[1], 1);
+        assert_eq!(histogram[2], 1);
+        assert_eq!(histogram[14], 1);
+        assert_eq!(histogram[N_BUCKETS - 1], 2);
+        assert_eq!(histogram.iter().sum::<u32>() as u64, stats.count());
+
+        stats.reset();
+        assert_eq!(stats, JitterStats::new(100));
+    }
+
+    #[test]
+    fn display() {
+        let mut stats = JitterStats::new(10);
+        stats.record(5);
+        stats.record(15);
+        let report = format!("{}", stats);
+        let mut lines = report.lines();
+        assert_eq!(
+            lines.next(),
+            Some("jitter: 2 samples, min 5 us, max 15 us, mean 10.00 us, stddev 5.00 us")
+        );
+        assert_eq!(lines.next(), Some("  [0, 10) us: 1"));
+        assert_eq!(lines.next(), Some("  [10, 20) us: 1"));
+        assert_eq!(lines.last(), Some("  [150, inf) us: 0"));
+
+        // means and deviations are rounded to two decimals
+        let mut samples = Samples::new(10);
+        for us in [1, 2, 2] {
+            samples.record(us);
+        }
+        let report = format!("{}", samples);
+        assert!(report.starts_with("3 samples, min 1 us, max 2 us, mean 1.67 us, stddev 0.47 us"));
+    }
+
+    #[test]
+    fn overruns() {
+        let mut stats = JitterStats::new(10);
+        stats.record(5);
+        assert!(!format!("{}", stats).contains("overruns"));
+        stats.record_overrun(0);
+        stats.record_overrun(1_500);
+        stats.record_overrun(500);
+        assert_eq!(stats.overruns(), 3);
+        assert_eq!(stats.drift_us(), 2_000);
+        let report = format!("{}", stats);
+        assert_eq!(report.lines().last(), Some("  overruns: 3, drift: 2000 us"));
+        stats.reset();
+        assert_eq!(stats.overruns(), 0);
+        assert_eq!(stats.drift_us(), 0);
+    }
+
+    #[test]
+    fn compute() {
+        let mut stats = JitterStats::new(10);
+        stats.record(5);
+        stats.record_compute(100);
+        stats.record_compute(300);
+        assert_eq!(stats.count(), 1);
+        assert_eq!(stats.compute().count(), 2);
+        assert_eq!(stats.compute().max_us(), Some(300));
+        assert_eq!(stats.compute().mean_us(), Some(200.));
+        assert_eq!(stats.compute().histogram()[10], 1);
+        assert_eq!(stats.compute().histogram()[N_BUCKETS - 1], 1);
+        let report = format!("{}", stats);
+        assert_eq!(
+            report.lines().last(),
+            Some("compute: 2 samples, min 100 us, max 300 us, mean 200.00 us, stddev 100.00 us")
+        );
+    }
+
+    #[test]
+    fn step_log() {
+        let step = |step, jitter_us, compute_us| StepTiming {
+            step,
+            jitter_us,
+            compute_us,
+            ..StepTiming::default()
+        };
+        let mut log = StepLog::<2>::new();
+        assert!(log.is_empty());
+        assert_eq!(log.iter().next(), None);
+        log.record(step(0, Some(5), None));
+        assert_eq!(log.len(), 1);
+        log.record(step(1, None, Some(100)));
+        log.record(step(2, Some(7), Some(300)));
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.count(), 3);
+        let steps: Vec<_> = log.iter().copied().collect();
+        assert_eq!(
+            steps,
+            [step(1, None, Some(100)), step(2, Some(7), Some(300))]
+        );
+        let report = format!("{}", log);
+        let mut lines = report.lines();
+        assert_eq!(lines.next(), Some("last 2 of 3 steps:"));
+        assert_eq!(lines.next(), Some("  step 1: early, compute 100 us"));
+        assert_eq!(lines.next(), Some("  step 2: jitter 7 us, compute 300 us"));
+        log.reset();
+        assert!(log.is_empty());
+        assert_eq!(format!("{}", step(0, Some(5), None)), "step 0: jitter 5 us");
+        let overrun = StepTiming {
+            overrun: true,
+            ..step(3, None, Some(1_500))
+        };
+        assert_eq!(format!("{}", overrun), "step 3: overrun, compute 1500 us");
+    }
+
+    #[test]
+    fn square_root() {
+        assert_eq!(sqrt(0.), 0.);
+        assert_eq!(sqrt(1.), 1.);
+        assert_eq!(sqrt(0.25), 0.5);
+        assert_eq!(sqrt(1e6), 1e3);
+        assert!((sqrt(2.) - core::f64::consts::SQRT_2).abs() < 1e-12);
+    }
+}
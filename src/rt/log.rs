@@ -0,0 +1,557 @@
+//! Leveled logging and buffered logging through the UART transmit interrupt.
+//!
+//! The [`crate::error`], [`crate::warn`], [`crate::info`], [`crate::debug`],
+//! and [`crate::trace`] macros print a message tagged with its [`Component`]
+//! (e.g., `[T] acceptance: 0.50`) if it passes two filters:
+//!
+//! - A compile-time maximum level, selected with the `max-level-*` features (see [`MAX_LEVEL`]).
+//!   Messages above it are removed from the binary, including their formatting code, even without
+//!   optimizations. With the `log-deferred` feature, their format strings are still interned,
+//!   but the `.xdevs_log` section is not loaded into the board (see [`super::defer`]).
+//! - A runtime mask of components (see [`set_mask`], [`enable`], and [`disable`]).
+//!
+//! `hifive1::sprintln!` blocks until the whole message is in the transmit FIFO of the UART,
+//! which takes almost 90 µs per byte at 115200 baud. With the `log-buffer` feature,
+//! [`crate::println`] and [`crate::print`] format their messages into a [`LogBuffer`] in RAM,
+//! and the transmit watermark interrupt of UART0 drains it in the background.
+//! If the buffer is full, the rest of the message is dropped and counted (see [`dropped`]).
+//!
+//! UART0 must be configured as usual (e.g., with `hifive1::stdout::configure`), its interrupt
+//! handler must be generated with [`crate::uart_handler`], and [`start`] must be called before
+//! enabling machine interrupts. Messages logged before are kept until then.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use portable_atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+#[cfg(all(target_arch = "riscv32", feature = "log-buffer"))]
+use super::uart::Uart;
+#[cfg(all(target_arch = "riscv32", feature = "log-buffer"))]
+use hifive1::hal::e310x::Priority;
+
+/// Level of a log message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Errors (e.g., a failed peripheral).
+    Error,
+    /// Unexpected situations that do not stop the simulation (e.g., invalid input frames).
+    Warn,
+    /// Reports of the models (e.g., the statistics of the transducer).
+    Info,
+    /// Per-event messages of the models (e.g., every job of the processor).
+    Debug,
+    /// Detailed traces.
+    Trace,
+}
+
+/// Maximum level of the messages compiled in.
+/// It is [`Level::Trace`] unless a `max-level-*` feature (`off`, `error`, `warn`, `info`, or `debug`)
+/// is enabled. If several of them are enabled, the most restrictive one wins.
+pub const MAX_LEVEL: Option<Level> = if cfg!(feature = "max-level-off") {
+    None
+} else if cfg!(feature = "max-level-error") {
+    Some(Level::Error)
+} else if cfg!(feature = "max-level-warn") {
+    Some(Level::Warn)
+} else if cfg!(feature = "max-level-info") {
+    Some(Level::Info)
+} else if cfg!(feature = "max-level-debug") {
+    Some(Level::Debug)
+} else {
+    Some(Level::Trace)
+};
+
+/// Returns `true` if messages with the given level are compiled in (see [`MAX_LEVEL`]).
+#[inline]
+pub const fn compiled_in(level: Level) -> bool {
+    match MAX_LEVEL {
+        Some(max) => level as u8 <= max as u8,
+        None => false,
+    }
+}
+
+/// Source of log messages (e.g., an atomic model), identified by a bit of the runtime mask.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Component {
+    /// Bit of the component in the mask.
+    id: u8,
+    /// Tag printed before its messages.
+    tag: &'static str,
+}
+
+impl Component {
+    /// Creates a new component with the given ID (between 0 and 31) and tag.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `id` is greater than 31.
+    pub const fn new(id: u8, tag: &'static str) -> Self {
+        assert!(id < 32, "log component ID out of range");
+        Self { id, tag }
+    }
+
+    /// Returns the ID of the component.
+    #[inline]
+    pub const fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Returns the tag of the component.
+    #[inline]
+    pub const fn tag(&self) -> &'static str {
+        self.tag
+    }
+
+    /// Returns the bit of the component in the mask.
+    #[inline]
+    const fn bit(&self) -> u32 {
+        1 << self.id
+    }
+}
+
+/// Component of the messages of this crate (e.g., invalid input lines).
+pub const RT: Component = Component::new(31, "rt");
+
+/// Runtime mask of components. All of them are enabled by default.
+static MASK: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// Returns the runtime mask of components.
+#[inline]
+pub fn mask() -> u32 {
+    MASK.load(Ordering::Relaxed)
+}
+
+/// Sets the runtime mask of components: only the components whose bit is set print messages.
+#[inline]
+pub fn set_mask(mask: u32) {
+    MASK.store(mask, Ordering::Relaxed);
+}
+
+/// Enables the messages of a component.
+#[inline]
+pub fn enable(component: Component) {
+    MASK.fetch_or(component.bit(), Ordering::Relaxed);
+}
+
+/// Disables the messages of a component.
+#[inline]
+pub fn disable(component: Component) {
+    MASK.fetch_and(!component.bit(), Ordering::Relaxed);
+}
+
+/// Returns `true` if the bit of `component` is set in `mask`.
+#[inline]
+const fn selected(component: Component, mask: u32) -> bool {
+    mask & component.bit() != 0
+}
+
+/// Returns `true` if messages of `component` with the given level must be printed.
+#[inline]
+pub fn enabled(level: Level, component: Component) -> bool {
+    compiled_in(level) && selected(component, mask())
+}
+
+/// Prints a message with the given level and component if it passes the filters of [`enabled`].
+/// With the `log-deferred` feature, the message is sent as a [`super::defer::Record`] instead,
+/// so its arguments must implement [`super::defer::Arg`].
+///
+/// The level must be a constant: it is checked against [`MAX_LEVEL`] at compile time,
+/// so messages above it expand to dead code.
+///
+/// ```ignore
+/// riscv_xdevs::log!(Level::Info, LOG, "acceptance: {:.2}", acceptance);
+/// ```
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $component:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {{
+        const COMPILED_IN: bool = $crate::rt::log::compiled_in($level);
+        if COMPILED_IN && $crate::rt::log::enabled($level, $component) {
+            $crate::__log_message!($level, $component, $fmt $(, $arg)*);
+        }
+    }};
+}
+
+// The features are checked here (and not in `log!`) so they are the features of this crate,
+// not the ones of the crate that calls the macro.
+
+/// Sends a message of [`crate::log`] as a deferred record.
+#[cfg(all(feature = "log-deferred", target_arch = "riscv32"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_message {
+    ($level:expr, $component:expr, $fmt:literal $(, $arg:expr)*) => {{
+        #[link_section = ".xdevs_log"]
+        #[used]
+        static FORMAT: [u8; $fmt.len() + 1] = $crate::rt::defer::intern($fmt);
+        let mut record =
+            $crate::rt::defer::Record::new(FORMAT.as_ptr() as u32, $level, $component.tag());
+        $(record.arg(&$arg);)*
+        $crate::rt::defer::write(&record);
+    }};
+}
+
+/// Prints a message of [`crate::log`] with its tag.
+#[cfg(not(all(feature = "log-deferred", target_arch = "riscv32")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_message {
+    ($level:expr, $component:expr, $fmt:literal $(, $arg:expr)*) => {
+        $crate::println!("[{}] {}", $component.tag(), core::format_args!($fmt $(, $arg)*))
+    };
+}
+
+/// Prints an error message of a component (see [`crate::log`]).
+#[macro_export]
+macro_rules! error {
+    ($component:expr, $($arg:tt)+) => {
+        $crate::log!($crate::rt::log::Level::Error, $component, $($arg)+)
+    };
+}
+
+/// Prints a warning message of a component (see [`crate::log`]).
+#[macro_export]
+macro_rules! warn {
+    ($component:expr, $($arg:tt)+) => {
+        $crate::log!($crate::rt::log::Level::Warn, $component, $($arg)+)
+    };
+}
+
+/// Prints an informational message of a component (see [`crate::log`]).
+#[macro_export]
+macro_rules! info {
+    ($component:expr, $($arg:tt)+) => {
+        $crate::log!($crate::rt::log::Level::Info, $component, $($arg)+)
+    };
+}
+
+/// Prints a debug message of a component (see [`crate::log`]).
+#[macro_export]
+macro_rules! debug {
+    ($component:expr, $($arg:tt)+) => {
+        $crate::log!($crate::rt::log::Level::Debug, $component, $($arg)+)
+    };
+}
+
+/// Prints a trace message of a component (see [`crate::log`]).
+#[macro_export]
+macro_rules! trace {
+    ($component:expr, $($arg:tt)+) => {
+        $crate::log!($crate::rt::log::Level::Trace, $component, $($arg)+)
+    };
+}
+
+/// Fixed-capacity, lock-free ring buffer of log bytes.
+///
+/// As [`super::queue::EventQueue`], it has a single producer and a single consumer.
+/// Writes that preempt another write (e.g., logging from an interrupt handler) are dropped.
+pub struct LogBuffer<const N: usize> {
+    /// Storage of the bytes.
+    buffer: UnsafeCell<[u8; N]>,
+    /// Read counter, modulo `2 * N`. It is only modified by the consumer.
+    head: AtomicUsize,
+    /// Write counter, modulo `2 * N`. It is only modified by the producer.
+    tail: AtomicUsize,
+    /// It is set to `true` while bytes are being written.
+    writing: AtomicBool,
+    /// It is set to `true` while a byte is being read.
+    reading: AtomicBool,
+    /// Number of bytes that could not be written.
+    dropped: AtomicUsize,
+}
+
+// Bytes are only accessed by one side at a time (see `EventQueue`).
+unsafe impl<const N: usize> Sync for LogBuffer<N> {}
+
+impl<const N: usize> LogBuffer<N> {
+    /// Creates a new, empty buffer.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `N` is zero.
+    pub const fn new() -> Self {
+        assert!(N > 0, "log buffer capacity must be greater than zero");
+        Self {
+            buffer: UnsafeCell::new([0; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            writing: AtomicBool::new(false),
+            reading: AtomicBool::new(false),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the maximum number of bytes in the buffer.
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of bytes in the buffer.
+    #[inline]
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        Self::distance(head, tail)
+    }
+
+    /// Returns `true` if the buffer is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of bytes that were dropped because the buffer was full.
+    #[inline]
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Writes as many bytes as fit in the buffer and returns how many were written.
+    /// The remaining bytes are dropped.
+    pub fn write(&self, bytes: &[u8]) -> usize {
+        if self.writing.swap(true, Ordering::Acquire) {
+            self.dropped.fetch_add(bytes.len(), Ordering::Relaxed);
+            return 0;
+        }
+        let mut tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        let n = usize::min(bytes.len(), N - Self::distance(head, tail));
+        for &byte in &bytes[..n] {
+            // the consumer never reads slots between tail and head
+            unsafe { (self.buffer.get() as *mut u8).add(tail % N).write(byte) };
+            tail = Self::next(tail);
+        }
+        self.tail.store(tail, Ordering::Release);
+        self.writing.store(false, Ordering::Release);
+        if n < bytes.len() {
+            self.dropped.fetch_add(bytes.len() - n, Ordering::Relaxed);
+        }
+        n
+    }
+
+    /// Pops the oldest byte (if any).
+    pub fn pop(&self) -> Option<u8> {
+        if self.reading.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        let byte = if head == tail {
+            None
+        } else {
+            // the producer never writes slots between head and tail
+            let byte = unsafe { (self.buffer.get() as *const u8).add(head % N).read() };
+            self.head.store(Self::next(head), Ordering::Release);
+            Some(byte)
+        };
+        self.reading.store(false, Ordering::Release);
+        byte
+    }
+
+    /// Returns the counter that follows the given one.
+    #[inline]
+    fn next(counter: usize) -> usize {
+        (counter + 1) % (2 * N)
+    }
+
+    /// Returns the number of bytes between the two counters.
+    #[inline]
+    fn distance(head: usize, tail: usize) -> usize {
+        (tail + 2 * N - head) % (2 * N)
+    }
+}
+
+impl<const N: usize> Default for LogBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Write for &LogBuffer<N> {
+    /// It never fails: bytes that do not fit are dropped and counted.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Buffer of the messages logged with [`crate::println`] and [`crate::print`].
+#[cfg(all(target_arch = "riscv32", feature = "log-buffer"))]
+static LOG: LogBuffer<1024> = LogBuffer::new();
+
+/// Prints a message. It is called by [`crate::print`].
+///
+/// With the `qemu` feature, the message is sent through semihosting. Otherwise,
+/// with the `log-buffer` feature, it is formatted into the log buffer and the transmitter
+/// is woken up, and without it, it is sent through `hifive1::sprint`.
+/// On the host, messages are discarded.
+pub fn print(args: fmt::Arguments) {
+    #[cfg(all(target_arch = "riscv32", feature = "qemu"))]
+    semihosting::print!("{}", args);
+    #[cfg(all(target_arch = "riscv32", not(feature = "qemu"), feature = "log-buffer"))]
+    {
+        fmt::Write::write_fmt(&mut &LOG, args).ok();
+        // the log owns the transmitter of UART0 (see `start`)
+        unsafe { Uart::Uart0.set_tx_interrupt(true) };
+    }
+    #[cfg(all(
+        target_arch = "riscv32",
+        not(feature = "qemu"),
+        not(feature = "log-buffer")
+    ))]
+    hifive1::sprint!("{}", args);
+    #[cfg(not(target_arch = "riscv32"))]
+    let _ = args;
+}
+
+/// Prints a message and a new line (see [`print()`]). It is called by [`crate::println`].
+pub fn println(args: fmt::Arguments) {
+    print(format_args!("{}\n", args));
+}
+
+/// Writes raw bytes into the log buffer (e.g., deferred log records) and wakes up the transmitter.
+#[cfg(all(target_arch = "riscv32", feature = "log-buffer"))]
+pub fn write(bytes: &[u8]) {
+    LOG.write(bytes);
+    // as in `print`
+    unsafe { Uart::Uart0.set_tx_interrupt(true) };
+}
+
+/// Returns the number of log bytes that were dropped because the buffer was full.
+#[cfg(all(target_arch = "riscv32", feature = "log-buffer"))]
+pub fn dropped() -> usize {
+    LOG.dropped()
+}
+
+/// Starts draining the log buffer: it enables the transmit watermark interrupt of UART0
+/// with the given PLIC priority. Machine interrupts are not enabled.
+///
+/// UART0 has a single PLIC source, so its receive interrupt (see [`Uart::listen`]) shares
+/// this priority: if it is already enabled, `priority` must be the same one.
+///
+/// # Safety
+///
+/// From now on, the log owns the transmitter of UART0: no other code may write to it.
+/// The caller must also uphold the contract of [`Uart::enable_tx`].
+#[cfg(all(target_arch = "riscv32", feature = "log-buffer"))]
+pub unsafe fn start(priority: Priority) {
+    Uart::Uart0.enable_tx(priority);
+}
+
+/// Moves log bytes into the transmit FIFO of UART0 until it is full.
+/// Once the buffer is empty, the transmit interrupt is disabled until the next message.
+/// It is called from the interrupt handler of UART0.
+#[cfg(all(target_arch = "riscv32", feature = "log-buffer"))]
+pub fn transmit() {
+    while !Uart::Uart0.tx_full() {
+        // the log owns the transmitter of UART0 (see `start`)
+        match LOG.pop() {
+            Some(byte) => unsafe { Uart::Uart0.write(&[byte]) },
+            None => {
+                unsafe { Uart::Uart0.set_tx_interrupt(false) };
+                break;
+            }
+        }
+    }
+}
+
+/// Sends all the buffered log bytes, blocking until they are in the transmit FIFO
+/// (e.g., before exiting). Interrupts are not needed.
+#[cfg(all(target_arch = "riscv32", feature = "log-buffer"))]
+pub fn flush() {
+    while let Some(byte) = LOG.pop() {
+        // as in `transmit`
+        unsafe { Uart::Uart0.write(&[byte]) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    /// Pops all the bytes of the buffer.
+    fn read<const N: usize>(buffer: &LogBuffer<N>) -> Vec<u8> {
+        core::iter::from_fn(|| buffer.pop()).collect()
+    }
+
+    #[test]
+    fn levels() {
+        assert!(Level::Error < Level::Warn && Level::Debug < Level::Trace);
+        let levels = [
+            Level::Error,
+            Level::Warn,
+            Level::Info,
+            Level::Debug,
+            Level::Trace,
+        ];
+        for level in levels {
+            assert_eq!(
+                compiled_in(level),
+                matches!(MAX_LEVEL, Some(max) if level <= max)
+            );
+        }
+        #[cfg(not(any(
+            feature = "max-level-off",
+            feature = "max-level-error",
+            feature = "max-level-warn",
+            feature = "max-level-info",
+            feature = "max-level-debug"
+        )))]
+        assert_eq!(MAX_LEVEL, Some(Level::Trace));
+        #[cfg(feature = "max-level-off")]
+        assert_eq!(MAX_LEVEL, None);
+        crate::info!(RT, "messages are formatted as usual: {}", 42);
+    }
+
+    #[test]
+    fn components() {
+        const A: Component = Component::new(0, "A");
+        const B: Component = Component::new(5, "B");
+        assert!(selected(A, u32::MAX) && selected(B, u32::MAX));
+        assert!(selected(A, !(1 << 5)) && !selected(B, !(1 << 5)));
+        assert!(!selected(A, 1 << 5) && selected(B, 1 << 5));
+        assert!(!selected(RT, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "log component ID out of range")]
+    fn component_out_of_range() {
+        Component::new(32, "X");
+    }
+
+    #[test]
+    fn write_and_read() {
+        let buffer = LogBuffer::<8>::new();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.write(b"hello"), 5);
+        assert_eq!(buffer.len(), 5);
+        assert_eq!(read(&buffer), b"hello");
+        // the buffer wraps around
+        assert_eq!(buffer.write(b"world"), 5);
+        assert_eq!(read(&buffer), b"world");
+        assert_eq!(buffer.dropped(), 0);
+    }
+
+    #[test]
+    fn overflow() {
+        let buffer = LogBuffer::<8>::new();
+        assert_eq!(buffer.write(b"0123456"), 7);
+        assert_eq!(buffer.write(b"789"), 1);
+        assert_eq!(buffer.len(), buffer.capacity());
+        assert_eq!(buffer.dropped(), 2);
+        assert_eq!(buffer.write(b"x"), 0);
+        assert_eq!(buffer.dropped(), 3);
+        assert_eq!(read(&buffer), b"01234567");
+    }
+
+    #[test]
+    fn format() {
+        let buffer = LogBuffer::<32>::new();
+        writeln!(&buffer, "[T] acceptance: {:.2}", 0.5).unwrap();
+        assert_eq!(read(&buffer), b"[T] acceptance: 0.50\n");
+        // formatting never fails, even if the message does not fit
+        write!(&buffer, "{}", "x".repeat(40)).unwrap();
+        assert_eq!(buffer.dropped(), 8);
+    }
+}
@@ -0,0 +1,50 @@
+//! Real-time wait strategies for [`xdevs::simulator::Simulator::simulate_rt`].
+//!
+//! Every constructor in this module returns a closure to be used as the `wait_until`
+//! argument of `simulate_rt`, and all of them share the same signature:
+//!
+//! - `clock`: timer used for waiting (see [`clock::Clock`] and [`clock::Alarm`]).
+//! - `timeline`: mapping between simulation time and the ticks of `clock` (see [`time::Timeline`]).
+//!   Its epoch is the tick at which the simulation starts, so timers are never reset.
+//!   Output handlers can share the timeline to timestamp events consistently.
+//!   Its pacing defines how overruns are handled (see [`time::Pacing`]).
+//! - `options`: optional features of the strategy (see [`WaitOptions`]):
+//!   - a [`control::Control`] handle to pause, resume, and change the time scale
+//!     of the simulation at runtime.
+//!   - [`output::Deferred`] actions (e.g., the end of a pulse) to run on time while waiting
+//!     for the next simulation step, as part of the same timer schedule.
+//!   - the maximum jitter allowed and what to do when a step misses it (see [`deadline::Deadline`]).
+//!   - a [`jitter::JitterStats`] collector of the jitter of every step, the time the simulator
+//!     spent computing it, and the overruns. It can be printed once the simulation is over.
+//!   - a callback that receives the timing of every step (see [`jitter::StepTiming`]).
+//! - `input_handler`: closure that injects external events into the input bag.
+//!   It returns an [`Injection`]: either `true` if at least one event was injected,
+//!   or the clock tick at which the earliest injected event was captured.
+//!   In the latter case, strategies that wake up early return the simulation time of the capture.
+//!   Use [`no_input`] for models without external events, or [`queue::drain`] for events
+//!   captured by interrupt handlers in a [`queue::EventQueue`].
+//!   Inputs that bounce (e.g., buttons) can be filtered with [`debounce::drain`],
+//!   and GPIO pins can be mapped to input ports with [`gpio::GpioInputs`].
+//!   Commands received over a UART (e.g., `in_job 42`) are injected with [`uart::SerialInputs`].
+//!   Binary frames sent by host tools are injected with [`frame::FramedInputs`].
+
+pub mod clock;
+pub mod codec;
+pub mod control;
+pub mod deadline;
+pub mod debounce;
+pub mod defer;
+pub mod frame;
+pub mod gpio;
+#[cfg(test)]
+mod harness;
+pub mod jitter;
+pub mod log;
+pub mod output;
+pub mod pwm;
+pub mod queue;
+pub mod time;
+pub mod uart;
+mod wait;
+
+pub use wait::*;
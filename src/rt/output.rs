@@ -0,0 +1,414 @@
+//! Output handlers that drive GPIO pins from the output ports of the model.
+//!
+//! [`GpioOutputs`] maps triggers (e.g., a port with a given value) to [`Action`]s on output pins.
+//! Pins are any [`OutputPin`], and the level of every pin is tracked, so they can be toggled.
+//! Triggers are closures that receive the output bag of the model.
+//! [`present`] and [`when`] build triggers from a port and a predicate on its values:
+//!
+//! ```ignore
+//! let stop = rt::output::when(|o: &PTOutput| o.out_stop.get_values(), |&stop| stop);
+//! let mut outputs = GpioOutputs::new(clock.time_base());
+//! let led = outputs.pin(&mut blueled);
+//! outputs.on(led, &stop, Action::Pulse(200));
+//! // pulses end on time if the wait strategy runs the deferred actions of the outputs
+//! let options = rt::WaitOptions::new().with_deferred(&outputs).with_deadline(deadline);
+//! let wait = rt::wait_sleep(clock, timeline, options, rt::no_input);
+//! simulator.simulate_rt(0.0, t_sim, wait, outputs.handler(rt::clock::Clint::new()));
+//! ```
+
+use super::clock::Clock;
+use super::time::{Rounding, TimeBase};
+use core::cell::RefCell;
+use core::fmt::Debug;
+use embedded_hal::digital::v2::OutputPin;
+use xdevs::aux::Bag;
+
+/// Maximum number of pins of a [`GpioOutputs`].
+pub const MAX_PINS: usize = 8;
+/// Maximum number of rules of a [`GpioOutputs`].
+pub const MAX_RULES: usize = 16;
+
+/// Action on an output pin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// The pin is set high.
+    Set,
+    /// The pin is set low.
+    Clear,
+    /// The level of the pin is inverted.
+    Toggle,
+    /// The pin is set high, and it is set low after the given number of milliseconds.
+    /// Triggering the pulse again extends it.
+    Pulse(u64),
+}
+
+/// Output pin whose level can be set. It is implemented for every [`OutputPin`].
+pub trait Actuator {
+    /// Sets the level of the pin.
+    fn set_level(&mut self, high: bool);
+}
+
+impl<P: OutputPin> Actuator for P
+where
+    P::Error: Debug,
+{
+    fn set_level(&mut self, high: bool) {
+        let res = match high {
+            true => self.set_high(),
+            false => self.set_low(),
+        };
+        res.expect("failed to set output pin");
+    }
+}
+
+/// Actions deferred to a future tick (e.g., the end of a pulse).
+///
+/// Wait strategies fold the deferred actions into their timer schedule: they wake up
+/// when the next action is due, run it, and go back to wait for the next simulation step.
+/// Thus, deferred actions neither block the simulation nor add jitter to it.
+pub trait Deferred {
+    /// Returns the tick of the earliest pending action (if any).
+    fn next_tick(&self) -> Option<u64>;
+
+    /// Runs all the actions that are due at `tick`.
+    fn run(&self, tick: u64);
+}
+
+/// Trigger that fires when `port` has at least one value.
+pub fn present<T, V>(port: impl Fn(&T) -> &[V]) -> impl Fn(&T) -> bool {
+    move |output| !port(output).is_empty()
+}
+
+/// Trigger that fires when at least one value of `port` fulfills `predicate`.
+pub fn when<T, V>(
+    port: impl Fn(&T) -> &[V],
+    predicate: impl Fn(&V) -> bool,
+) -> impl Fn(&T) -> bool {
+    move |output| port(output).iter().any(&predicate)
+}
+
+/// Identifier of a pin of a [`GpioOutputs`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinId(usize);
+
+/// Output pin and its state.
+struct Slot<'a> {
+    /// Output pin.
+    pin: &'a mut dyn Actuator,
+    /// Current level of the pin.
+    level: bool,
+    /// Tick at which the active pulse of the pin ends (if any).
+    release: Option<u64>,
+}
+
+impl<'a> Slot<'a> {
+    /// Sets the level of the pin and cancels any active pulse.
+    fn set_level(&mut self, level: bool) {
+        self.pin.set_level(level);
+        self.level = level;
+        self.release = None;
+    }
+}
+
+/// Mapping of a trigger to an action on a pin.
+struct Rule<'a, T> {
+    /// Pin of the action.
+    pin: usize,
+    /// Trigger of the action.
+    trigger: &'a dyn Fn(&T) -> bool,
+    /// Action to take when the trigger fires.
+    action: Action,
+}
+
+/// Builder of the mapping between the output ports of the model and GPIO actions.
+///
+/// Every time the model produces an output, rules are checked in the order they were added,
+/// and the actions of all the rules that fire are taken.
+pub struct GpioOutputs<'a, T> {
+    /// Time base of the clock used to time pulses.
+    base: TimeBase,
+    /// Output pins.
+    slots: RefCell<[Option<Slot<'a>>; MAX_PINS]>,
+    /// Rules, in order.
+    rules: [Option<Rule<'a, T>>; MAX_RULES],
+}
+
+impl<'a, T: Bag> GpioOutputs<'a, T> {
+    /// Creates a new builder with no pins. Pulses are timed with a clock of the given time base.
+    pub fn new(base: TimeBase) -> Self {
+        Self {
+            base,
+            slots: RefCell::new(Default::default()),
+            rules: Default::default(),
+        }
+    }
+
+    /// Adds an output pin. The pin is set low.
+    ///
+    /// # Panics
+    ///
+    /// It panics if there are already [`MAX_PINS`] pins.
+    pub fn pin(&mut self, pin: &'a mut dyn Actuator) -> PinId {
+        let slots = self.slots.get_mut();
+        let index = slots
+            .iter()
+            .position(Option::is_none)
+            .expect("too many output pins");
+        pin.set_level(false);
+        slots[index] = Some(Slot {
+            pin,
+            level: false,
+            release: None,
+        });
+        PinId(index)
+    }
+
+    /// Adds a rule: when `trigger` fires, `action` is taken on `pin`.
+    ///
+    /// # Panics
+    ///
+    /// It panics if there are already [`MAX_RULES`] rules.
+    pub fn on(&mut self, pin: PinId, trigger: &'a dyn Fn(&T) -> bool, action: Action) {
+        let rule = (self.rules.iter_mut())
+            .find(|rule| rule.is_none())
+            .expect("too many output rules");
+        *rule = Some(Rule {
+            pin: pin.0,
+            trigger,
+            action,
+        });
+    }
+
+    /// Returns the current level of a pin.
+    pub fn level(&self, pin: PinId) -> bool {
+        self.slots.borrow()[pin.0].as_ref().unwrap().level
+    }
+
+    /// Ends the pulses that are over at `tick`.
+    pub fn update(&self, tick: u64) {
+        for slot in self.slots.borrow_mut().iter_mut().flatten() {
+            if matches!(slot.release, Some(release) if release <= tick) {
+                slot.set_level(false);
+            }
+        }
+    }
+
+    /// Takes the actions of the rules that fire with `output`, at the given tick.
+    pub fn apply(&self, tick: u64, output: &T) {
+        let mut slots = self.slots.borrow_mut();
+        for rule in self.rules.iter().flatten() {
+            if !(rule.trigger)(output) {
+                continue;
+            }
+            let slot = slots[rule.pin].as_mut().unwrap();
+            match rule.action {
+                Action::Set => slot.set_level(true),
+                Action::Clear => slot.set_level(false),
+                Action::Toggle => slot.set_level(!slot.level),
+                Action::Pulse(ms) => {
+                    let release = tick.saturating_add(
+                        self.base
+                            .us_to_ticks(ms.saturating_mul(1_000), Rounding::Ceil),
+                    );
+                    slot.set_level(true);
+                    slot.release = Some(release);
+                }
+            }
+        }
+    }
+
+    /// Returns the output handler of the simulation. It reads `clock` to time pulses.
+    ///
+    /// Pulses that are over are ended every time the output handler is called.
+    /// To end them on time, pass this builder to the wait strategy as its [`Deferred`] actions.
+    ///
+    /// # Panics
+    ///
+    /// It panics if the time base of `clock` does not match the one of the builder.
+    pub fn handler<C: Clock + 'a>(&'a self, mut clock: C) -> impl FnMut(&T) + 'a {
+        assert_eq!(
+            clock.time_base(),
+            self.base,
+            "output clock does not match the time base"
+        );
+        move |output| {
+            let tick = clock.now();
+            self.update(tick);
+            if !output.is_empty() {
+                self.apply(tick, output);
+            }
+        }
+    }
+}
+
+impl<'a, T: Bag> Deferred for GpioOutputs<'a, T> {
+    fn next_tick(&self) -> Option<u64> {
+        (self.slots.borrow().iter().flatten())
+            .filter_map(|slot| slot.release)
+            .min()
+    }
+
+    fn run(&self, tick: u64) {
+        self.update(tick);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rt::clock::MockClock;
+    use crate::rt::harness::Shared;
+    use core::convert::Infallible;
+    use std::rc::Rc;
+    use xdevs::port::Port;
+
+    const FREQ: u64 = 32_768;
+
+    /// Output pin that records its level changes.
+    #[derive(Default)]
+    struct Recorder(Rc<RefCell<Vec<bool>>>);
+
+    impl OutputPin for Recorder {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.0.borrow_mut().push(false);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.0.borrow_mut().push(true);
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct Output {
+        out_level: Port<u8, 2>,
+        out_stop: Port<bool, 1>,
+    }
+
+    impl Bag for Output {
+        fn is_empty(&self) -> bool {
+            self.out_level.is_empty() && self.out_stop.is_empty()
+        }
+
+        fn clear(&mut self) {
+            self.out_level.clear();
+            self.out_stop.clear();
+        }
+    }
+
+    #[test]
+    fn triggers() {
+        let stop = present(|o: &Output| o.out_stop.get_values());
+        let high = when(|o: &Output| o.out_level.get_values(), |&level| level > 10);
+        let mut output = Output::default();
+        assert!(!stop(&output));
+        assert!(!high(&output));
+        output.out_stop.add_value(false).unwrap();
+        output.out_level.add_value(3).unwrap();
+        assert!(stop(&output));
+        assert!(!high(&output));
+        output.out_level.add_value(11).unwrap();
+        assert!(high(&output));
+    }
+
+    #[test]
+    fn actions() {
+        let (mut red, mut blue) = (Recorder::default(), Recorder::default());
+        let (red_log, blue_log) = (red.0.clone(), blue.0.clone());
+        let stop = when(|o: &Output| o.out_stop.get_values(), |&stop| stop);
+        let resume = when(|o: &Output| o.out_stop.get_values(), |&stop| !stop);
+        let level = present(|o: &Output| o.out_level.get_values());
+
+        let mut outputs = GpioOutputs::new(TimeBase::new(FREQ));
+        let red_id = outputs.pin(&mut red);
+        let blue_id = outputs.pin(&mut blue);
+        outputs.on(red_id, &stop, Action::Set);
+        outputs.on(red_id, &resume, Action::Clear);
+        outputs.on(blue_id, &level, Action::Toggle);
+
+        let mut output = Output::default();
+        output.out_stop.add_value(true).unwrap();
+        outputs.apply(0, &output);
+        assert!(outputs.level(red_id));
+        assert!(!outputs.level(blue_id));
+        output.clear();
+        output.out_level.add_value(1).unwrap();
+        outputs.apply(0, &output);
+        outputs.apply(0, &output);
+        output.out_stop.add_value(false).unwrap();
+        outputs.apply(0, &output);
+        assert!(!outputs.level(red_id));
+        assert!(outputs.level(blue_id));
+
+        // pins are set low when they are added
+        assert_eq!(*red_log.borrow(), [false, true, false]);
+        assert_eq!(*blue_log.borrow(), [false, true, false, true]);
+    }
+
+    #[test]
+    fn pulses() {
+        let mut led = Recorder::default();
+        let log = led.0.clone();
+        let stop = present(|o: &Output| o.out_stop.get_values());
+        let clock = RefCell::new(MockClock::new(FREQ));
+        let mut outputs = GpioOutputs::new(TimeBase::new(FREQ));
+        let id = outputs.pin(&mut led);
+        outputs.on(id, &stop, Action::Pulse(250));
+        let mut handler = outputs.handler(Shared(&clock));
+
+        let mut output = Output::default();
+        output.out_stop.add_value(true).unwrap();
+        handler(&output);
+        assert!(outputs.level(id));
+        // the pulse is extended if it is triggered again before it ends
+        clock.borrow_mut().set(FREQ / 8);
+        handler(&output);
+        output.clear();
+        clock.borrow_mut().set(FREQ / 4);
+        handler(&output);
+        assert!(outputs.level(id));
+        clock.borrow_mut().set(3 * FREQ / 8);
+        handler(&output);
+        assert!(!outputs.level(id));
+        assert_eq!(*log.borrow(), [false, true, true, false]);
+    }
+
+    #[test]
+    fn deferred_pulses() {
+        let (mut red, mut blue) = (Recorder::default(), Recorder::default());
+        let stop = present(|o: &Output| o.out_stop.get_values());
+        let level = present(|o: &Output| o.out_level.get_values());
+        let mut outputs = GpioOutputs::new(TimeBase::new(1_000));
+        let red_id = outputs.pin(&mut red);
+        let blue_id = outputs.pin(&mut blue);
+        outputs.on(red_id, &stop, Action::Pulse(200));
+        outputs.on(blue_id, &level, Action::Pulse(100));
+        assert_eq!(outputs.next_tick(), None);
+
+        let mut output = Output::default();
+        output.out_stop.add_value(true).unwrap();
+        output.out_level.add_value(1).unwrap();
+        outputs.apply(10, &output);
+        assert_eq!(outputs.next_tick(), Some(110));
+        outputs.run(110);
+        assert!(outputs.level(red_id));
+        assert!(!outputs.level(blue_id));
+        assert_eq!(outputs.next_tick(), Some(210));
+        outputs.run(300);
+        assert!(!outputs.level(red_id));
+        assert_eq!(outputs.next_tick(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "too many output pins")]
+    fn too_many_pins() {
+        let mut pins: [Recorder; MAX_PINS + 1] = Default::default();
+        let mut outputs = GpioOutputs::<Output>::new(TimeBase::new(FREQ));
+        for pin in pins.iter_mut() {
+            outputs.pin(pin);
+        }
+    }
+}
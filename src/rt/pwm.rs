@@ -0,0 +1,399 @@
+//! PWM outputs driven from numeric output ports.
+//!
+//! [`handler`] maps a port of the output bag to the duty cycle of a [`Channel`],
+//! so models can control the brightness of an LED or the speed of a motor.
+//! Port values are converted to duty cycles with the [`Duty`] trait:
+//! integers span their whole range, while floats are clamped to `[0, 1]`.
+//!
+//! On the board, [`Pwm`] takes one of the three PWM peripherals of the E310x and configures it,
+//! and [`Pwm::channel`] returns its comparator channels. As QEMU does not emulate the PWM blocks,
+//! channels only log their duty changes when the `qemu` feature is enabled.
+//! Note that the pins of the channels must be routed to the PWM block (IOF1) by the application.
+
+use core::fmt;
+use xdevs::aux::Bag;
+
+#[cfg(target_arch = "riscv32")]
+use core::ops::Deref;
+#[cfg(target_arch = "riscv32")]
+use hifive1::hal::e310x::{pwm0, PWM0, PWM1, PWM2};
+
+/// Value of an output port that can be converted to a duty cycle.
+pub trait Duty {
+    /// Returns the duty cycle, between `0.0` (always low) and `1.0` (always high).
+    fn to_duty(&self) -> f32;
+}
+
+impl Duty for bool {
+    fn to_duty(&self) -> f32 {
+        match self {
+            true => 1.,
+            false => 0.,
+        }
+    }
+}
+
+impl Duty for u8 {
+    fn to_duty(&self) -> f32 {
+        *self as f32 / u8::MAX as f32
+    }
+}
+
+impl Duty for u16 {
+    fn to_duty(&self) -> f32 {
+        *self as f32 / u16::MAX as f32
+    }
+}
+
+impl Duty for f32 {
+    /// NaN is mapped to `0.0`.
+    fn to_duty(&self) -> f32 {
+        match self.is_nan() {
+            true => 0.,
+            false => self.clamp(0., 1.),
+        }
+    }
+}
+
+impl Duty for f64 {
+    /// NaN is mapped to `0.0`.
+    fn to_duty(&self) -> f32 {
+        (*self as f32).to_duty()
+    }
+}
+
+/// Output channel whose duty cycle can be set.
+pub trait Channel {
+    /// Sets the duty cycle of the channel, between `0.0` and `1.0`.
+    fn set_duty(&mut self, duty: f32);
+}
+
+/// Channel that only logs its duty changes (e.g., for testing without the actual hardware).
+#[derive(Clone, Copy, Debug)]
+pub struct LogChannel {
+    /// Name of the channel in the log.
+    name: &'static str,
+}
+
+impl LogChannel {
+    /// Creates a new log channel with the given name.
+    pub const fn new(name: &'static str) -> Self {
+        Self { name }
+    }
+}
+
+impl Channel for LogChannel {
+    fn set_duty(&mut self, duty: f32) {
+        crate::println!("[{}] duty: {}", self.name, Milli::new(duty));
+    }
+}
+
+/// Duty cycle rounded to three decimals, which is printed as integers.
+/// Float formatting would link its code into the board even with the `log-deferred` feature.
+struct Milli(u32);
+
+impl Milli {
+    /// Rounds a duty cycle to three decimals.
+    fn new(duty: f32) -> Self {
+        Self((duty.to_duty() * 1000. + 0.5) as u32)
+    }
+}
+
+impl fmt::Display for Milli {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:03}", self.0 / 1000, self.0 % 1000)
+    }
+}
+
+/// Returns the comparator value that keeps the output of a PWM channel high
+/// for a fraction `duty` of a period of `period` counts.
+///
+/// The output of a channel is high while the counter is greater than or equal to its comparator.
+pub fn compare(duty: f32, period: u32) -> u32 {
+    let high = (duty.to_duty() * period as f32 + 0.5) as u32;
+    period - u32::min(high, period)
+}
+
+/// Output handler that sets the duty cycle of `channel` from the last value of `port`.
+/// The duty cycle is only updated when it changes, and it is kept while the port is empty.
+pub fn handler<'a, T: Bag, V: Duty + 'a>(
+    port: impl Fn(&T) -> &[V] + 'a,
+    mut channel: impl Channel + 'a,
+) -> impl FnMut(&T) + 'a {
+    let mut current = None;
+
+    move |output| {
+        if let Some(value) = port(output).last() {
+            let duty = value.to_duty();
+            if current != Some(duty) {
+                channel.set_duty(duty);
+                current = Some(duty);
+            }
+        }
+    }
+}
+
+/// PWM blocks of the E310x.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Block {
+    /// PWM0, with 8-bit comparators.
+    Pwm0,
+    /// PWM1, with 16-bit comparators.
+    Pwm1,
+    /// PWM2, with 16-bit comparators.
+    Pwm2,
+}
+
+impl Block {
+    /// Returns the maximum value of the comparators of the block.
+    pub const fn max_compare(self) -> u32 {
+        match self {
+            Self::Pwm0 => u8::MAX as u32,
+            Self::Pwm1 | Self::Pwm2 => u16::MAX as u32,
+        }
+    }
+}
+
+/// Configuration bit that resets the counter when it reaches comparator 0.
+#[cfg(all(target_arch = "riscv32", not(feature = "qemu")))]
+const PWMZEROCMP: u32 = 1 << 9;
+/// Configuration bit that latches the comparator outputs within the same cycle.
+#[cfg(all(target_arch = "riscv32", not(feature = "qemu")))]
+const PWMDEGLITCH: u32 = 1 << 10;
+/// Configuration bit that keeps the counter running continuously.
+#[cfg(all(target_arch = "riscv32", not(feature = "qemu")))]
+const PWMENALWAYS: u32 = 1 << 12;
+
+/// PWM peripheral of the E310x, which is owned by a [`Pwm`].
+#[cfg(target_arch = "riscv32")]
+pub trait Instance: Deref<Target = pwm0::RegisterBlock> {
+    /// PWM block of the peripheral.
+    const BLOCK: Block;
+}
+
+#[cfg(target_arch = "riscv32")]
+impl Instance for PWM0 {
+    const BLOCK: Block = Block::Pwm0;
+}
+
+#[cfg(target_arch = "riscv32")]
+impl Instance for PWM1 {
+    const BLOCK: Block = Block::Pwm1;
+}
+
+#[cfg(target_arch = "riscv32")]
+impl Instance for PWM2 {
+    const BLOCK: Block = Block::Pwm2;
+}
+
+/// PWM block of the E310x.
+///
+/// It owns its PWM peripheral, so no other driver can reconfigure the block.
+/// Comparator 0 sets the period of the block, so comparators 1 to 3 drive the output channels.
+#[cfg(target_arch = "riscv32")]
+pub struct Pwm<P> {
+    /// PWM peripheral.
+    pwm: P,
+    /// Counts per period.
+    period: u32,
+}
+
+#[cfg(target_arch = "riscv32")]
+impl<P: Instance> Pwm<P> {
+    /// Configures a PWM block with the given period (in counts) and counter scale
+    /// (the counter advances once every `2^scale` cycles of the peripheral clock).
+    /// All the channels start with a duty cycle of `0.0`.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `period` is zero or greater than the maximum comparator value of the block,
+    /// or if `scale` is greater than 15.
+    pub fn new(pwm: P, period: u32, scale: u8) -> Self {
+        assert!(
+            period > 0 && period <= P::BLOCK.max_compare(),
+            "PWM period out of range"
+        );
+        assert!(scale < 16, "PWM scale out of range");
+        #[cfg(not(feature = "qemu"))]
+        unsafe {
+            pwm.cfg.write(|w| w.bits(0));
+            pwm.cmp0.write(|w| w.bits(period));
+            pwm.cmp1.write(|w| w.bits(period));
+            pwm.cmp2.write(|w| w.bits(period));
+            pwm.cmp3.write(|w| w.bits(period));
+            let cfg = PWMENALWAYS | PWMDEGLITCH | PWMZEROCMP | scale as u32;
+            pwm.cfg.write(|w| w.bits(cfg));
+        }
+        Self { pwm, period }
+    }
+
+    /// Returns the output channel of comparator `n`.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `n` is not between 1 and 3.
+    pub fn channel(&self, n: u8) -> PwmChannel<'_, P> {
+        assert!((1..=3).contains(&n), "PWM channel out of range");
+        PwmChannel {
+            pwm: &self.pwm,
+            n,
+            period: self.period,
+            inverted: false,
+        }
+    }
+
+    /// Stops the PWM block and releases its peripheral.
+    pub fn free(self) -> P {
+        #[cfg(not(feature = "qemu"))]
+        unsafe {
+            self.pwm.cfg.write(|w| w.bits(0));
+        }
+        self.pwm
+    }
+}
+
+/// Output channel of a [`Pwm`] block.
+#[cfg(target_arch = "riscv32")]
+pub struct PwmChannel<'a, P> {
+    /// PWM peripheral.
+    #[cfg_attr(feature = "qemu", allow(dead_code))]
+    pwm: &'a P,
+    /// Number of the comparator.
+    n: u8,
+    /// Counts per period.
+    period: u32,
+    /// If `true`, the duty cycle is inverted (e.g., for active-low LEDs).
+    inverted: bool,
+}
+
+// channels only hold a shared reference to their block, so they are copied regardless of `P`
+#[cfg(target_arch = "riscv32")]
+impl<'a, P> Clone for PwmChannel<'a, P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+impl<'a, P> Copy for PwmChannel<'a, P> {}
+
+#[cfg(target_arch = "riscv32")]
+impl<'a, P> PwmChannel<'a, P> {
+    /// Inverts the duty cycle of the channel (e.g., for active-low LEDs).
+    pub const fn inverted(mut self) -> Self {
+        self.inverted = !self.inverted;
+        self
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+impl<'a, P: Instance> Channel for PwmChannel<'a, P> {
+    fn set_duty(&mut self, duty: f32) {
+        let duty = match self.inverted {
+            true => 1. - duty.to_duty(),
+            false => duty.to_duty(),
+        };
+        let compare = compare(duty, self.period);
+        #[cfg(feature = "qemu")]
+        crate::println!(
+            "[{:?}.{}] duty: {} ({})",
+            P::BLOCK,
+            self.n,
+            Milli::new(duty),
+            compare
+        );
+        #[cfg(not(feature = "qemu"))]
+        unsafe {
+            match self.n {
+                1 => self.pwm.cmp1.write(|w| w.bits(compare)),
+                2 => self.pwm.cmp2.write(|w| w.bits(compare)),
+                _ => self.pwm.cmp3.write(|w| w.bits(compare)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::RefCell;
+    use xdevs::port::Port;
+
+    /// Channel that records its duty cycles.
+    struct Recorder<'a>(&'a RefCell<Vec<f32>>);
+
+    impl<'a> Channel for Recorder<'a> {
+        fn set_duty(&mut self, duty: f32) {
+            self.0.borrow_mut().push(duty);
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct Output {
+        out_level: Port<u8, 2>,
+    }
+
+    impl Bag for Output {
+        fn is_empty(&self) -> bool {
+            self.out_level.is_empty()
+        }
+
+        fn clear(&mut self) {
+            self.out_level.clear();
+        }
+    }
+
+    #[test]
+    fn duty() {
+        assert_eq!(true.to_duty(), 1.);
+        assert_eq!(false.to_duty(), 0.);
+        assert_eq!(0u8.to_duty(), 0.);
+        assert_eq!(255u8.to_duty(), 1.);
+        assert_eq!(u16::MAX.to_duty(), 1.);
+        assert_eq!(0.25f32.to_duty(), 0.25);
+        assert_eq!((-1f32).to_duty(), 0.);
+        assert_eq!(2f64.to_duty(), 1.);
+        assert_eq!(f32::NAN.to_duty(), 0.);
+    }
+
+    #[test]
+    fn milli() {
+        assert_eq!(format!("{}", Milli::new(0.)), "0.000");
+        assert_eq!(format!("{}", Milli::new(0.25)), "0.250");
+        assert_eq!(format!("{}", Milli::new(0.0125)), "0.013");
+        assert_eq!(format!("{}", Milli::new(1.)), "1.000");
+        assert_eq!(format!("{}", Milli::new(f32::NAN)), "0.000");
+    }
+
+    #[test]
+    fn compare_values() {
+        assert_eq!(compare(0., 255), 255);
+        assert_eq!(compare(1., 255), 0);
+        assert_eq!(compare(0.5, 100), 50);
+        assert_eq!(compare(0.25, 100), 75);
+        // duty cycles out of range are clamped
+        assert_eq!(compare(1.5, 100), 0);
+        assert_eq!(compare(f32::NAN, 100), 100);
+        assert_eq!(Block::Pwm0.max_compare(), 255);
+        assert_eq!(Block::Pwm2.max_compare(), 65_535);
+    }
+
+    #[test]
+    fn duty_changes() {
+        let duties = RefCell::new(Vec::new());
+        let mut handler = handler(|o: &Output| o.out_level.get_values(), Recorder(&duties));
+        let mut output = Output::default();
+        handler(&output);
+        output.out_level.add_value(51).unwrap();
+        handler(&output);
+        // the duty cycle is only set when it changes
+        handler(&output);
+        output.clear();
+        handler(&output);
+        // the last value of the port wins
+        output.out_level.add_value(0).unwrap();
+        output.out_level.add_value(255).unwrap();
+        handler(&output);
+        assert_eq!(*duties.borrow(), [0.2, 1.]);
+    }
+}
@@ -0,0 +1,354 @@
+//! Lock-free event queue between interrupt handlers and input handlers.
+//!
+//! A single flag (e.g., an `AtomicBool`) collapses several interrupts between two wake-ups
+//! into one, and it cannot carry any data. Instead, interrupt handlers can push events into
+//! an [`EventQueue`], together with the tick at which they were captured. Then, the input handler
+//! of the wait strategy pops them and injects them into the model (see [`drain`]).
+//!
+//! The queue has a fixed capacity and it only relies on atomics, so it can be placed in a `static`.
+//! Events must be timestamped with the clock of the timeline of the wait strategy, which interrupt
+//! handlers can read through a [`CaptureClock`] placed in a `static` next to the queue.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use portable_atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use xdevs::aux::Bag;
+
+/// External event captured by an interrupt handler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event<E> {
+    /// Tick of the clock at which the event was captured.
+    pub tick: u64,
+    /// Payload of the event.
+    pub payload: E,
+}
+
+/// Fixed-capacity, single-producer, single-consumer event queue.
+///
+/// It is meant to be filled from one interrupt handler and emptied from the input handler.
+/// Pushing from several contexts that may preempt each other (or popping from several contexts)
+/// is still memory safe: the preempting operation fails as if the queue was full (or empty).
+pub struct EventQueue<E, const N: usize> {
+    /// Storage of the events.
+    buffer: UnsafeCell<MaybeUninit<[Event<E>; N]>>,
+    /// Read counter, modulo `2 * N`. It is only modified by the consumer.
+    head: AtomicUsize,
+    /// Write counter, modulo `2 * N`. It is only modified by the producer.
+    tail: AtomicUsize,
+    /// It is set to `true` while an event is being pushed.
+    pushing: AtomicBool,
+    /// It is set to `true` while an event is being popped.
+    popping: AtomicBool,
+    /// Number of events that could not be pushed.
+    dropped: AtomicUsize,
+}
+
+// Events are moved from the producer to the consumer, so they must be `Send`.
+unsafe impl<E: Send, const N: usize> Sync for EventQueue<E, N> {}
+
+impl<E, const N: usize> EventQueue<E, N> {
+    /// Creates a new, empty queue.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `N` is zero.
+    pub const fn new() -> Self {
+        assert!(N > 0, "queue capacity must be greater than zero");
+        Self {
+            buffer: UnsafeCell::new(MaybeUninit::uninit()),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            pushing: AtomicBool::new(false),
+            popping: AtomicBool::new(false),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the maximum number of events in the queue.
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of events in the queue.
+    #[inline]
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        Self::distance(head, tail)
+    }
+
+    /// Returns `true` if the queue is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of events that could not be pushed because the queue was full.
+    #[inline]
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Pushes an event captured at the given tick.
+    /// If the queue is full, the event is dropped and its payload is returned.
+    pub fn push(&self, tick: u64, payload: E) -> Result<(), E> {
+        if self.pushing.swap(true, Ordering::Acquire) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return Err(payload);
+        }
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        let result = if Self::distance(head, tail) == N {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            Err(payload)
+        } else {
+            // the consumer never reads slots between tail and head
+            unsafe { self.slot(tail).write(Event { tick, payload }) };
+            self.tail.store(Self::next(tail), Ordering::Release);
+            Ok(())
+        };
+        self.pushing.store(false, Ordering::Release);
+        result
+    }
+
+    /// Pops the oldest event (if any).
+    pub fn pop(&self) -> Option<Event<E>> {
+        if self.popping.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        let event = if head == tail {
+            None
+        } else {
+            // the producer never writes slots between head and tail
+            let event = unsafe { self.slot(head).read() };
+            self.head.store(Self::next(head), Ordering::Release);
+            Some(event)
+        };
+        self.popping.store(false, Ordering::Release);
+        event
+    }
+
+    /// Returns a pointer to the slot of the given counter.
+    #[inline]
+    fn slot(&self, counter: usize) -> *mut Event<E> {
+        (self.buffer.get() as *mut Event<E>).wrapping_add(counter % N)
+    }
+
+    /// Returns the counter that follows the given one.
+    /// Counters run modulo `2 * N`, so a full queue is told apart from an empty one.
+    #[inline]
+    fn next(counter: usize) -> usize {
+        (counter + 1) % (2 * N)
+    }
+
+    /// Returns the number of events between the given read and write counters.
+    #[inline]
+    fn distance(head: usize, tail: usize) -> usize {
+        (tail + 2 * N - head) % (2 * N)
+    }
+}
+
+impl<E, const N: usize> Default for EventQueue<E, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E, const N: usize> Drop for EventQueue<E, N> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// Clock read by interrupt handlers to timestamp the events they push into a queue.
+///
+/// Interrupt handlers have no handle to the clock of the wait strategy, so it stores
+/// a function that reads it (see [`super::clock::Timestamp`]). It can be placed in a `static`.
+pub struct CaptureClock {
+    /// Function that reads the clock (null until it is set).
+    now: AtomicPtr<()>,
+}
+
+impl CaptureClock {
+    /// Creates a new capture clock with no function.
+    pub const fn new() -> Self {
+        Self {
+            now: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    /// Sets the function that reads the clock.
+    pub fn set(&self, now: fn() -> u64) {
+        self.now.store(now as *mut (), Ordering::Release);
+    }
+
+    /// Reads the clock. It returns `None` if the clock is not set yet.
+    pub fn now(&self) -> Option<u64> {
+        let now = self.now.load(Ordering::Acquire);
+        if now.is_null() {
+            return None;
+        }
+        // only `set` stores non-null pointers, and they come from a `fn() -> u64`
+        let now: fn() -> u64 = unsafe { core::mem::transmute(now) };
+        Some(now())
+    }
+}
+
+impl Default for CaptureClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Input handler that drains `queue` into the input bag.
+/// Every event is passed to `inject`, which must add it to an input port and return `true`.
+/// If `inject` returns `false` (e.g., the port is full), the event is kept for the next call.
+/// It returns the capture tick of the earliest injected event (if any),
+/// so the wait strategies can return the simulation time at which the event really happened.
+pub fn drain<'a, E: 'a, T: Bag, const N: usize>(
+    queue: &'a EventQueue<E, N>,
+    mut inject: impl FnMut(&mut T, &Event<E>) -> bool + 'a,
+) -> impl FnMut(&mut T) -> Option<u64> + 'a {
+    let mut pending = None;
+
+    move |input| -> Option<u64> {
+        let mut capture_tick: Option<u64> = None;
+        while let Some(event) = pending.take().or_else(|| queue.pop()) {
+            if !inject(input, &event) {
+                pending = Some(event);
+                break;
+            }
+            capture_tick = Some(capture_tick.map_or(event.tick, |tick| tick.min(event.tick)));
+        }
+        capture_tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rt::harness::Input;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[test]
+    fn fifo() {
+        let queue = EventQueue::<u8, 3>::new();
+        assert_eq!(queue.capacity(), 3);
+        assert!(queue.is_empty());
+        for round in 0..10 {
+            assert_eq!(queue.push(round, 1), Ok(()));
+            assert_eq!(queue.push(round + 1, 2), Ok(()));
+            assert_eq!(queue.len(), 2);
+            assert_eq!(
+                queue.pop(),
+                Some(Event {
+                    tick: round,
+                    payload: 1
+                })
+            );
+            assert_eq!(queue.pop().map(|event| event.payload), Some(2));
+            assert_eq!(queue.pop(), None);
+        }
+    }
+
+    #[test]
+    fn full() {
+        let queue = EventQueue::<u8, 2>::new();
+        assert_eq!(queue.push(0, 1), Ok(()));
+        assert_eq!(queue.push(0, 2), Ok(()));
+        assert_eq!(queue.push(0, 3), Err(3));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(queue.pop().map(|event| event.payload), Some(1));
+        assert_eq!(queue.push(0, 4), Ok(()));
+        assert_eq!(queue.pop().map(|event| event.payload), Some(2));
+        assert_eq!(queue.pop().map(|event| event.payload), Some(4));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn drop_pending_events() {
+        let payload = Rc::new(());
+        {
+            let queue = EventQueue::<_, 4>::new();
+            queue.push(0, payload.clone()).unwrap();
+            queue.push(1, payload.clone()).unwrap();
+            drop(queue.pop());
+            assert_eq!(Rc::strong_count(&payload), 2);
+        }
+        assert_eq!(Rc::strong_count(&payload), 1);
+    }
+
+    #[test]
+    fn concurrent() {
+        const N_EVENTS: u64 = 10_000;
+        let queue = Arc::new(EventQueue::<u64, 8>::new());
+        let producer = {
+            let queue = queue.clone();
+            std::thread::spawn(move || {
+                for tick in 0..N_EVENTS {
+                    while queue.push(tick, tick * 2).is_err() {
+                        std::thread::yield_now();
+                    }
+                }
+            })
+        };
+        let mut expected = 0;
+        while expected < N_EVENTS {
+            if let Some(event) = queue.pop() {
+                assert_eq!(
+                    event,
+                    Event {
+                        tick: expected,
+                        payload: expected * 2
+                    }
+                );
+                expected += 1;
+            } else {
+                std::thread::yield_now();
+            }
+        }
+        producer.join().unwrap();
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn capture_clock() {
+        static CLOCK: CaptureClock = CaptureClock::new();
+        assert_eq!(CLOCK.now(), None);
+        CLOCK.set(|| 42);
+        assert_eq!(CLOCK.now(), Some(42));
+        CLOCK.set(|| 7);
+        assert_eq!(CLOCK.now(), Some(7));
+    }
+
+    #[test]
+    fn drain_into_port() {
+        let queue = EventQueue::<u64, 4>::new();
+        // the port can only hold two values
+        let mut handler = drain(&queue, |input: &mut Input, event| {
+            input.0.len() < 2 && {
+                input.0.push(event.payload);
+                true
+            }
+        });
+        let mut input = Input::default();
+        assert_eq!(handler(&mut input), None);
+        for payload in 1..=3 {
+            queue.push(10 * payload, payload).unwrap();
+        }
+        // it reports the capture tick of the earliest injected event
+        assert_eq!(handler(&mut input), Some(10));
+        assert_eq!(input.0, [1, 2]);
+        // the third event is kept until the port is cleared
+        assert_eq!(handler(&mut input), None);
+        input.clear();
+        assert_eq!(handler(&mut input), Some(30));
+        assert_eq!(input.0, [3]);
+        assert!(queue.is_empty());
+    }
+}
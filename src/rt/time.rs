@@ -0,0 +1,352 @@
+//! Conversions between wall-clock time, simulation time, and timer ticks.
+//!
+//! Conversions from seconds to ticks saturate: negative values and NaN become `0`,
+//! while `f64::INFINITY` and values out of range become `u64::MAX`.
+//! Integer conversions never overflow, regardless of the number of ticks.
+
+use super::clock::Clock;
+
+/// Microseconds per second.
+const US_PER_SEC: u64 = 1_000_000;
+
+/// Rounding mode for conversions from seconds (or microseconds) to ticks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round towards the previous tick.
+    Floor,
+    /// Round to the nearest tick (ties round up).
+    Nearest,
+    /// Round towards the next tick. A deadline converted with this mode is never early.
+    Ceil,
+}
+
+/// Time base of a timer that counts at a fixed frequency.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeBase {
+    /// Ticks per second.
+    freq: u64,
+}
+
+impl TimeBase {
+    /// Creates a new time base for a timer counting at `freq` ticks per second.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `freq` is zero.
+    pub const fn new(freq: u64) -> Self {
+        assert!(freq > 0, "timer frequency must be greater than zero");
+        Self { freq }
+    }
+
+    /// Returns the frequency of the timer, in ticks per second.
+    #[inline]
+    pub const fn freq(&self) -> u64 {
+        self.freq
+    }
+
+    /// Converts seconds to ticks.
+    ///
+    /// Note that the product of `t` and the frequency may not be exact in `f64`
+    /// (e.g., `1.1 * 1_000_000.` is slightly above `1_100_000`), which matters for [`Rounding::Ceil`].
+    /// For power-of-two frequencies (e.g., CLINT's 32768 Hz), the product is always exact.
+    pub fn secs_to_ticks(&self, t: f64, rounding: Rounding) -> u64 {
+        let ticks = t * self.freq as f64;
+        if ticks.is_nan() || ticks <= 0. {
+            return 0;
+        }
+        // casting a float to an integer truncates towards zero and saturates at u64::MAX
+        match rounding {
+            Rounding::Floor => ticks as u64,
+            Rounding::Nearest => (ticks + 0.5) as u64,
+            Rounding::Ceil => {
+                let floor = ticks as u64;
+                if (floor as f64) < ticks {
+                    floor.saturating_add(1)
+                } else {
+                    floor
+                }
+            }
+        }
+    }
+
+    /// Converts ticks to seconds.
+    #[inline]
+    pub fn ticks_to_secs(&self, ticks: u64) -> f64 {
+        ticks as f64 / self.freq as f64
+    }
+
+    /// Converts microseconds to ticks.
+    pub fn us_to_ticks(&self, us: u64, rounding: Rounding) -> u64 {
+        let (secs, us) = (us / US_PER_SEC, us % US_PER_SEC);
+        let rem = us * self.freq; // us < 10^6, so this only overflows for absurd frequencies
+        let frac = match rounding {
+            Rounding::Floor => rem / US_PER_SEC,
+            Rounding::Nearest => (rem + US_PER_SEC / 2) / US_PER_SEC,
+            Rounding::Ceil => (rem + US_PER_SEC - 1) / US_PER_SEC,
+        };
+        secs.saturating_mul(self.freq).saturating_add(frac)
+    }
+
+    /// Converts ticks to microseconds, rounding towards zero.
+    pub fn ticks_to_us(&self, ticks: u64) -> u64 {
+        let (secs, ticks) = (ticks / self.freq, ticks % self.freq);
+        // ticks < freq, so we first scale up to keep as much precision as possible
+        let us = (ticks as u128 * US_PER_SEC as u128 / self.freq as u128) as u64;
+        secs.saturating_mul(US_PER_SEC).saturating_add(us)
+    }
+}
+
+/// What to do when a simulation step starts after its deadline (i.e., an overrun),
+/// because the previous step took longer than the gap between both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pacing {
+    /// Keep the absolute alignment with the wall clock.
+    /// Late steps run back-to-back until the simulation catches up.
+    /// They are counted as overruns, not as deadline misses.
+    CatchUp,
+    /// Shift the epoch by the overrun, so the relative spacing between steps is preserved.
+    /// The simulation drifts from the wall clock.
+    Slip,
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Self::CatchUp
+    }
+}
+
+/// Mapping between simulation time and the ticks of a timer.
+///
+/// Simulation time `t_start` corresponds to tick `epoch`, and every simulation time unit
+/// lasts `time_scale` wall-clock seconds. As all the deadlines are relative to the epoch,
+/// timers never need to be reset, and other users of the timer are not disturbed.
+/// By default, overruns are handled with [`Pacing::CatchUp`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timeline {
+    base: TimeBase,
+    epoch: u64,
+    t_start: f64,
+    time_scale: f64,
+    pacing: Pacing,
+}
+
+impl Timeline {
+    /// Creates a new timeline.
+    pub const fn new(base: TimeBase, epoch: u64, t_start: f64, time_scale: f64) -> Self {
+        Self {
+            base,
+            epoch,
+            t_start,
+            time_scale,
+            pacing: Pacing::CatchUp,
+        }
+    }
+
+    /// Sets how overruns are handled.
+    pub const fn with_pacing(mut self, pacing: Pacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// Creates a new timeline that starts now, i.e., its epoch is the current tick of `clock`.
+    /// Create it right before calling `simulate_rt`, as the simulation starts at this point.
+    pub fn start<C: Clock + ?Sized>(clock: &mut C, t_start: f64, time_scale: f64) -> Self {
+        Self::new(clock.time_base(), clock.now(), t_start, time_scale)
+    }
+
+    /// Returns the time base of the timeline.
+    #[inline]
+    pub const fn base(&self) -> TimeBase {
+        self.base
+    }
+
+    /// Returns the tick that corresponds to the start of the simulation.
+    #[inline]
+    pub const fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns the simulation time that corresponds to the epoch.
+    #[inline]
+    pub const fn t_start(&self) -> f64 {
+        self.t_start
+    }
+
+    /// Returns the wall-clock seconds per simulation time unit.
+    #[inline]
+    pub const fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    /// Returns how overruns are handled.
+    #[inline]
+    pub const fn pacing(&self) -> Pacing {
+        self.pacing
+    }
+
+    /// Returns the ticks elapsed from the epoch to the given tick.
+    /// Ticks before the epoch saturate to zero.
+    #[inline]
+    pub fn elapsed(&self, tick: u64) -> u64 {
+        tick.saturating_sub(self.epoch)
+    }
+
+    /// Shifts the epoch `ticks` forward, so the simulation does not see them (e.g., after a pause).
+    #[inline]
+    pub fn shift(&mut self, ticks: u64) {
+        self.epoch = self.epoch.saturating_add(ticks);
+    }
+
+    /// Changes the time scale from `tick` on.
+    /// The simulation time that corresponds to `tick` is preserved, so the simulation never jumps.
+    pub fn rebase(&mut self, tick: u64, time_scale: f64) {
+        self.t_start = self.tick_to_sim(tick);
+        self.epoch = u64::max(self.epoch, tick);
+        self.time_scale = time_scale;
+    }
+
+    /// Returns the first tick at which simulation time `t` has been reached.
+    /// Deadlines computed with this method are never early.
+    #[inline]
+    pub fn sim_to_tick(&self, t: f64) -> u64 {
+        let ticks = self
+            .base
+            .secs_to_ticks((t - self.t_start) * self.time_scale, Rounding::Ceil);
+        self.epoch.saturating_add(ticks)
+    }
+
+    /// Returns the simulation time that corresponds to the given tick.
+    /// Output handlers can use it to timestamp events consistently with the simulation.
+    #[inline]
+    pub fn tick_to_sim(&self, tick: u64) -> f64 {
+        self.base.ticks_to_secs(self.elapsed(tick)) / self.time_scale + self.t_start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLINT: TimeBase = TimeBase::new(32_768);
+    const MHZ: TimeBase = TimeBase::new(1_000_000);
+
+    #[test]
+    fn secs_to_ticks_keeps_fraction() {
+        // 2.1 s used to be truncated to 2 s before scaling
+        assert_eq!(CLINT.secs_to_ticks(2.1, Rounding::Floor), 68_812);
+        assert_eq!(CLINT.secs_to_ticks(2.1, Rounding::Nearest), 68_813);
+        assert_eq!(CLINT.secs_to_ticks(2.1, Rounding::Ceil), 68_813);
+        assert_eq!(CLINT.secs_to_ticks(2.0, Rounding::Ceil), 65_536);
+    }
+
+    #[test]
+    fn secs_to_ticks_millis() {
+        assert_eq!(CLINT.secs_to_ticks(0.001, Rounding::Floor), 32);
+        assert_eq!(CLINT.secs_to_ticks(0.001, Rounding::Nearest), 33);
+        assert_eq!(CLINT.secs_to_ticks(0.001, Rounding::Ceil), 33);
+        assert_eq!(CLINT.secs_to_ticks(1.5, Rounding::Ceil), 49_152);
+        assert_eq!(MHZ.secs_to_ticks(0.001, Rounding::Nearest), 1_000);
+        assert_eq!(MHZ.secs_to_ticks(12.345, Rounding::Nearest), 12_345_000);
+    }
+
+    #[test]
+    fn secs_to_ticks_micros() {
+        assert_eq!(CLINT.secs_to_ticks(0.000_001, Rounding::Floor), 0);
+        assert_eq!(CLINT.secs_to_ticks(0.000_001, Rounding::Ceil), 1);
+        assert_eq!(CLINT.secs_to_ticks(0.000_031, Rounding::Nearest), 1);
+        assert_eq!(MHZ.secs_to_ticks(0.000_001, Rounding::Nearest), 1);
+        assert_eq!(MHZ.secs_to_ticks(1.000_001, Rounding::Nearest), 1_000_001);
+        assert_eq!(MHZ.secs_to_ticks(1.1, Rounding::Floor), 1_100_000);
+    }
+
+    #[test]
+    fn secs_to_ticks_saturates() {
+        for rounding in [Rounding::Floor, Rounding::Nearest, Rounding::Ceil] {
+            assert_eq!(CLINT.secs_to_ticks(f64::INFINITY, rounding), u64::MAX);
+            assert_eq!(CLINT.secs_to_ticks(1e300, rounding), u64::MAX);
+            assert_eq!(CLINT.secs_to_ticks(f64::NEG_INFINITY, rounding), 0);
+            assert_eq!(CLINT.secs_to_ticks(-2.1, rounding), 0);
+            assert_eq!(CLINT.secs_to_ticks(f64::NAN, rounding), 0);
+        }
+    }
+
+    #[test]
+    fn ticks_to_secs() {
+        assert_eq!(CLINT.ticks_to_secs(0), 0.);
+        assert_eq!(CLINT.ticks_to_secs(65_536), 2.);
+        assert_eq!(CLINT.ticks_to_secs(16_384), 0.5);
+        assert_eq!(MHZ.ticks_to_secs(1_500), 0.0015);
+    }
+
+    #[test]
+    fn us_to_ticks() {
+        assert_eq!(CLINT.us_to_ticks(1_000, Rounding::Floor), 32);
+        assert_eq!(CLINT.us_to_ticks(1_000, Rounding::Nearest), 33);
+        assert_eq!(CLINT.us_to_ticks(1_000, Rounding::Ceil), 33);
+        assert_eq!(CLINT.us_to_ticks(1, Rounding::Floor), 0);
+        assert_eq!(CLINT.us_to_ticks(1, Rounding::Ceil), 1);
+        assert_eq!(CLINT.us_to_ticks(2_100_000, Rounding::Floor), 68_812);
+        assert_eq!(MHZ.us_to_ticks(1_234_567, Rounding::Floor), 1_234_567);
+        assert_eq!(MHZ.us_to_ticks(u64::MAX, Rounding::Ceil), u64::MAX);
+    }
+
+    #[test]
+    fn ticks_to_us() {
+        assert_eq!(CLINT.ticks_to_us(1), 30);
+        assert_eq!(CLINT.ticks_to_us(33), 1_007);
+        assert_eq!(CLINT.ticks_to_us(68_813), 2_100_006);
+        assert_eq!(MHZ.ticks_to_us(1_234_567), 1_234_567);
+        assert_eq!(MHZ.ticks_to_us(u64::MAX), u64::MAX);
+        assert_eq!(CLINT.ticks_to_us(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn timeline() {
+        let timeline = Timeline::new(CLINT, 0, 10., 0.5);
+        assert_eq!(timeline.sim_to_tick(10.), 0);
+        assert_eq!(timeline.sim_to_tick(12.), 32_768);
+        assert_eq!(timeline.sim_to_tick(14.2), 68_813);
+        assert_eq!(timeline.sim_to_tick(5.), 0);
+        assert_eq!(timeline.sim_to_tick(f64::INFINITY), u64::MAX);
+        assert_eq!(timeline.tick_to_sim(0), 10.);
+        assert_eq!(timeline.tick_to_sim(32_768), 12.);
+        assert!(timeline.tick_to_sim(68_813) >= 14.2);
+    }
+
+    #[test]
+    fn timeline_epoch() {
+        let epoch = u32::MAX as u64 - 100;
+        let timeline = Timeline::new(CLINT, epoch, 10., 0.5);
+        assert_eq!(timeline.epoch(), epoch);
+        assert_eq!(timeline.sim_to_tick(10.), epoch);
+        assert_eq!(timeline.sim_to_tick(12.), epoch + 32_768);
+        assert_eq!(timeline.sim_to_tick(5.), epoch);
+        assert_eq!(timeline.sim_to_tick(f64::INFINITY), u64::MAX);
+        assert_eq!(timeline.elapsed(epoch + 32_768), 32_768);
+        assert_eq!(timeline.elapsed(0), 0);
+        assert_eq!(timeline.tick_to_sim(epoch), 10.);
+        assert_eq!(timeline.tick_to_sim(epoch + 32_768), 12.);
+        // ticks before the epoch belong to the start of the simulation
+        assert_eq!(timeline.tick_to_sim(0), 10.);
+    }
+
+    #[test]
+    fn timeline_shift_and_rebase() {
+        let mut timeline = Timeline::new(CLINT, 1_000, 0., 1.);
+        timeline.shift(32_768);
+        assert_eq!(timeline.epoch(), 33_768);
+        assert_eq!(timeline.sim_to_tick(1.), 66_536);
+        // from simulation time 1 on, every time unit lasts 2 seconds
+        timeline.rebase(66_536, 2.);
+        assert_eq!(timeline.epoch(), 66_536);
+        assert_eq!(timeline.t_start(), 1.);
+        assert_eq!(timeline.time_scale(), 2.);
+        assert_eq!(timeline.tick_to_sim(66_536), 1.);
+        assert_eq!(timeline.sim_to_tick(2.), 66_536 + 65_536);
+        // rebasing before the epoch keeps the start of the simulation
+        timeline.rebase(0, 0.5);
+        assert_eq!(timeline.epoch(), 66_536);
+        assert_eq!(timeline.t_start(), 1.);
+        assert_eq!(timeline.sim_to_tick(2.), 66_536 + 16_384);
+    }
+}
@@ -0,0 +1,629 @@
+//! UART receive path as a source of external events.
+//!
+//! [`SerialInputs`] maps the input ports of the model to names of a simple line protocol:
+//! every line holds the name of a port and the value to inject, separated by whitespace
+//! (e.g., `in_job 42`). Values are parsed by the injection of the port (see [`parse`]).
+//! Invalid lines (e.g., unknown ports or values that cannot be parsed) are logged and dropped.
+//!
+//! On the board, the UART interrupt handler pushes every received byte into a queue,
+//! together with the tick at which it arrived (read from the clock of the wait strategy),
+//! and [`SerialInputs::enable`] returns the input handler of the wait strategy.
+//! Lines are timestamped when their end of line arrives, so strategies that wake up early
+//! (e.g., [`super::wait_exti`]) return the time of the command. If the queue overflows,
+//! the handler marks the gap with [`Rx::Lost`], and the line that contains it is dropped.
+//! UART handlers are generated with [`crate::uart_handler`]:
+//!
+//! ```ignore
+//! riscv_xdevs::uart_handler!(UART0);
+//!
+//! let mut on_job = rt::uart::parse(|input: &mut PTInput, job| input.in_job.add_value(job).is_ok());
+//! let inputs = SerialInputs::new().port("in_job", &mut on_job);
+//! // machine interrupts are enabled later, by the wait strategy
+//! let handler = unsafe { inputs.enable(Uart::Uart0, Priority::P1, &clock) };
+//! ```
+
+use super::queue::EventQueue;
+use core::str::FromStr;
+use xdevs::aux::Bag;
+
+#[cfg(target_arch = "riscv32")]
+use super::{clock::Timestamp, queue::CaptureClock};
+#[cfg(target_arch = "riscv32")]
+use hifive1::hal::e310x::{Interrupt, Priority, PLIC};
+#[cfg(target_arch = "riscv32")]
+use portable_atomic::{AtomicBool, Ordering};
+
+/// Maximum length of a line, in bytes. Longer lines are dropped.
+pub const MAX_LINE: usize = 64;
+/// Maximum number of ports of a [`SerialInputs`].
+pub const MAX_PORTS: usize = 8;
+
+/// Item received by a UART interrupt handler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rx {
+    /// Received byte.
+    Byte(u8),
+    /// One or more bytes were dropped here because the queue was full.
+    Lost,
+}
+
+/// Reason why a value could not be injected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InjectError {
+    /// The port is full. The line is injected again in the next call of the input handler.
+    Full,
+    /// The value is invalid. The line is dropped.
+    Invalid,
+}
+
+/// Closure that injects the value of a line into the input bag.
+pub type Inject<'a, T> = &'a mut dyn FnMut(&mut T, &str) -> Result<(), InjectError>;
+
+/// Returns an injection that parses the value of a line and passes it to `add`,
+/// which must add it to an input port and return `true` (or `false` if the port is full).
+pub fn parse<T, V: FromStr>(
+    mut add: impl FnMut(&mut T, V) -> bool,
+) -> impl FnMut(&mut T, &str) -> Result<(), InjectError> {
+    move |input, value| {
+        let value = value.parse().map_err(|_| InjectError::Invalid)?;
+        match add(input, value) {
+            true => Ok(()),
+            false => Err(InjectError::Full),
+        }
+    }
+}
+
+/// Line being received.
+struct Line {
+    /// Bytes of the line.
+    buf: [u8; MAX_LINE],
+    /// Number of bytes of the line.
+    len: usize,
+    /// It is set to `true` if the line is longer than [`MAX_LINE`].
+    overflow: bool,
+    /// It is set to `true` if bytes of the line were lost.
+    lost: bool,
+}
+
+impl Line {
+    /// Creates a new, empty line.
+    const fn new() -> Self {
+        Self {
+            buf: [0; MAX_LINE],
+            len: 0,
+            overflow: false,
+            lost: false,
+        }
+    }
+
+    /// Appends a byte to the line.
+    fn push(&mut self, byte: u8) {
+        match self.buf.get_mut(self.len) {
+            Some(slot) => {
+                *slot = byte;
+                self.len += 1;
+            }
+            None => self.overflow = true,
+        }
+    }
+
+    /// Empties the line.
+    fn clear(&mut self) {
+        self.len = 0;
+        self.overflow = false;
+        self.lost = false;
+    }
+}
+
+/// Builder of the mapping between the line protocol and the input ports of the model.
+pub struct SerialInputs<'a, T> {
+    /// Names and injections of the ports.
+    ports: [Option<(&'a str, Inject<'a, T>)>; MAX_PORTS],
+}
+
+impl<'a, T: Bag> SerialInputs<'a, T> {
+    /// Creates a new builder with no ports.
+    pub fn new() -> Self {
+        Self {
+            ports: Default::default(),
+        }
+    }
+
+    /// Maps a port name of the line protocol to an injection.
+    ///
+    /// # Panics
+    ///
+    /// It panics if there are already [`MAX_PORTS`] ports or if the name is already mapped.
+    pub fn port(mut self, name: &'a str, inject: Inject<'a, T>) -> Self {
+        assert!(
+            !self.ports.iter().flatten().any(|(n, _)| *n == name),
+            "serial port name already mapped"
+        );
+        let port = (self.ports.iter_mut())
+            .find(|port| port.is_none())
+            .expect("too many serial ports");
+        *port = Some((name, inject));
+        self
+    }
+
+    /// Injects a line. Empty lines are ignored.
+    fn inject(&mut self, input: &mut T, line: &Line) -> Result<bool, InjectError> {
+        if line.overflow {
+            return Err(InjectError::Invalid);
+        }
+        let line = core::str::from_utf8(&line.buf[..line.len]).map_err(|_| InjectError::Invalid)?;
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(false);
+        }
+        let (name, value) = match line.find(char::is_whitespace) {
+            Some(split) => (&line[..split], line[split..].trim_start()),
+            None => (line, ""),
+        };
+        let (_, inject) = (self.ports.iter_mut().flatten())
+            .find(|(n, _)| *n == name)
+            .ok_or(InjectError::Invalid)?;
+        inject(input, value).map(|_| true)
+    }
+
+    /// Returns an input handler that parses the bytes in `queue` and injects the resulting lines.
+    /// If a port is full, the line is kept for the next call.
+    /// Lines with lost bytes (see [`Rx::Lost`]) are logged as a warning and dropped.
+    /// As [`super::queue::drain`], it returns the capture tick of the earliest injected line (if any),
+    /// which is the tick at which its end of line was received.
+    pub fn drain<const N: usize>(
+        mut self,
+        queue: &'a EventQueue<Rx, N>,
+    ) -> impl FnMut(&mut T) -> Option<u64> + 'a
+    where
+        T: 'a,
+    {
+        let mut line = Line::new();
+        let mut pending: Option<u64> = None;
+
+        move |input| -> Option<u64> {
+            let mut capture_tick: Option<u64> = None;
+            loop {
+                let tick = match pending.take() {
+                    Some(tick) => tick,
+                    None => match queue.pop() {
+                        Some(event) => match event.payload {
+                            Rx::Byte(b'\n' | b'\r') => event.tick,
+                            Rx::Byte(byte) => {
+                                line.push(byte);
+                                continue;
+                            }
+                            Rx::Lost => {
+                                line.lost = true;
+                                continue;
+                            }
+                        },
+                        None => break,
+                    },
+                };
+                if line.lost {
+                    crate::warn!(super::log::RT, "line dropped (UART bytes lost)");
+                    line.clear();
+                    continue;
+                }
+                match self.inject(input, &line) {
+                    Ok(true) => {
+                        capture_tick = Some(capture_tick.map_or(tick, |t| t.min(tick)));
+                    }
+                    Ok(false) => {}
+                    Err(InjectError::Full) => {
+                        pending = Some(tick);
+                        break;
+                    }
+                    Err(InjectError::Invalid) => {
+                        let text = core::str::from_utf8(&line.buf[..line.len]).unwrap_or("?");
+                        crate::warn!(super::log::RT, "invalid line: {}", text);
+                    }
+                }
+                line.clear();
+            }
+            capture_tick
+        }
+    }
+}
+
+impl<'a, T: Bag> Default for SerialInputs<'a, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// UARTs of the E310x.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Uart {
+    /// UART0 (connected to the USB debugger of the board, and to the standard input of QEMU).
+    Uart0,
+    /// UART1.
+    Uart1,
+}
+
+impl Uart {
+    /// Returns the base address of the registers of the UART.
+    pub const fn address(self) -> usize {
+        match self {
+            Self::Uart0 => 0x1001_3000,
+            Self::Uart1 => 0x1002_3000,
+        }
+    }
+}
+
+/// Offset of the receive data register.
+#[cfg(target_arch = "riscv32")]
+const RXDATA: usize = 0x04;
+/// Offset of the receive control register.
+#[cfg(target_arch = "riscv32")]
+const RXCTRL: usize = 0x0C;
+/// Offset of the transmit control register.
+#[cfg(target_arch = "riscv32")]
+const TXCTRL: usize = 0x08;
+/// Offset of the interrupt enable register.
+#[cfg(target_arch = "riscv32")]
+const IE: usize = 0x10;
+/// Offset of the transmit data register.
+#[cfg(target_arch = "riscv32")]
+const TXDATA: usize = 0x00;
+/// Flag of the transmit data register that tells that the transmit FIFO is full.
+#[cfg(target_arch = "riscv32")]
+const TXDATA_FULL: u32 = 1 << 31;
+/// Flag of the receive data register that tells that the receive FIFO is empty.
+#[cfg(target_arch = "riscv32")]
+const RXDATA_EMPTY: u32 = 1 << 31;
+/// Bit of the receive control register that enables the receiver.
+#[cfg(target_arch = "riscv32")]
+const RXCTRL_RXEN: u32 = 1 << 0;
+/// Bit of the interrupt enable register that enables the receive watermark interrupt.
+#[cfg(target_arch = "riscv32")]
+const IE_RXWM: u32 = 1 << 1;
+/// Bit of the interrupt enable register that enables the transmit watermark interrupt.
+#[cfg(target_arch = "riscv32")]
+const IE_TXWM: u32 = 1 << 0;
+/// Mask of the transmit watermark field of the transmit control register.
+#[cfg(target_arch = "riscv32")]
+const TXCTRL_TXCNT: u32 = 0b111 << 16;
+/// Transmit watermark: the interrupt is pending while the transmit FIFO has less than 4 bytes.
+#[cfg(target_arch = "riscv32")]
+const TXCNT: u32 = 4 << 16;
+
+/// Bytes received by the UART0 interrupt handler.
+#[cfg(target_arch = "riscv32")]
+static RX0: EventQueue<Rx, 64> = EventQueue::new();
+/// Bytes received by the UART1 interrupt handler.
+#[cfg(target_arch = "riscv32")]
+static RX1: EventQueue<Rx, 64> = EventQueue::new();
+/// It is set to `true` when a byte of UART0 is dropped, until the gap is marked in its queue.
+#[cfg(target_arch = "riscv32")]
+static LOST0: AtomicBool = AtomicBool::new(false);
+/// It is set to `true` when a byte of UART1 is dropped, until the gap is marked in its queue.
+#[cfg(target_arch = "riscv32")]
+static LOST1: AtomicBool = AtomicBool::new(false);
+/// Clock that timestamps the received bytes (i.e., the clock of the wait strategy).
+#[cfg(target_arch = "riscv32")]
+static CLOCK: CaptureClock = CaptureClock::new();
+
+#[cfg(target_arch = "riscv32")]
+impl Uart {
+    /// Returns the queue of received bytes.
+    fn queue(self) -> &'static EventQueue<Rx, 64> {
+        match self {
+            Self::Uart0 => &RX0,
+            Self::Uart1 => &RX1,
+        }
+    }
+
+    /// Returns the flag that tells that a gap must be marked in the queue of received bytes.
+    fn lost(self) -> &'static AtomicBool {
+        match self {
+            Self::Uart0 => &LOST0,
+            Self::Uart1 => &LOST1,
+        }
+    }
+
+    /// Returns the PLIC interrupt source of the UART.
+    fn interrupt(self) -> Interrupt {
+        match self {
+            Self::Uart0 => Interrupt::UART0,
+            Self::Uart1 => Interrupt::UART1,
+        }
+    }
+
+    /// Returns a pointer to a register of the UART.
+    fn register(self, offset: usize) -> *mut u32 {
+        (self.address() + offset) as *mut u32
+    }
+
+    /// Enables the receiver of the UART and its interrupt, with the given PLIC priority.
+    /// Received bytes are timestamped with `clock`, which must be the clock of the wait strategy.
+    /// It returns the queue where the interrupt handler pushes the received bytes.
+    ///
+    /// The baud rate and the pins of the UART must already be configured
+    /// (e.g., with `hifive1::stdout::configure`), and its interrupt handler must be generated
+    /// with [`crate::uart_handler`]. Machine interrupts are not enabled.
+    /// The receive and transmit interrupts of the UART share one PLIC source,
+    /// so the last call of [`Uart::listen`] or [`Uart::enable_tx`] sets the priority of both.
+    ///
+    /// # Safety
+    ///
+    /// The caller must own the receiver of the UART: no other code (e.g., a HAL serial driver)
+    /// may configure it. The registers of the UART are read, modified, and written back,
+    /// so it must be called before machine interrupts are enabled.
+    pub unsafe fn listen<C: Timestamp>(
+        self,
+        priority: Priority,
+        _clock: &C,
+    ) -> &'static EventQueue<Rx, 64> {
+        CLOCK.set(C::timestamp);
+        // interrupt as soon as there is one byte in the receive FIFO (watermark 0)
+        self.register(RXCTRL).write_volatile(RXCTRL_RXEN);
+        let ie = self.register(IE);
+        ie.write_volatile(ie.read_volatile() | IE_RXWM);
+        PLIC::priorities().set_priority(self.interrupt(), priority);
+        PLIC::ctx0().enables().enable(self.interrupt());
+        PLIC::enable();
+        self.queue()
+    }
+
+    /// Sets the transmit watermark and enables the interrupt of the UART in the PLIC,
+    /// with the given priority. The transmit interrupt itself is enabled with
+    /// [`Uart::set_tx_interrupt`] when there is something to send.
+    ///
+    /// The transmitter must already be configured (e.g., with `hifive1::stdout::configure`),
+    /// and the interrupt handler must be generated with [`crate::uart_handler`].
+    /// Machine interrupts are not enabled. As in [`Uart::listen`], `priority` is shared
+    /// with the receive interrupt.
+    ///
+    /// # Safety
+    ///
+    /// The caller must own the transmitter of the UART: no other code may configure it.
+    /// The registers of the UART are read, modified, and written back,
+    /// so it must be called before machine interrupts are enabled.
+    pub unsafe fn enable_tx(self, priority: Priority) {
+        let txctrl = self.register(TXCTRL);
+        txctrl.write_volatile(txctrl.read_volatile() & !TXCTRL_TXCNT | TXCNT);
+        PLIC::priorities().set_priority(self.interrupt(), priority);
+        PLIC::ctx0().enables().enable(self.interrupt());
+        PLIC::enable();
+    }
+
+    /// Enables or disables the transmit watermark interrupt of the UART.
+    ///
+    /// # Safety
+    ///
+    /// The interrupt enable register is read, modified, and written back, so the caller must own
+    /// the transmit interrupt, and no other bit of the register may change meanwhile
+    /// (i.e., it must not preempt [`Uart::listen`] or be preempted by it).
+    pub unsafe fn set_tx_interrupt(self, enabled: bool) {
+        let ie = self.register(IE);
+        let bits = ie.read_volatile();
+        ie.write_volatile(match enabled {
+            true => bits | IE_TXWM,
+            false => bits & !IE_TXWM,
+        });
+    }
+
+    /// Returns the number of bytes dropped because the queue of received bytes was full.
+    pub fn dropped(self) -> usize {
+        self.queue().dropped()
+    }
+
+    /// Returns `true` if the transmit FIFO of the UART is full.
+    pub fn tx_full(self) -> bool {
+        unsafe { self.register(TXDATA).read_volatile() & TXDATA_FULL != 0 }
+    }
+
+    /// Sends `bytes` through the UART, blocking while its transmit FIFO is full.
+    /// The transmitter must already be configured (e.g., with `hifive1::stdout::configure`).
+    ///
+    /// # Safety
+    ///
+    /// The caller must own the transmitter of the UART: no other code (e.g., the log buffer
+    /// of the `log-buffer` feature or a HAL serial driver) may write to it concurrently.
+    pub unsafe fn write(self, bytes: &[u8]) {
+        let txdata = self.register(TXDATA);
+        for &byte in bytes {
+            while self.tx_full() {}
+            txdata.write_volatile(byte as u32);
+        }
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+impl<'a, T: Bag + 'a> SerialInputs<'a, T> {
+    /// Enables the receiver of `uart` and its interrupt, with the given PLIC priority.
+    /// Received bytes are timestamped with `clock`, which must be the clock of the wait strategy.
+    /// It returns the input handler of the wait strategy (see [`Uart::listen`]).
+    ///
+    /// # Safety
+    ///
+    /// The caller must uphold the contract of [`Uart::listen`].
+    pub unsafe fn enable<C: Timestamp>(
+        self,
+        uart: Uart,
+        priority: Priority,
+        clock: &C,
+    ) -> impl FnMut(&mut T) -> Option<u64> + 'a {
+        self.drain(uart.listen(priority, clock))
+    }
+}
+
+/// Timestamps all the bytes in the receive FIFO of `uart`. Reading them clears the interrupt.
+/// If the queue is full, bytes are dropped (see [`Uart::dropped`]), and the gap is marked
+/// with [`Rx::Lost`] before the next byte that fits.
+/// With the `log-buffer` feature, it also sends the buffered log of UART0 (see [`super::log`]).
+/// It is called from the interrupt handlers generated with [`crate::uart_handler`].
+#[cfg(target_arch = "riscv32")]
+pub fn dispatch(uart: Uart) {
+    // the clock is set before the interrupt is enabled
+    let tick = CLOCK.now().unwrap_or_default();
+    let (queue, lost) = (uart.queue(), uart.lost());
+    loop {
+        let rxdata = unsafe { uart.register(RXDATA).read_volatile() };
+        if rxdata & RXDATA_EMPTY != 0 {
+            break;
+        }
+        if lost.load(Ordering::Relaxed) {
+            if queue.push(tick, Rx::Lost).is_err() {
+                continue;
+            }
+            lost.store(false, Ordering::Relaxed);
+        }
+        if queue.push(tick, Rx::Byte(rxdata as u8)).is_err() {
+            lost.store(true, Ordering::Relaxed);
+        }
+    }
+    #[cfg(feature = "log-buffer")]
+    if uart == Uart::Uart0 {
+        super::log::transmit();
+    }
+}
+
+/// Generates the interrupt handler of the given UART (`UART0` or `UART1`),
+/// which forwards the received bytes to [`SerialInputs`].
+///
+/// ```ignore
+/// riscv_xdevs::uart_handler!(UART0);
+/// ```
+#[macro_export]
+macro_rules! uart_handler {
+    (UART0) => {
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        fn UART0() {
+            $crate::rt::uart::dispatch($crate::rt::uart::Uart::Uart0);
+        }
+    };
+    (UART1) => {
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        fn UART1() {
+            $crate::rt::uart::dispatch($crate::rt::uart::Uart::Uart1);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rt::harness::Input;
+
+    /// Pushes the bytes of `text` into `queue`, all of them received at `tick`.
+    fn send<const N: usize>(queue: &EventQueue<Rx, N>, tick: u64, text: &str) {
+        for &byte in text.as_bytes() {
+            queue.push(tick, Rx::Byte(byte)).unwrap();
+        }
+    }
+
+    #[test]
+    fn parse_values() {
+        let mut inject = parse(|input: &mut Input, value: u64| {
+            input.0.len() < 2 && {
+                input.0.push(value);
+                true
+            }
+        });
+        let mut input = Input::default();
+        assert_eq!(inject(&mut input, "42"), Ok(()));
+        assert_eq!(inject(&mut input, "forty-two"), Err(InjectError::Invalid));
+        assert_eq!(inject(&mut input, "7"), Ok(()));
+        assert_eq!(inject(&mut input, "8"), Err(InjectError::Full));
+        assert_eq!(input.0, [42, 7]);
+    }
+
+    #[test]
+    #[should_panic(expected = "serial port name already mapped")]
+    fn port_already_mapped() {
+        let mut job = parse(|_: &mut Input, _: u64| true);
+        let mut stop = parse(|_: &mut Input, _: bool| true);
+        let _ = SerialInputs::new()
+            .port("in_job", &mut job)
+            .port("in_job", &mut stop);
+    }
+
+    #[test]
+    fn drain_lines() {
+        let queue = EventQueue::<Rx, 64>::new();
+        let mut job = parse(|input: &mut Input, job: u64| {
+            input.0.len() < 2 && {
+                input.0.push(job);
+                true
+            }
+        });
+        let mut stop = parse(|input: &mut Input, stop: bool| {
+            input.0.push(stop as u64 + 100);
+            true
+        });
+        let mut handler = SerialInputs::new()
+            .port("in_job", &mut job)
+            .port("in_stop", &mut stop)
+            .drain(&queue);
+        let mut input = Input::default();
+        assert_eq!(handler(&mut input), None);
+
+        // lines are only injected once they are complete
+        send(&queue, 10, "in_job 4");
+        assert_eq!(handler(&mut input), None);
+        send(&queue, 20, "2\r\n");
+        send(&queue, 30, "  in_stop   true \n");
+        assert_eq!(handler(&mut input), Some(20));
+        assert_eq!(input.0, [42, 101]);
+        input.clear();
+
+        // invalid lines are dropped
+        send(&queue, 40, "in_job x\nout_job 1\n\n");
+        assert_eq!(handler(&mut input), None);
+        assert!(input.is_empty());
+
+        // lines are kept while their port is full
+        send(&queue, 50, "in_job 1\nin_job 2\nin_job 3\n");
+        assert_eq!(handler(&mut input), Some(50));
+        assert_eq!(input.0, [1, 2]);
+        assert_eq!(handler(&mut input), None);
+        input.clear();
+        assert_eq!(handler(&mut input), Some(50));
+        assert_eq!(input.0, [3]);
+    }
+
+    #[test]
+    fn long_lines() {
+        let queue = EventQueue::<Rx, 128>::new();
+        let mut job = parse(|input: &mut Input, job: u64| {
+            input.0.push(job);
+            true
+        });
+        let mut handler = SerialInputs::new().port("in_job", &mut job).drain(&queue);
+        let mut input = Input::default();
+        let long = "in_job 1".repeat(MAX_LINE / 8 + 1);
+        send(&queue, 10, &long);
+        send(&queue, 10, "\nin_job 5\n");
+        assert_eq!(handler(&mut input), Some(10));
+        assert_eq!(input.0, [5]);
+    }
+
+    #[test]
+    fn lost_bytes() {
+        let queue = EventQueue::<Rx, 64>::new();
+        let mut job = parse(|input: &mut Input, job: u64| {
+            input.0.push(job);
+            true
+        });
+        let mut handler = SerialInputs::new().port("in_job", &mut job).drain(&queue);
+        let mut input = Input::default();
+
+        // the line with the gap is dropped, even if the gap hides its end of line
+        send(&queue, 10, "in_job 1");
+        queue.push(20, Rx::Lost).unwrap();
+        send(&queue, 20, "2\nin_job 3\n");
+        assert_eq!(handler(&mut input), Some(20));
+        assert_eq!(input.0, [3]);
+        input.clear();
+
+        // the next lines are injected again
+        send(&queue, 30, "in_job 4\n");
+        assert_eq!(handler(&mut input), Some(30));
+        assert_eq!(input.0, [4]);
+    }
+}
@@ -0,0 +1,1118 @@
+use super::clock::{Alarm, Clock};
+use super::control::Control;
+use super::deadline::Deadline;
+use super::jitter::{JitterStats, StepTiming};
+use super::output::Deferred;
+use super::time::{Pacing, Rounding, Timeline};
+use xdevs::aux::Bag;
+
+/// Outcome of an input handler.
+///
+/// Input handlers that only tell whether they injected any event return a `bool`.
+/// Input handlers that also know when the events were captured (e.g., in an interrupt handler)
+/// return the capture tick of the earliest injected event, or `None` if they injected nothing.
+/// Then, the wait strategy returns the simulation time of the capture instead of the wake-up time,
+/// so the elapsed time seen by `delta_ext` does not include the wake-up latency.
+pub trait Injection {
+    /// Returns `true` if at least one event was injected.
+    fn injected(&self) -> bool;
+
+    /// Returns the tick at which the earliest injected event was captured (if known).
+    fn capture_tick(&self) -> Option<u64>;
+}
+
+impl Injection for bool {
+    #[inline]
+    fn injected(&self) -> bool {
+        *self
+    }
+
+    #[inline]
+    fn capture_tick(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl Injection for Option<u64> {
+    #[inline]
+    fn injected(&self) -> bool {
+        self.is_some()
+    }
+
+    #[inline]
+    fn capture_tick(&self) -> Option<u64> {
+        *self
+    }
+}
+
+/// Input handler for models that do not receive external events.
+#[inline]
+pub fn no_input<T: Bag>(_input: &mut T) -> bool {
+    false
+}
+
+/// Idles an alarm-based strategy while the simulation is paused.
+/// It wakes up at `deferred_tick` (if any), so deferred actions still run on time.
+fn sleep<A: Alarm>(clock: &mut A, deferred_tick: Option<u64>) {
+    match deferred_tick {
+        Some(tick) => clock.arm(tick),
+        None => clock.disarm(),
+    }
+    clock.wait_for_interrupt();
+}
+
+/// Idles a polling strategy while the simulation is paused.
+fn spin<C: Clock>(clock: &mut C, _deferred_tick: Option<u64>) {
+    clock.now();
+}
+
+/// Optional features shared by all the wait strategies.
+///
+/// By default, the simulation cannot be controlled at runtime, there are no deferred actions,
+/// deadlines are never missed, and the jitter of the steps is not recorded.
+///
+/// ```ignore
+/// let options = WaitOptions::new().with_deadline(deadline).with_stats(&mut stats);
+/// let wait = rt::wait_exti(clock, timeline, options, input_handler);
+/// ```
+pub struct WaitOptions<'a, T> {
+    /// Handle to pause, resume, and change the time scale of the simulation.
+    control: Option<&'a Control>,
+    /// Actions to run on time while waiting for the next simulation step.
+    deferred: Option<&'a dyn Deferred>,
+    /// Maximum jitter allowed and what to do when a step misses it.
+    deadline: Deadline<'a, T>,
+    /// Collector of the jitter, the compute time, and the overruns of the steps.
+    stats: Option<&'a mut JitterStats>,
+    /// Callback that receives the timing of every step.
+    steps: Option<&'a mut dyn FnMut(StepTiming)>,
+}
+
+impl<'a, T: Bag> WaitOptions<'a, T> {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self {
+            control: None,
+            deferred: None,
+            deadline: Deadline::none(),
+            stats: None,
+            steps: None,
+        }
+    }
+
+    /// Sets the [`Control`] handle that can pause, resume, and change the time scale
+    /// of the simulation at runtime.
+    pub fn with_control(mut self, control: &'a Control) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// Sets the [`Deferred`] actions (e.g., the end of a pulse) to run on time while waiting
+    /// for the next simulation step, as part of the same timer schedule.
+    pub fn with_deferred(mut self, deferred: &'a dyn Deferred) -> Self {
+        self.deferred = Some(deferred);
+        self
+    }
+
+    /// Sets the maximum jitter allowed and what to do when a step misses it.
+    pub fn with_deadline(mut self, deadline: Deadline<'a, T>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Sets the [`JitterStats`] collector of the jitter of every step, the time the simulator
+    /// spent computing it, and the overruns.
+    pub fn with_stats(mut self, stats: &'a mut JitterStats) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Sets a callback that receives the [`StepTiming`] of every step once the wait strategy
+    /// returns (e.g., to keep the last steps in a [`super::jitter::StepLog`]).
+    pub fn with_steps(mut self, steps: &'a mut dyn FnMut(StepTiming)) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+}
+
+impl<'a, T: Bag> Default for WaitOptions<'a, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Jitter bookkeeping shared by all the wait strategies.
+struct Monitor<'a, T> {
+    timeline: Timeline,
+    control: Option<&'a Control>,
+    deferred: Option<&'a dyn Deferred>,
+    deadline: Deadline<'a, T>,
+    stats: Option<&'a mut JitterStats>,
+    steps: Option<&'a mut dyn FnMut(StepTiming)>,
+    /// Timing of the current step (only tracked with stats or steps).
+    step: StepTiming,
+    /// Tick at which the wait strategy returned for the last time (only tracked with stats or steps).
+    returned_at: Option<u64>,
+    /// Simulation time that the wait strategy returned for the last time.
+    t_last: f64,
+}
+
+impl<'a, T: Bag> Monitor<'a, T> {
+    /// Creates a new monitor for the given clock.
+    ///
+    /// # Panics
+    ///
+    /// It panics if the time base of `timeline` does not match the frequency of `clock`.
+    fn new<C: Clock>(clock: &C, timeline: Timeline, options: WaitOptions<'a, T>) -> Self {
+        assert_eq!(
+            timeline.base(),
+            clock.time_base(),
+            "timeline does not match the clock"
+        );
+        Self {
+            timeline,
+            control: options.control,
+            deferred: options.deferred,
+            deadline: options.deadline,
+            stats: options.stats,
+            steps: options.steps,
+            step: StepTiming::default(),
+            returned_at: None,
+            t_last: timeline.t_start(),
+        }
+    }
+
+    /// Applies the pending commands of the control handle (if any).
+    /// While the simulation is paused, it calls `idle` in a loop with the tick of the next deferred action.
+    /// Deferred actions follow the wall clock, so they keep running on time during the pause
+    /// (e.g., a pulse ends when it was scheduled to, not after the simulation resumes).
+    /// It returns `true` if the timeline changed, so the wait strategy must recompute its deadlines.
+    fn sync<C: Clock>(&mut self, clock: &mut C, idle: fn(&mut C, Option<u64>)) -> bool {
+        let control = match self.control {
+            Some(control) => control,
+            None => return false,
+        };
+        let mut rebased = false;
+        if control.is_paused() {
+            // the model must not see the paused interval
+            let paused_at = clock.now();
+            while control.is_paused() {
+                idle(
+                    clock,
+                    self.deferred.and_then(|deferred| deferred.next_tick()),
+                );
+                self.run_deferred(clock);
+            }
+            self.timeline.shift(clock.now() - paused_at);
+            rebased = true;
+        }
+        if let Some(time_scale) = control.take_time_scale() {
+            self.timeline.rebase(clock.now(), time_scale);
+            rebased = true;
+        }
+        rebased
+    }
+
+    /// Returns the tick at which the wait strategy must wake up to reach `tick`.
+    /// It is earlier than `tick` if a deferred action is due before.
+    fn wake_tick(&self, tick: u64) -> u64 {
+        match self.deferred.and_then(|deferred| deferred.next_tick()) {
+            Some(deferred_tick) => u64::min(deferred_tick, tick),
+            None => tick,
+        }
+    }
+
+    /// Runs the deferred actions that are due (if any).
+    fn run_deferred<C: Clock>(&self, clock: &mut C) {
+        if let Some(deferred) = self.deferred {
+            if let Some(tick) = deferred.next_tick() {
+                let now = clock.now();
+                if now >= tick {
+                    deferred.run(now);
+                }
+            }
+        }
+    }
+
+    /// Prepares a new simulation step scheduled at `t_next`, and returns its deadline tick.
+    /// First, it records the time that the simulator spent computing the previous step,
+    /// and it applies the pending commands of the control handle (if any).
+    /// Then, if the step already overran its deadline, it is handled according to the pacing of the timeline.
+    fn start_step<C: Clock>(
+        &mut self,
+        clock: &mut C,
+        idle: fn(&mut C, Option<u64>),
+        t_next: f64,
+    ) -> u64 {
+        if let Some(returned_at) = self.returned_at {
+            let compute = self.timeline.base().ticks_to_us(clock.now() - returned_at);
+            if let Some(stats) = &mut self.stats {
+                stats.record_compute(compute);
+            }
+            self.step.compute_us = Some(compute);
+        }
+        self.sync(clock, idle);
+        let next_tick = self.timeline.sim_to_tick(t_next);
+        let now = clock.now();
+        if now <= next_tick {
+            return next_tick;
+        }
+        let drift = match self.timeline.pacing() {
+            Pacing::CatchUp => 0,
+            Pacing::Slip => now - next_tick,
+        };
+        self.timeline.shift(drift);
+        self.step.overrun = true;
+        if let Some(stats) = &mut self.stats {
+            stats.record_overrun(self.timeline.base().ticks_to_us(drift));
+        }
+        next_tick + drift
+    }
+
+    /// Finishes a simulation step that returns simulation time `t`. It reports the timing of the step,
+    /// and it timestamps the return of the wait strategy, so the next step can measure the time
+    /// that the simulator spent computing this one.
+    fn end_step<C: Clock>(&mut self, clock: &mut C, t: f64) {
+        if let Some(steps) = &mut self.steps {
+            steps(self.step);
+        }
+        self.step = StepTiming {
+            step: self.step.step + 1,
+            ..StepTiming::default()
+        };
+        if self.stats.is_some() || self.steps.is_some() {
+            self.returned_at = Some(clock.now());
+        }
+        if !t.is_nan() {
+            self.t_last = t;
+        }
+    }
+
+    /// Records the jitter of a simulation step that reached `next_tick` and checks its deadline.
+    /// It returns the simulation time that the wait strategy must return.
+    /// Overrun steps were late before waiting, so they are neither sampled nor checked.
+    fn on_time(&mut self, current_tick: u64, next_tick: u64, t_next: f64, input: &mut T) -> f64 {
+        if self.step.overrun {
+            return t_next;
+        }
+        let jitter = self.timeline.base().ticks_to_us(current_tick - next_tick);
+        if let Some(stats) = &mut self.stats {
+            stats.record(jitter);
+        }
+        self.step.jitter_us = Some(jitter);
+        self.deadline.check(t_next, jitter, input)
+    }
+
+    /// Computes the simulation time to return after waking up.
+    /// If we woke up before `next_tick`, it is due to an external event captured at `capture_tick`
+    /// (or now, if unknown). Events captured while the simulator was still computing the previous step
+    /// are not moved back in time beyond the last returned simulation time.
+    /// Otherwise, we check the jitter of the step.
+    fn wake_up_time(
+        &mut self,
+        current_tick: u64,
+        capture_tick: Option<u64>,
+        next_tick: u64,
+        t_next: f64,
+        input: &mut T,
+    ) -> f64 {
+        if current_tick < next_tick {
+            let tick = capture_tick.map_or(current_tick, |tick| u64::min(tick, current_tick));
+            let t = f64::min(self.timeline.tick_to_sim(tick), t_next);
+            f64::max(t, self.t_last)
+        } else {
+            self.on_time(current_tick, next_tick, t_next, input)
+        }
+    }
+}
+
+/// Closure for RT simulation.
+/// It sleeps until the alarm reaches the next simulation time.
+/// External events are only sampled once the next simulation time is reached,
+/// so they never shorten the sleep period.
+pub fn wait_sleep<'a, A: Alarm + 'a, T: Bag, R: Injection>(
+    mut clock: A,
+    timeline: Timeline,
+    options: WaitOptions<'a, T>,
+    mut input_handler: impl FnMut(&mut T) -> R + 'a,
+) -> impl FnMut(f64, &mut T) -> f64 + 'a {
+    let mut monitor = Monitor::new(&clock, timeline, options);
+
+    move |t_next, input| -> f64 {
+        // configure alarm and sleep until next tick
+        let mut next_tick = monitor.start_step(&mut clock, sleep, t_next);
+        while clock.now() < next_tick {
+            clock.arm(monitor.wake_tick(next_tick));
+            clock.wait_for_interrupt();
+            monitor.run_deferred(&mut clock);
+            if monitor.sync(&mut clock, sleep) {
+                next_tick = monitor.timeline.sim_to_tick(t_next);
+            }
+        }
+        clock.disarm(); // make sure the alarm is disabled after sleep
+
+        // sample external events at the scheduled time
+        input_handler(input);
+        let t = monitor.on_time(clock.now(), next_tick, t_next, input);
+        monitor.end_step(&mut clock, t);
+        t
+    }
+}
+
+/// Closure for RT simulation.
+/// This is based on busy loops, and interrupts are not used.
+/// While this approach reduces the jitter, it incurs a high CPU load.
+/// The input handler is checked in every iteration of the loop.
+pub fn wait_poll<'a, C: Clock + 'a, T: Bag, R: Injection>(
+    mut clock: C,
+    timeline: Timeline,
+    options: WaitOptions<'a, T>,
+    mut input_handler: impl FnMut(&mut T) -> R + 'a,
+) -> impl FnMut(f64, &mut T) -> f64 + 'a {
+    let mut monitor = Monitor::new(&clock, timeline, options);
+
+    move |t_next, input| -> f64 {
+        // wait until next tick in busy loop (or until an external event arrives)
+        let mut next_tick = monitor.start_step(&mut clock, spin, t_next);
+        let mut capture_tick = None;
+        while clock.now() < next_tick {
+            let injection = input_handler(input);
+            if injection.injected() {
+                capture_tick = injection.capture_tick();
+                break;
+            }
+            monitor.run_deferred(&mut clock);
+            if monitor.sync(&mut clock, spin) {
+                next_tick = monitor.timeline.sim_to_tick(t_next);
+            }
+        }
+
+        let t = monitor.wake_up_time(clock.now(), capture_tick, next_tick, t_next, input);
+        monitor.end_step(&mut clock, t);
+        t
+    }
+}
+
+/// Closure for RT simulation.
+/// It sleeps until the alarm reaches the next simulation time.
+/// Any other interrupt (e.g., a GPIO interrupt routed through the PLIC) also wakes up the core.
+/// In that case, the input handler is checked, and the closure returns early if it injected an event.
+pub fn wait_exti<'a, A: Alarm + 'a, T: Bag, R: Injection>(
+    mut clock: A,
+    timeline: Timeline,
+    options: WaitOptions<'a, T>,
+    mut input_handler: impl FnMut(&mut T) -> R + 'a,
+) -> impl FnMut(f64, &mut T) -> f64 + 'a {
+    let mut monitor = Monitor::new(&clock, timeline, options);
+
+    move |t_next, input| -> f64 {
+        // configure alarm and sleep until next tick
+        let mut next_tick = monitor.start_step(&mut clock, sleep, t_next);
+        let mut capture_tick = None;
+        while clock.now() < next_tick {
+            clock.arm(monitor.wake_tick(next_tick));
+            clock.wait_for_interrupt();
+            monitor.run_deferred(&mut clock);
+            if monitor.sync(&mut clock, sleep) {
+                next_tick = monitor.timeline.sim_to_tick(t_next);
+            }
+            // check for external events and break if one is found
+            let injection = input_handler(input);
+            if injection.injected() {
+                capture_tick = injection.capture_tick();
+                break;
+            }
+        }
+        clock.disarm(); // make sure the alarm is disabled after sleep
+
+        let t = monitor.wake_up_time(clock.now(), capture_tick, next_tick, t_next, input);
+        monitor.end_step(&mut clock, t);
+        t
+    }
+}
+
+/// Guard band of the [`wait_hybrid`] strategy, in microseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuardBand {
+    /// The guard band is fixed to the given value.
+    Fixed(u64),
+    /// The guard band starts with the given value and is calibrated on every step
+    /// from the observed wake-up latency of the alarm.
+    Auto(u64),
+}
+
+/// Closure for RT simulation.
+/// It sleeps until the alarm reaches `guard_band` before the next simulation time,
+/// and then busy-polls the clock for the remaining time.
+/// This approach achieves a jitter similar to [`wait_poll`] with a CPU load close to [`wait_sleep`].
+/// As in [`wait_exti`], the closure returns early if the input handler injects an event.
+///
+/// With [`GuardBand::Auto`], the guard band converges to twice the worst-case wake-up latency,
+/// and slowly decays afterwards so sporadic latency peaks are eventually forgotten.
+pub fn wait_hybrid<'a, A: Alarm + 'a, T: Bag, R: Injection>(
+    mut clock: A,
+    timeline: Timeline,
+    options: WaitOptions<'a, T>,
+    mut input_handler: impl FnMut(&mut T) -> R + 'a,
+    guard_band: GuardBand,
+) -> impl FnMut(f64, &mut T) -> f64 + 'a {
+    let mut monitor = Monitor::new(&clock, timeline, options);
+
+    let base = monitor.timeline.base();
+    let (mut guard, calibrate) = match guard_band {
+        GuardBand::Fixed(us) => (base.us_to_ticks(us, Rounding::Ceil), false),
+        GuardBand::Auto(us) => (base.us_to_ticks(us, Rounding::Ceil), true),
+    };
+
+    move |t_next, input| -> f64 {
+        let mut next_tick = monitor.start_step(&mut clock, sleep, t_next);
+        let mut wake_tick = next_tick.saturating_sub(guard);
+
+        // configure alarm and sleep until the guard band
+        let (mut slept, mut event, mut rebased) = (false, false, false);
+        let mut capture_tick = None;
+        while !event && clock.now() < wake_tick {
+            let alarm_tick = monitor.wake_tick(wake_tick);
+            clock.arm(alarm_tick);
+            clock.wait_for_interrupt();
+            // only wake-ups at the guard band are representative of the latency
+            slept = alarm_tick == wake_tick;
+            monitor.run_deferred(&mut clock);
+            if monitor.sync(&mut clock, sleep) {
+                next_tick = monitor.timeline.sim_to_tick(t_next);
+                wake_tick = next_tick.saturating_sub(guard);
+                rebased = true;
+            }
+            // check for external events and break if one is found
+            let injection = input_handler(input);
+            event = injection.injected();
+            capture_tick = injection.capture_tick();
+        }
+        clock.disarm(); // make sure the alarm is disabled after sleep
+
+        // calibrate the guard band with the wake-up latency of this step
+        // (rebased timelines may move the wake-up tick, so they are not representative)
+        if calibrate && slept && !event && !rebased {
+            let latency = clock.now() - wake_tick;
+            // the decay is rounded up, so small guard bands still converge
+            guard = u64::max(2 * latency, guard - (guard + 15) / 16);
+        }
+
+        // busy-poll the clock for the rest of the guard band
+        while !event && clock.now() < next_tick {
+            let injection = input_handler(input);
+            event = injection.injected();
+            capture_tick = injection.capture_tick();
+            monitor.run_deferred(&mut clock);
+            if monitor.sync(&mut clock, spin) {
+                next_tick = monitor.timeline.sim_to_tick(t_next);
+            }
+        }
+
+        let t = monitor.wake_up_time(clock.now(), capture_tick, next_tick, t_next, input);
+        monitor.end_step(&mut clock, t);
+        t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rt::clock::{Interrupts, MockClock};
+    use crate::rt::control::Control;
+    use crate::rt::deadline::OnMiss;
+    use crate::rt::harness::{on_interrupt, Actions, Input, Shared};
+    use crate::rt::jitter::StepLog;
+    use crate::rt::queue::{drain, EventQueue};
+    use crate::rt::time::Pacing;
+    use core::cell::RefCell;
+
+    const FREQ: u64 = 32_768;
+
+    #[test]
+    fn sleep_converts_ticks() {
+        // the simulation starts right before the lower word of the clock rolls over
+        let epoch = u32::MAX as u64 - 100;
+        let mut clock = MockClock::new(FREQ);
+        clock.set(epoch);
+        let mut stats = JitterStats::new(100);
+        let mut input = Input::default();
+        {
+            let timeline = Timeline::start(&mut clock, 10., 0.5);
+            let mut wait = wait_sleep(
+                &mut clock,
+                timeline,
+                WaitOptions::new().with_stats(&mut stats),
+                no_input,
+            );
+            assert_eq!(wait(12., &mut input), 12.);
+            // 2.1 s after the start, the fraction of a second must not be truncated
+            assert_eq!(wait(14.2, &mut input), 14.2);
+        }
+        assert_eq!(clock.now(), epoch + 68_813);
+        assert_eq!(clock.alarm(), None);
+        assert_eq!(stats.count(), 2);
+        assert_eq!(stats.max_us(), Some(0));
+    }
+
+    #[test]
+    fn sleep_records_latency() {
+        let mut clock = MockClock::new(FREQ);
+        clock.set_latency(33);
+        let mut stats = JitterStats::new(100);
+        let mut misses = 0;
+        let mut input = Input::default();
+        {
+            let deadline = Deadline::new(1_000, OnMiss::Count(&mut misses));
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            let mut wait = wait_sleep(
+                &mut clock,
+                timeline,
+                WaitOptions::new()
+                    .with_deadline(deadline)
+                    .with_stats(&mut stats),
+                no_input,
+            );
+            assert_eq!(wait(1., &mut input), 1.);
+            assert_eq!(wait(2., &mut input), 2.);
+        }
+        assert_eq!(clock.now(), 2 * FREQ + 33);
+        assert_eq!(stats.count(), 2);
+        assert_eq!(stats.min_us(), Some(1_007));
+        assert_eq!(misses, 2);
+    }
+
+    #[test]
+    fn sleep_runs_deferred_actions() {
+        let mut clock = MockClock::new(FREQ);
+        let actions = Actions::new(&[FREQ / 4, FREQ / 2, 3 * FREQ / 2]);
+        let mut stats = JitterStats::new(100);
+        let mut input = Input::default();
+        {
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            let mut wait = wait_sleep(
+                &mut clock,
+                timeline,
+                WaitOptions::new()
+                    .with_deferred(&actions)
+                    .with_stats(&mut stats),
+                no_input,
+            );
+            assert_eq!(wait(1., &mut input), 1.);
+        }
+        // actions run on time while waiting, and later actions are left for the next steps
+        assert_eq!(*actions.done.borrow(), [FREQ / 4, FREQ / 2]);
+        assert_eq!(*actions.pending.borrow(), [3 * FREQ / 2]);
+        assert_eq!(clock.now(), FREQ);
+        assert_eq!(clock.alarm(), None);
+        assert_eq!(stats.max_us(), Some(0));
+    }
+
+    #[test]
+    fn sleep_samples_input_on_time() {
+        let interrupts = Interrupts::new(&[100, 200]);
+        let mut clock = MockClock::new(FREQ);
+        clock.set_interrupts(&interrupts);
+        let mut input = Input::default();
+        {
+            let handler = on_interrupt(&interrupts);
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            let mut wait = wait_sleep(&mut clock, timeline, WaitOptions::new(), handler);
+            assert_eq!(wait(1., &mut input), 1.);
+        }
+        assert_eq!(clock.now(), FREQ);
+        assert_eq!(input.0, [2]);
+    }
+
+    #[test]
+    fn exti_wakes_up_early() {
+        let interrupts = Interrupts::new(&[32_768, 65_536]);
+        let mut clock = MockClock::new(FREQ);
+        clock.set_interrupts(&interrupts);
+        let mut stats = JitterStats::new(100);
+        let mut log = StepLog::<4>::new();
+        let mut input = Input::default();
+        {
+            let handler = on_interrupt(&interrupts);
+            let timeline = Timeline::start(&mut clock, 10., 0.5);
+            let mut steps = |step| log.record(step);
+            let options = WaitOptions::new()
+                .with_stats(&mut stats)
+                .with_steps(&mut steps);
+            let mut wait = wait_exti(&mut clock, timeline, options, handler);
+            // the event arrives 1 s after the start, i.e., at simulation time 12
+            assert_eq!(wait(15., &mut input), 12.);
+            assert_eq!(input.0, [1]);
+            input.clear();
+            // an event right on time does not shorten the step
+            assert_eq!(wait(14., &mut input), 14.);
+            assert_eq!(input.0, [1]);
+            input.clear();
+            assert_eq!(wait(15., &mut input), 15.);
+            assert!(input.is_empty());
+        }
+        assert_eq!(clock.now(), 5 * FREQ / 2);
+        // early wake-ups are not steps, so their jitter is not recorded
+        assert_eq!(stats.count(), 2);
+        let jitter: Vec<_> = log.iter().map(|step| step.jitter_us).collect();
+        assert_eq!(jitter, [None, Some(0), Some(0)]);
+    }
+
+    #[test]
+    fn exti_ignores_spurious_interrupts() {
+        let interrupts = Interrupts::new(&[100, 200]);
+        let mut clock = MockClock::new(FREQ);
+        clock.set_interrupts(&interrupts);
+        let mut input = Input::default();
+        {
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            let mut wait = wait_exti(&mut clock, timeline, WaitOptions::new(), no_input);
+            assert_eq!(wait(1., &mut input), 1.);
+        }
+        assert_eq!(clock.now(), FREQ);
+        assert_eq!(interrupts.take(), 2);
+    }
+
+    #[test]
+    fn exti_returns_capture_time() {
+        let queue = EventQueue::<u64, 4>::new();
+        // the interrupt handler timestamps the event as soon as it fires
+        let isr = |_| queue.push(FREQ, 1).unwrap();
+        let interrupts = Interrupts::with_handler(&[FREQ], &isr);
+        let mut clock = MockClock::new(FREQ);
+        clock.set_interrupts(&interrupts);
+        let mut input = Input::default();
+        {
+            let handler = drain(&queue, |input: &mut Input, event| {
+                input.0.push(event.payload);
+                true
+            });
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            // the core needs some time to wake up and run the input handler
+            clock.set_step(100);
+            let mut wait = wait_exti(&mut clock, timeline, WaitOptions::new(), handler);
+            assert_eq!(wait(2., &mut input), 1.);
+            assert_eq!(input.0, [1]);
+        }
+        assert!(clock.now() > FREQ);
+    }
+
+    #[test]
+    fn exti_clamps_capture_time() {
+        let queue = EventQueue::<u64, 4>::new();
+        let interrupts = Interrupts::new(&[3 * FREQ / 2]);
+        let mut clock = MockClock::new(FREQ);
+        clock.set_interrupts(&interrupts);
+        let mut input = Input::default();
+        let handler = drain(&queue, |input: &mut Input, event| {
+            input.0.push(event.payload);
+            true
+        });
+        let timeline = Timeline::start(&mut clock, 0., 1.);
+        let mut wait = wait_exti(&mut clock, timeline, WaitOptions::new(), handler);
+        assert_eq!(wait(1., &mut input), 1.);
+        // an event captured before the last step is not injected back in time
+        queue.push(FREQ / 2, 1).unwrap();
+        assert_eq!(wait(2., &mut input), 1.);
+        assert_eq!(input.0, [1]);
+    }
+
+    #[test]
+    fn injection() {
+        assert!(true.injected());
+        assert_eq!(true.capture_tick(), None);
+        assert!(!false.injected());
+        assert!(Some(0).injected());
+        assert_eq!(Some(42).capture_tick(), Some(42));
+        assert!(!None.injected());
+    }
+
+    #[test]
+    fn exti_aborts() {
+        let mut clock = MockClock::new(FREQ);
+        clock.set_latency(1);
+        let mut input = Input(vec![1]);
+        let deadline = Deadline::new(0, OnMiss::Abort);
+        let timeline = Timeline::start(&mut clock, 0., 1.);
+        let mut wait = wait_exti(
+            &mut clock,
+            timeline,
+            WaitOptions::new().with_deadline(deadline),
+            no_input,
+        );
+        assert!(wait(1., &mut input).is_nan());
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn poll_wakes_up_early() {
+        let interrupts = Interrupts::new(&[1_000]);
+        let mut clock = MockClock::new(FREQ);
+        clock.set_interrupts(&interrupts);
+        let mut stats = JitterStats::new(100);
+        let mut input = Input::default();
+        {
+            let handler = on_interrupt(&interrupts);
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            clock.set_step(1);
+            let mut wait = wait_poll(
+                &mut clock,
+                timeline,
+                WaitOptions::new().with_stats(&mut stats),
+                handler,
+            );
+            // the clock advances one tick every time it is read, so the event is seen one tick late
+            assert_eq!(wait(1., &mut input), 1_001. / FREQ as f64);
+            assert_eq!(input.0, [1]);
+            input.clear();
+            assert_eq!(wait(1., &mut input), 1.);
+            assert!(input.is_empty());
+        }
+        assert_eq!(stats.count(), 1);
+        assert_eq!(stats.max_us(), Some(30));
+    }
+
+    #[test]
+    fn hybrid_fixed_guard_band() {
+        let mut clock = MockClock::new(FREQ);
+        clock.set_step(1);
+        clock.set_latency(20);
+        let mut stats = JitterStats::new(100);
+        let mut input = Input::default();
+        {
+            let guard = GuardBand::Fixed(1_000); // 33 ticks
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            let mut wait = wait_hybrid(
+                &mut clock,
+                timeline,
+                WaitOptions::new().with_stats(&mut stats),
+                no_input,
+                guard,
+            );
+            assert_eq!(wait(1., &mut input), 1.);
+            assert_eq!(wait(2., &mut input), 2.);
+        }
+        // the wake-up latency is absorbed by the guard band
+        assert_eq!(stats.count(), 2);
+        assert_eq!(stats.max_us(), Some(30));
+    }
+
+    #[test]
+    fn hybrid_runs_deferred_actions() {
+        let mut clock = MockClock::new(FREQ);
+        clock.set_step(1);
+        clock.set_latency(20);
+        // the second action is due within the guard band
+        let actions = Actions::new(&[FREQ / 4, FREQ - 10]);
+        let mut stats = JitterStats::new(100);
+        let mut input = Input::default();
+        {
+            let guard = GuardBand::Fixed(1_000); // 33 ticks
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            let mut wait = wait_hybrid(
+                &mut clock,
+                timeline,
+                WaitOptions::new()
+                    .with_deferred(&actions)
+                    .with_stats(&mut stats),
+                no_input,
+                guard,
+            );
+            assert_eq!(wait(1., &mut input), 1.);
+        }
+        let done = actions.done.borrow();
+        assert_eq!(done.len(), 2);
+        assert!((FREQ / 4..FREQ / 4 + 25).contains(&done[0]));
+        assert!((FREQ - 10..FREQ).contains(&done[1]));
+        // deferred actions do not add jitter
+        assert_eq!(stats.max_us(), Some(30));
+    }
+
+    #[test]
+    fn hybrid_auto_guard_band() {
+        let mut clock = MockClock::new(FREQ);
+        clock.set_step(1);
+        clock.set_latency(10);
+        let mut stats = JitterStats::new(100);
+        let mut input = Input::default();
+        {
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            let mut wait = wait_hybrid(
+                &mut clock,
+                timeline,
+                WaitOptions::new().with_stats(&mut stats),
+                no_input,
+                GuardBand::Auto(0),
+            );
+            // without guard band, the first step suffers the wake-up latency...
+            assert_eq!(wait(1., &mut input), 1.);
+            // ... but the guard band is calibrated for the next ones
+            assert_eq!(wait(2., &mut input), 2.);
+            assert_eq!(wait(3., &mut input), 3.);
+        }
+        assert_eq!(stats.count(), 3);
+        assert_eq!(stats.max_us(), Some(427));
+        assert_eq!(stats.min_us(), Some(30));
+        assert_eq!(stats.histogram()[0], 2);
+    }
+
+    #[test]
+    fn hybrid_auto_guard_band_decays() {
+        /// Shared mock clock that records the ticks of the alarm.
+        struct Armed<'a, 'b>(Shared<'a, 'b>, &'a RefCell<Vec<u64>>);
+
+        impl<'a, 'b> Clock for Armed<'a, 'b> {
+            fn now(&mut self) -> u64 {
+                self.0.now()
+            }
+
+            fn freq(&self) -> u64 {
+                self.0.freq()
+            }
+        }
+
+        impl<'a, 'b> Alarm for Armed<'a, 'b> {
+            fn arm(&mut self, tick: u64) {
+                self.1.borrow_mut().push(tick);
+                self.0.arm(tick)
+            }
+
+            fn disarm(&mut self) {
+                self.0.disarm()
+            }
+
+            fn wait_for_interrupt(&mut self) {
+                self.0.wait_for_interrupt()
+            }
+        }
+
+        let clock = RefCell::new(MockClock::new(FREQ));
+        clock.borrow_mut().set_step(1);
+        let armed = RefCell::new(Vec::new());
+        let mut input = Input::default();
+        let timeline = Timeline::start(&mut Shared(&clock), 0., 1.);
+        let epoch = timeline.epoch();
+        let mut wait = wait_hybrid(
+            Armed(Shared(&clock), &armed),
+            timeline,
+            WaitOptions::new(),
+            no_input,
+            GuardBand::Auto(0),
+        );
+        // one large latency peak...
+        clock.borrow_mut().set_latency(1_000);
+        assert_eq!(wait(1., &mut input), 1.);
+        // ... is forgotten once the latency is small again
+        clock.borrow_mut().set_latency(3);
+        for t in 2..200 {
+            assert_eq!(wait(t as f64, &mut input), t as f64);
+        }
+        // the clock advances one tick every time it is read,
+        // so the measured latency is 5 ticks and the guard band converges to 10 ticks
+        let guard = epoch + 199 * FREQ - armed.borrow().last().unwrap();
+        assert_eq!(guard, 2 * 5);
+    }
+
+    #[test]
+    fn hybrid_wakes_up_early() {
+        let interrupts = Interrupts::new(&[16_384]);
+        let mut clock = MockClock::new(FREQ);
+        clock.set_interrupts(&interrupts);
+        let mut input = Input::default();
+        let handler = on_interrupt(&interrupts);
+        let guard = GuardBand::Fixed(1_000);
+        let timeline = Timeline::start(&mut clock, 0., 1.);
+        clock.set_step(1);
+        let mut wait = wait_hybrid(&mut clock, timeline, WaitOptions::new(), handler, guard);
+        // the clock is read once more after the event
+        assert_eq!(wait(1., &mut input), 16_385. / FREQ as f64);
+        assert_eq!(input.0, [1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "timeline does not match the clock")]
+    fn timeline_mismatch() {
+        let timeline = Timeline::start(&mut MockClock::new(1_000_000), 0., 1.);
+        let _ =
+            wait_sleep::<_, Input, _>(MockClock::new(FREQ), timeline, WaitOptions::new(), no_input);
+    }
+
+    #[test]
+    fn exti_pauses() {
+        let control = Control::new();
+        let handler = |index| match index {
+            0 => control.pause(),
+            _ => control.resume(),
+        };
+        let interrupts = Interrupts::with_handler(&[16_384, 49_152], &handler);
+        let mut clock = MockClock::new(FREQ);
+        clock.set_interrupts(&interrupts);
+        let mut stats = JitterStats::new(100);
+        let mut input = Input::default();
+        {
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            let mut wait = wait_exti(
+                &mut clock,
+                timeline,
+                WaitOptions::new()
+                    .with_control(&control)
+                    .with_stats(&mut stats),
+                no_input,
+            );
+            // the simulation is paused for one second
+            assert_eq!(wait(1., &mut input), 1.);
+            assert_eq!(wait(2., &mut input), 2.);
+        }
+        assert_eq!(clock.now(), 3 * FREQ);
+        assert_eq!(stats.max_us(), Some(0));
+    }
+
+    #[test]
+    fn exti_runs_deferred_actions_while_paused() {
+        let control = Control::new();
+        let handler = |index| match index {
+            0 => control.pause(),
+            _ => control.resume(),
+        };
+        let interrupts = Interrupts::with_handler(&[16_384, 49_152], &handler);
+        let mut clock = MockClock::new(FREQ);
+        clock.set_interrupts(&interrupts);
+        // both actions are due while the simulation is paused
+        let actions = Actions::new(&[FREQ, 5 * FREQ / 4]);
+        let mut input = Input::default();
+        {
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            let options = WaitOptions::new()
+                .with_control(&control)
+                .with_deferred(&actions);
+            let mut wait = wait_exti(&mut clock, timeline, options, no_input);
+            assert_eq!(wait(1., &mut input), 1.);
+        }
+        // they follow the wall clock instead of waiting for the simulation to resume
+        assert_eq!(*actions.done.borrow(), [FREQ, 5 * FREQ / 4]);
+        assert_eq!(clock.now(), 2 * FREQ);
+        assert_eq!(clock.alarm(), None);
+    }
+
+    #[test]
+    fn poll_pauses_before_waiting() {
+        let control = Control::new();
+        let handler = |_| control.resume();
+        let interrupts = Interrupts::with_handler(&[1_000], &handler);
+        let mut clock = MockClock::new(FREQ);
+        clock.set_interrupts(&interrupts);
+        let mut stats = JitterStats::new(100);
+        let mut input = Input::default();
+        {
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            clock.set_step(1);
+            let mut wait = wait_poll(
+                &mut clock,
+                timeline,
+                WaitOptions::new()
+                    .with_control(&control)
+                    .with_stats(&mut stats),
+                no_input,
+            );
+            // the model paused the simulation during its last transition
+            control.pause();
+            assert_eq!(wait(1., &mut input), 1.);
+        }
+        assert!(clock.now() > FREQ + 1_000);
+        assert_eq!(stats.max_us(), Some(30));
+    }
+
+    #[test]
+    fn exti_changes_time_scale() {
+        let control = Control::new();
+        let handler = |_| control.set_time_scale(2.);
+        let interrupts = Interrupts::with_handler(&[16_384], &handler);
+        let mut clock = MockClock::new(FREQ);
+        clock.set_interrupts(&interrupts);
+        let mut input = Input::default();
+        {
+            let timeline = Timeline::start(&mut clock, 0., 1.);
+            let options = WaitOptions::new().with_control(&control);
+            let mut wait = wait_exti(&mut clock, timeline, options, no_input);
+            // from simulation time 0.5 on, every time unit lasts 2 seconds
+            assert_eq!(wait(1., &mut input), 1.);
+            assert_eq!(wait(2., &mut input), 2.);
+        }
+        assert_eq!(clock.now(), FREQ / 2 + 3 * FREQ);
+    }
+
+    #[test]
+    fn sleep_catches_up() {
+        let clock = RefCell::new(MockClock::new(FREQ));
+        let mut stats = JitterStats::new(100);
+        let mut log = StepLog::<4>::new();
+        let mut input = Input::default();
+        {
+            let timeline = Timeline::start(&mut Shared(&clock), 0., 1.);
+            let mut steps = |step| log.record(step);
+            // the late step is an overrun, not a deadline miss
+            let options = WaitOptions::new()
+                .with_deadline(Deadline::new(100, OnMiss::Panic))
+                .with_stats(&mut stats)
+                .with_steps(&mut steps);
+            let mut wait = wait_sleep(Shared(&clock), timeline, options, no_input);
+            assert_eq!(wait(1., &mut input), 1.);
+            // the transition takes 1.5 seconds, so the next step is late...
+            clock.borrow_mut().advance(3 * FREQ / 2);
+            assert_eq!(wait(2., &mut input), 2.);
+            assert_eq!(clock.borrow_mut().now(), 5 * FREQ / 2);
+            // ... but the one after it is aligned with the wall clock again
+            assert_eq!(wait(3., &mut input), 3.);
+            assert_eq!(clock.borrow_mut().now(), 3 * FREQ);
+        }
+        assert_eq!(stats.overruns(), 1);
+        assert_eq!(stats.drift_us(), 0);
+        // the overrun is not mistaken for wake-up latency either
+        assert_eq!(stats.count(), 2);
+        assert_eq!(stats.max_us(), Some(0));
+        // the slow transition is not mistaken for wake-up latency
+        assert_eq!(stats.compute().count(), 2);
+        assert_eq!(stats.compute().min_us(), Some(0));
+        assert_eq!(stats.compute().max_us(), Some(1_500_000));
+        // the late step comes with the slow transition that delayed it
+        let step = |step, jitter_us, compute_us| StepTiming {
+            step,
+            jitter_us,
+            compute_us,
+            ..StepTiming::default()
+        };
+        let steps: Vec<_> = log.iter().copied().collect();
+        assert_eq!(
+            steps,
+            [
+                step(0, Some(0), None),
+                StepTiming {
+                    overrun: true,
+                    ..step(1, None, Some(1_500_000))
+                },
+                step(2, Some(0), Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn sleep_slips() {
+        let clock = RefCell::new(MockClock::new(FREQ));
+        let mut stats = JitterStats::new(100);
+        let mut input = Input::default();
+        {
+            let timeline = Timeline::start(&mut Shared(&clock), 0., 1.).with_pacing(Pacing::Slip);
+            let options = WaitOptions::new().with_stats(&mut stats);
+            let mut wait = wait_sleep(Shared(&clock), timeline, options, no_input);
+            assert_eq!(wait(1., &mut input), 1.);
+            // the transition takes 1.5 seconds, so the timeline slips 0.5 seconds
+            clock.borrow_mut().advance(3 * FREQ / 2);
+            assert_eq!(wait(2., &mut input), 2.);
+            assert_eq!(wait(3., &mut input), 3.);
+            assert_eq!(clock.borrow_mut().now(), 7 * FREQ / 2);
+        }
+        assert_eq!(stats.overruns(), 1);
+        assert_eq!(stats.drift_us(), 500_000);
+        assert_eq!(stats.max_us(), Some(0));
+    }
+}
@@ -0,0 +1,77 @@
+//! Host-side encoder and decoder of the framed serial protocol of `riscv-xdevs`
+//! (see `riscv_xdevs::rt::frame`).
+//!
+//! [`Frames`] reads the output events streamed by the board from any byte stream
+//! (e.g., a serial port or the standard output of QEMU), and [`write_frame`] sends input events.
+
+pub use riscv_xdevs::rt::frame::{crc16, Decoder, Frame, FrameError, MAX_ENCODED, MAX_VALUE};
+
+use std::io::{self, BufReader, Read, Write};
+
+/// Iterator over the frames of a byte stream.
+///
+/// Invalid frames are returned as errors, and the decoder resynchronizes on the next delimiter.
+pub struct Frames<R> {
+    /// Bytes of the stream.
+    bytes: io::Bytes<BufReader<R>>,
+    /// Decoder of the frames.
+    decoder: Decoder,
+}
+
+impl<R: Read> Frames<R> {
+    /// Creates a new iterator over the frames read from `reader`.
+    pub fn new(reader: R) -> Self {
+        Self {
+            bytes: BufReader::new(reader).bytes(),
+            decoder: Decoder::new(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for Frames<R> {
+    type Item = io::Result<Result<Frame, FrameError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for byte in &mut self.bytes {
+            match byte {
+                Ok(byte) => {
+                    if let Some(frame) = self.decoder.push(byte) {
+                        return Some(Ok(frame));
+                    }
+                }
+                Err(error) => return Some(Err(error)),
+            }
+        }
+        None
+    }
+}
+
+/// Encodes a frame and writes it to `writer`.
+/// It fails with [`io::ErrorKind::InvalidInput`] if `value` is longer than [`MAX_VALUE`].
+pub fn write_frame<W: Write>(writer: &mut W, port: u8, tick: u64, value: &[u8]) -> io::Result<()> {
+    let frame = Frame::new(port, tick, value)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "value too long"))?;
+    let mut buf = [0; MAX_ENCODED];
+    let len = frame.encode(&mut buf);
+    writer.write_all(&buf[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut stream = b"log line\n".to_vec();
+        write_frame(&mut stream, 1, 100, &42u64.to_le_bytes()).unwrap();
+        write_frame(&mut stream, 2, 200, &[1]).unwrap();
+        assert!(write_frame(&mut stream, 3, 300, &[0; MAX_VALUE + 1]).is_err());
+
+        let frames: Vec<_> = Frames::new(&stream[..]).map(Result::unwrap).collect();
+        assert_eq!(frames.len(), 3);
+        // text between frames is not a frame
+        assert!(frames[0].is_err());
+        assert_eq!(frames[1], Frame::new(1, 100, &42u64.to_le_bytes()));
+        assert_eq!(frames[2], Frame::new(2, 200, &[1]));
+    }
+}
@@ -0,0 +1,60 @@
+//! Command-line encoder and decoder of the framed serial protocol of `riscv-xdevs`.
+//!
+//! ```sh
+//! # print the frames received from a serial port (or from the standard input)
+//! xdevs-frames decode /dev/ttyACM0
+//! # send the value 2a (hex) to port 1
+//! xdevs-frames encode 1 2a > /dev/ttyACM0
+//! ```
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::process;
+use xdevs_frames::{write_frame, FrameError, Frames};
+
+const USAGE: &str = "usage: xdevs-frames decode [PATH] | xdevs-frames encode PORT HEX";
+
+/// Prints every frame read from `reader` as `tick port value`, with the value in hex.
+/// Records of the deferred log share the serial line, so they are skipped silently.
+fn decode(reader: impl Read) -> io::Result<()> {
+    for frame in Frames::new(reader) {
+        match frame? {
+            Ok(frame) => {
+                let value: String = frame.value().iter().map(|b| format!("{:02x}", b)).collect();
+                println!("{}\t{}\t{}", frame.tick, frame.port, value);
+            }
+            Err(FrameError::Kind) => {}
+            Err(error) => eprintln!("invalid frame: {:?}", error),
+        }
+    }
+    Ok(())
+}
+
+/// Parses a hex string into bytes.
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args[..] {
+        ["decode"] => decode(io::stdin()),
+        ["decode", path] => File::open(path).and_then(decode),
+        ["encode", port, hex] => match (port.parse(), parse_hex(hex)) {
+            (Ok(port), Some(value)) => write_frame(&mut io::stdout(), port, 0, &value),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+        },
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
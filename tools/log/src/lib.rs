@@ -0,0 +1,508 @@
+//! Host-side decoder of the deferred log messages of `riscv-xdevs`
+//! (see `riscv_xdevs::rt::defer`).
+//!
+//! [`Table`] reads the interned format strings from the ELF file of the application,
+//! [`Messages`] splits the byte stream of the board (e.g., a serial port or the standard output
+//! of QEMU) into log records and plain text, and [`Table::format`] reconstructs their text.
+
+pub use riscv_xdevs::rt::defer::{Record, Value, SECTION};
+pub use riscv_xdevs::rt::frame::FrameError;
+pub use riscv_xdevs::rt::log::Level;
+
+use std::fmt::Write;
+use std::io::{self, BufReader, Read};
+
+/// Interned format strings of an ELF file.
+#[derive(Clone, Debug, Default)]
+pub struct Table {
+    /// Address of the section.
+    address: u64,
+    /// Contents of the section.
+    data: Vec<u8>,
+}
+
+/// Reads a little-endian unsigned integer of `N` bytes at `offset`.
+fn read<const N: usize>(elf: &[u8], offset: u64) -> Result<u64, String> {
+    let offset = offset as usize;
+    let end = offset.checked_add(N).ok_or("truncated ELF file")?;
+    let bytes = (elf.get(offset..end)).ok_or("truncated ELF file")?;
+    Ok(bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &b| value << 8 | b as u64))
+}
+
+impl Table {
+    /// Reads the format strings from the [`SECTION`] of an ELF file.
+    /// Only little-endian ELF files are supported.
+    pub fn from_elf(elf: &[u8]) -> Result<Self, String> {
+        if elf.get(..4) != Some(b"\x7fELF") || elf.get(5) != Some(&1) {
+            return Err("not a little-endian ELF file".into());
+        }
+        // offsets of the ELF header and the section headers for 32 and 64 bits
+        let (shoff, shentsize, header) = match elf[4] {
+            1 => (0x20, 0x2E, [0x0C, 0x10, 0x14, 4]),
+            2 => (0x28, 0x3A, [0x10, 0x18, 0x20, 8]),
+            _ => return Err("unknown ELF class".into()),
+        };
+        let [addr_at, offset_at, size_at, word] = header;
+        let read_word = |offset| match word {
+            4 => read::<4>(elf, offset),
+            _ => read::<8>(elf, offset),
+        };
+        let shoff = read_word(shoff)?;
+        let entsize = read::<2>(elf, shentsize)?;
+        let shnum = read::<2>(elf, shentsize + 2)?;
+        let shstrndx = read::<2>(elf, shentsize + 4)?;
+
+        let section = |index: u64| -> Result<(u64, u64, u64, u64), String> {
+            let header = (index.checked_mul(entsize))
+                .and_then(|header| header.checked_add(shoff))
+                .ok_or("truncated ELF file")?;
+            Ok((
+                read::<4>(elf, header)?,
+                read_word(header + addr_at)?,
+                read_word(header + offset_at)?,
+                read_word(header + size_at)?,
+            ))
+        };
+        let (_, _, names, _) = section(shstrndx)?;
+        for index in 0..shnum {
+            let (name, address, offset, size) = section(index)?;
+            let name = names.checked_add(name).ok_or("truncated ELF file")?;
+            let name = elf.get(name as usize..).unwrap_or_default();
+            if name.starts_with(SECTION.as_bytes()) && name.get(SECTION.len()) == Some(&0) {
+                let end = offset.checked_add(size).ok_or("truncated ELF file")?;
+                let data = elf.get(offset as usize..end as usize);
+                let data = data.ok_or("truncated ELF file")?.to_vec();
+                return Ok(Self { address, data });
+            }
+        }
+        Err(format!("no {} section in the ELF file", SECTION))
+    }
+
+    /// Returns the format string at the given address (if any).
+    pub fn format_string(&self, address: u32) -> Option<&str> {
+        let start = (address as u64).checked_sub(self.address)? as usize;
+        let data = self.data.get(start..)?;
+        let end = data.iter().position(|&b| b == 0)?;
+        std::str::from_utf8(&data[..end]).ok()
+    }
+
+    /// Returns the text of a record, tagged with its component (e.g., `[T] acceptance: 0.50`).
+    pub fn format(&self, record: &Record) -> String {
+        let args: Vec<_> = record.args().collect();
+        let text = match self.format_string(record.format()) {
+            Some(fmt) => format(fmt, &args).unwrap_or_else(|e| format!("{} ({:?})", e, args)),
+            None => format!("unknown format string {:#x} ({:?})", record.format(), args),
+        };
+        format!("[{}] {}", record.tag(), text)
+    }
+}
+
+/// Formats the arguments of a record with a format string.
+/// It supports positional arguments with fill, alignment, sign, width, precision,
+/// and the `?`, `x`, `X`, `o`, `b`, and `e` types.
+pub fn format(fmt: &str, args: &[Value]) -> Result<String, String> {
+    let mut out = String::new();
+    let mut next = 0;
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let spec: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let (index, spec) = spec.split_once(':').unwrap_or((&spec, ""));
+                let index = match index {
+                    "" => {
+                        next += 1;
+                        next - 1
+                    }
+                    index => index.parse().map_err(|_| "unsupported argument name")?,
+                };
+                let arg = args.get(index).ok_or("missing argument")?;
+                out.push_str(&Spec::parse(spec)?.format(arg));
+            }
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+/// Format specification of an argument.
+#[derive(Debug, Default)]
+struct Spec {
+    /// Fill character.
+    fill: Option<char>,
+    /// Alignment (`<`, `^`, or `>`).
+    align: Option<char>,
+    /// It is set to `true` if the sign of positive numbers is printed.
+    plus: bool,
+    /// It is set to `true` for the alternate form (e.g., `0x` prefixes).
+    alternate: bool,
+    /// It is set to `true` if numbers are padded with zeros.
+    zero: bool,
+    /// Minimum width.
+    width: usize,
+    /// Precision (if any).
+    precision: Option<usize>,
+    /// Type (e.g., `?` or `x`).
+    ty: Option<char>,
+}
+
+impl Spec {
+    /// Parses a format specification (the part after the colon).
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut result = Self::default();
+        let chars: Vec<char> = spec.chars().collect();
+        let mut i = 0;
+        let is_align = |c: Option<&char>| matches!(c, Some('<' | '^' | '>'));
+        if is_align(chars.get(1)) {
+            result.fill = Some(chars[0]);
+            result.align = Some(chars[1]);
+            i = 2;
+        } else if is_align(chars.first()) {
+            result.align = Some(chars[0]);
+            i = 1;
+        }
+        let flag = |c: char, i: &mut usize| {
+            let found = chars.get(*i) == Some(&c);
+            *i += found as usize;
+            found
+        };
+        result.plus = flag('+', &mut i);
+        result.alternate = flag('#', &mut i);
+        result.zero = flag('0', &mut i);
+        let digits = |i: &mut usize| {
+            let start = *i;
+            while chars.get(*i).map_or(false, char::is_ascii_digit) {
+                *i += 1;
+            }
+            chars[start..*i].iter().collect::<String>().parse().ok()
+        };
+        result.width = digits(&mut i).unwrap_or_default();
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            result.precision = Some(digits(&mut i).ok_or("invalid precision")?);
+        }
+        result.ty = chars.get(i).copied();
+        match (result.ty, chars.len() - i) {
+            (None, 0) | (Some('?' | 'x' | 'X' | 'o' | 'b' | 'e'), 1) => Ok(result),
+            _ => Err(format!("unsupported format spec {:?}", spec)),
+        }
+    }
+
+    /// Formats an argument.
+    fn format(&self, arg: &Value) -> String {
+        let (sign, prefix, body) = match *arg {
+            Value::Bool(value) => ("", "", value.to_string()),
+            Value::Str(value) => {
+                let body = match self.ty {
+                    Some('?') => format!("{:?}", value),
+                    _ => value.to_string(),
+                };
+                let body = match self.precision {
+                    Some(precision) => body.chars().take(precision).collect(),
+                    None => body,
+                };
+                ("", "", body)
+            }
+            Value::Unsigned(value) => {
+                let sign = if self.plus { "+" } else { "" };
+                let (prefix, body) = self.integer(value);
+                (sign, prefix, body)
+            }
+            Value::Signed(value) => {
+                let sign = match (value < 0, self.plus) {
+                    (true, _) => "-",
+                    (false, true) => "+",
+                    (false, false) => "",
+                };
+                let (prefix, body) = self.integer(value.unsigned_abs());
+                (sign, prefix, body)
+            }
+            Value::F32(value) => self.float(value as f64, value.is_sign_negative(), |v| {
+                format!("{:?}", v as f32)
+            }),
+            Value::F64(value) => {
+                self.float(value, value.is_sign_negative(), |v| format!("{:?}", v))
+            }
+        };
+        self.pad(
+            sign,
+            prefix,
+            body,
+            matches!(arg, Value::Bool(_) | Value::Str(_)),
+        )
+    }
+
+    /// Formats the magnitude of an integer. It returns its prefix and its digits.
+    fn integer(&self, value: u64) -> (&'static str, String) {
+        let (prefix, body) = match self.ty {
+            Some('x') => ("0x", format!("{:x}", value)),
+            Some('X') => ("0x", format!("{:X}", value)),
+            Some('o') => ("0o", format!("{:o}", value)),
+            Some('b') => ("0b", format!("{:b}", value)),
+            Some('e') => ("", format!("{:e}", value)),
+            _ => ("", value.to_string()),
+        };
+        (if self.alternate { prefix } else { "" }, body)
+    }
+
+    /// Formats a float. It returns its sign, an empty prefix, and its digits.
+    fn float(
+        &self,
+        value: f64,
+        negative: bool,
+        debug: impl Fn(f64) -> String,
+    ) -> (&'static str, &'static str, String) {
+        let sign = match (negative && !value.is_nan(), self.plus) {
+            (true, _) => "-",
+            (false, true) => "+",
+            (false, false) => "",
+        };
+        let value = value.abs();
+        let body = match (self.ty, self.precision) {
+            (Some('e'), Some(precision)) => format!("{:.*e}", precision, value),
+            (Some('e'), None) => format!("{:e}", value),
+            (_, Some(precision)) => format!("{:.*}", precision, value),
+            (Some('?'), None) => debug(value),
+            (_, None) => value.to_string(),
+        };
+        (sign, "", body)
+    }
+
+    /// Pads a formatted argument up to the width of the specification.
+    fn pad(&self, sign: &str, prefix: &str, body: String, text: bool) -> String {
+        let len = sign.chars().count() + prefix.len() + body.chars().count();
+        let padding = self.width.saturating_sub(len);
+        if self.zero && !text {
+            return format!("{}{}{}{}", sign, prefix, "0".repeat(padding), body);
+        }
+        let fill = self.fill.unwrap_or(' ');
+        let default = if text { '<' } else { '>' };
+        let (left, right) = match self.align.unwrap_or(default) {
+            '<' => (0, padding),
+            '^' => (padding / 2, padding - padding / 2),
+            _ => (padding, 0),
+        };
+        let mut out = String::new();
+        (0..left).for_each(|_| out.push(fill));
+        write!(out, "{}{}{}", sign, prefix, body).unwrap();
+        (0..right).for_each(|_| out.push(fill));
+        out
+    }
+}
+
+/// Output of the board: a log record or plain text (e.g., the output of `println!`).
+#[derive(Clone, Debug)]
+pub enum Message {
+    /// Deferred log record.
+    Record(Record),
+    /// Plain text between records.
+    Text(String),
+}
+
+/// Iterator over the messages of a byte stream.
+///
+/// The stream is split at the delimiters of the records. Chunks that are not valid records
+/// are returned as text, so the output of `println!` can be mixed with deferred log messages.
+/// Valid packets of other kinds (e.g., the frames of `riscv_xdevs::rt::frame`) are skipped.
+pub struct Messages<R> {
+    /// Bytes of the stream.
+    bytes: io::Bytes<BufReader<R>>,
+    /// Bytes of the current chunk.
+    chunk: Vec<u8>,
+}
+
+impl<R: Read> Messages<R> {
+    /// Creates a new iterator over the messages read from `reader`.
+    pub fn new(reader: R) -> Self {
+        Self {
+            bytes: BufReader::new(reader).bytes(),
+            chunk: Vec::new(),
+        }
+    }
+
+    /// Returns the message of the current chunk (if any) and clears it.
+    fn take(&mut self) -> Option<Message> {
+        if self.chunk.is_empty() {
+            return None;
+        }
+        let chunk = std::mem::take(&mut self.chunk);
+        match Record::decode(&chunk) {
+            Ok(record) => Some(Message::Record(record)),
+            Err(FrameError::Kind) => None,
+            Err(_) => Some(Message::Text(String::from_utf8_lossy(&chunk).into_owned())),
+        }
+    }
+}
+
+impl<R: Read> Iterator for Messages<R> {
+    type Item = io::Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.bytes.next() {
+                Some(Ok(0)) => {
+                    if let Some(message) = self.take() {
+                        return Some(Ok(message));
+                    }
+                }
+                Some(Ok(byte)) => self.chunk.push(byte),
+                Some(Err(error)) => return Some(Err(error)),
+                None => return self.take().map(Ok),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a minimal 32-bit ELF file with a section header string table and [`SECTION`]
+    /// at address 1.
+    fn elf(strings: &[u8]) -> Vec<u8> {
+        let names = b"\0.shstrtab\0.xdevs_log\0";
+        let mut elf = vec![0; 52];
+        elf[..6].copy_from_slice(b"\x7fELF\x01\x01");
+        let names_at = elf.len();
+        elf.extend_from_slice(names);
+        let strings_at = elf.len();
+        elf.extend_from_slice(strings);
+        let shoff = elf.len();
+        elf[0x20..0x24].copy_from_slice(&(shoff as u32).to_le_bytes());
+        elf[0x2E..0x30].copy_from_slice(&40u16.to_le_bytes());
+        elf[0x30..0x32].copy_from_slice(&3u16.to_le_bytes());
+        elf[0x32..0x34].copy_from_slice(&1u16.to_le_bytes());
+        let sections = [
+            (0, 0, 0, 0),
+            (1, 0, names_at, names.len()),
+            (11, 1, strings_at, strings.len()),
+        ];
+        for (name, address, offset, size) in sections {
+            let mut header = [0; 40];
+            header[..4].copy_from_slice(&(name as u32).to_le_bytes());
+            header[0x0C..0x10].copy_from_slice(&(address as u32).to_le_bytes());
+            header[0x10..0x14].copy_from_slice(&(offset as u32).to_le_bytes());
+            header[0x14..0x18].copy_from_slice(&(size as u32).to_le_bytes());
+            elf.extend_from_slice(&header);
+        }
+        elf
+    }
+
+    /// Builds a minimal 64-bit ELF file whose section header string table and [`SECTION`]
+    /// are at the given offsets, so they can point out of the file.
+    fn elf64(names_at: u64, offset: u64, size: u64) -> Vec<u8> {
+        let names = b"\0.shstrtab\0.xdevs_log\0";
+        let mut elf = vec![0; 64];
+        elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        elf.extend_from_slice(names);
+        let shoff = elf.len();
+        elf[0x28..0x30].copy_from_slice(&(shoff as u64).to_le_bytes());
+        elf[0x3A..0x3C].copy_from_slice(&64u16.to_le_bytes());
+        elf[0x3C..0x3E].copy_from_slice(&2u16.to_le_bytes());
+        let sections = [(1, names_at, names.len() as u64), (11, offset, size)];
+        for (name, offset, size) in sections {
+            let mut header = [0; 64];
+            header[..4].copy_from_slice(&(name as u32).to_le_bytes());
+            header[0x18..0x20].copy_from_slice(&offset.to_le_bytes());
+            header[0x20..0x28].copy_from_slice(&size.to_le_bytes());
+            elf.extend_from_slice(&header);
+        }
+        elf
+    }
+
+    #[test]
+    fn formats() {
+        let args = [
+            Value::F64(0.5),
+            Value::Unsigned(42),
+            Value::Signed(-7),
+            Value::Str("idle"),
+            Value::Bool(true),
+            Value::F32(1.5),
+        ];
+        let format = |fmt| format(fmt, &args).unwrap();
+        assert_eq!(format("{:.2} {} {} ({}) {}"), "0.50 42 -7 (idle) true");
+        assert_eq!(
+            format("{{{1}}} {1:x} {1:#06x} {2:05} {2:+}"),
+            "{42} 2a 0x002a -0007 -7"
+        );
+        assert_eq!(
+            format("[{3:>6}] [{3:*^8}] [{3:.2}] {3:?}"),
+            "[  idle] [**idle**] [id] \"idle\""
+        );
+        assert_eq!(
+            format("{0} {0:?} {0:+.1} {0:e} {5} {5:?}"),
+            "0.5 0.5 +0.5 5e-1 1.5 1.5"
+        );
+        assert!(super::format("{6}", &args).is_err());
+        assert!(super::format("{}", &[]).is_err());
+        assert!(super::format("{name}", &args).is_err());
+        assert!(super::format("{:w}", &args).is_err());
+    }
+
+    #[test]
+    fn records() {
+        let table = Table::from_elf(&elf(b"job {} ({})\0acceptance: {:.2}\0")).unwrap();
+        assert_eq!(table.format_string(1), Some("job {} ({})"));
+        assert_eq!(table.format_string(13), Some("acceptance: {:.2}"));
+        assert_eq!(table.format_string(0), None);
+
+        let mut record = Record::new(13, Level::Info, "T");
+        record.arg(&0.125f64);
+        assert_eq!(table.format(&record), "[T] acceptance: 0.12");
+        let mut record = Record::new(1, Level::Debug, "P");
+        record.arg(&3usize);
+        record.arg("busy");
+        assert_eq!(table.format(&record), "[P] job 3 (busy)");
+        assert!(table
+            .format(&Record::new(100, Level::Debug, "P"))
+            .contains("unknown"));
+        assert!(Table::from_elf(b"\x7fELF\x01\x01").is_err());
+    }
+
+    #[test]
+    fn malformed_elf() {
+        assert!(Table::from_elf(&elf64(64, 64, 4)).is_ok());
+        // offsets that overflow are reported as a truncated file instead of panicking
+        let error = |elf: &[u8]| Table::from_elf(elf).err();
+        let truncated = Some("truncated ELF file".to_string());
+        assert_eq!(error(&elf64(u64::MAX, 64, 4)), truncated);
+        assert_eq!(error(&elf64(64, u64::MAX, 4)), truncated);
+        let mut elf = elf64(64, 64, 4);
+        elf[0x28..0x30].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(error(&elf), truncated);
+    }
+
+    #[test]
+    fn messages() {
+        let mut record = Record::new(1, Level::Info, "G");
+        record.arg(&true);
+        let mut buf = [0; riscv_xdevs::rt::defer::MAX_ENCODED];
+        let len = record.encode(&mut buf);
+        let mut stream = b"Building model\n".to_vec();
+        stream.extend_from_slice(&buf[..len]);
+        // frames sent through the same UART are not text
+        let frame = riscv_xdevs::rt::frame::Frame::new(1, 100, &[42]).unwrap();
+        let mut buf = [0; riscv_xdevs::rt::frame::MAX_ENCODED];
+        let len = frame.encode(&mut buf);
+        stream.extend_from_slice(&buf[..len]);
+        stream.extend_from_slice(b"Simulation finished\n");
+
+        let messages: Vec<_> = Messages::new(&stream[..]).map(Result::unwrap).collect();
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(&messages[0], Message::Text(text) if text == "Building model\n"));
+        assert!(matches!(&messages[1], Message::Record(r) if r.format() == 1 && r.tag() == "G"));
+        assert!(matches!(&messages[2], Message::Text(text) if text == "Simulation finished\n"));
+    }
+}
@@ -0,0 +1,53 @@
+//! Command-line decoder of the deferred log messages of `riscv-xdevs`.
+//!
+//! ```sh
+//! # print the log messages of an application read from a serial port (or from the standard input)
+//! xdevs-log target/riscv32imc-unknown-none-elf/release/examples/serial /dev/ttyACM0
+//! ```
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::process;
+use xdevs_log::{Message, Messages, Table};
+
+const USAGE: &str = "usage: xdevs-log ELF [PATH]";
+
+/// Prints every message read from `reader`, formatting log records with the strings of `table`.
+fn decode(table: &Table, reader: impl Read) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    for message in Messages::new(reader) {
+        match message? {
+            Message::Record(record) => writeln!(stdout, "{}", table.format(&record))?,
+            Message::Text(text) => write!(stdout, "{}", text)?,
+        }
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let (elf, path) = match args[..] {
+        [elf] => (elf, None),
+        [elf, path] => (elf, Some(path)),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        }
+    };
+    let table = fs::read(elf)
+        .map_err(|e| e.to_string())
+        .and_then(|elf| Table::from_elf(&elf));
+    let result = match (table, path) {
+        (Ok(table), None) => decode(&table, io::stdin()).map_err(|e| e.to_string()),
+        (Ok(table), Some(path)) => File::open(path)
+            .and_then(|file| decode(&table, file))
+            .map_err(|e| e.to_string()),
+        (Err(error), _) => Err(error),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,10 @@
+/* Format strings of the deferred log messages (see src/rt/defer.rs).
+   The section is kept in the ELF file for the host decoder, but it is not loaded into the board.
+   It starts at address 1, so no format string is at the null address. */
+SECTIONS
+{
+  .xdevs_log 1 (INFO) :
+  {
+    KEEP(*(.xdevs_log .xdevs_log.*));
+  }
+}